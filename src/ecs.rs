@@ -1,22 +1,51 @@
 use crate::ecs::world::World;
+use crate::error::Result;
 
 pub mod components;
 pub mod entity;
+pub mod error;
+pub mod event;
 pub mod game;
+pub mod loader;
+pub mod pacing;
+pub mod query;
+pub mod resource;
+pub mod snapshot;
+pub mod stats;
+pub mod system;
+pub mod time;
 pub mod world;
 
-type System = dyn FnMut(&World);
+type System = dyn FnMut(&World) -> Result<()>;
 
 #[cfg(test)]
 mod tests {
-    use crate::ecs::entity::Entity;
-    use crate::ecs::game::Game;
+    use crate::ecs::components::{Transform2, Transform3};
+    use crate::ecs::entity::{Entity, EntityId, EntityMap, MapEntities};
+    use crate::ecs::error::{Access, BorrowError};
+    use crate::ecs::event::{EventReader, Events};
+    use crate::ecs::game::{AppExit, Game, Stage};
+    use crate::ecs::loader::WorldLoader;
+    use crate::ecs::pacing::FrameRateCap;
+    use crate::ecs::query::QueryState;
+    use crate::ecs::snapshot::{write_u32, Snapshot, SnapshotError, SnapshotReader};
+    use crate::ecs::stats::{FrameStats, Timings};
+    use crate::ecs::system::ErrorPolicy;
+    use crate::ecs::time::{Clock, ManualClock, Time};
     use crate::ecs::world::World;
+    use crate::error::{Error, Result};
+    use crate::runner::HeadlessRunner;
+    use nalgebra::{
+        Isometry3, Matrix3, Matrix4, Point2, Point3, Rotation2, Similarity2, Similarity3,
+        UnitQuaternion, Vector2, Vector3,
+    };
     use std::time::Duration;
 
+    #[derive(Debug)]
     struct Foo {
         x: i32,
     }
+    #[derive(Debug)]
     struct Bar {
         x: i32,
     }
@@ -43,30 +72,568 @@ mod tests {
         world.add_entity(entity);
         let mut game = Game::new(world);
         game.add_system(some_system);
-        let mut total_time = Duration::new(0, 0);
-        let mut frames = 0;
-        let mut lowest = Duration::new(0, 0);
-        let mut highest = Duration::new(u64::MAX, 0);
-        loop {
-            game.update();
-            total_time += game.delta_time;
-            frames += 1;
-            if game.delta_time > lowest {
-                lowest = game.delta_time;
+        game.step_frames(300, Duration::from_secs_f64(1.0 / 60.0))
+            .unwrap();
+        let entity = game.world.query::<(Foo,)>().next().unwrap();
+        assert_eq!(entity.get_component::<Foo>().unwrap().x, 300);
+        assert_eq!(entity.get_component::<Bar>().unwrap().x, 300);
+        drop(entity);
+        let time = *game.world.resource::<Time>().unwrap();
+        assert_eq!(time.frame, 300);
+        assert_eq!(time.elapsed, Duration::from_secs_f64(1.0 / 60.0) * 300);
+        let stats = game.world.resource::<FrameStats>().unwrap();
+        assert!(stats.system(type_name_of(some_system)).is_some());
+        assert_eq!(stats.fps().round(), 60.0);
+        drop(stats);
+
+        // A world handed to a new game keeps its time
+        let game = Game::new(game.world);
+        assert_eq!(game.world.resource::<Time>().unwrap().frame, 300);
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::default();
+        let mut game = Game::new(World::default());
+        game.set_clock(clock.clone());
+        game.add_system(|world: &World| {
+            let time = *world.resource::<Time>().unwrap();
+            if time.elapsed >= Duration::from_millis(50) {
+                world.send_event(AppExit);
+            }
+        });
+        clock.advance(Duration::from_millis(20));
+        game.update().unwrap();
+        assert_eq!(game.delta_time, Duration::from_millis(20));
+        game.update().unwrap();
+        assert_eq!(game.delta_time, Duration::ZERO);
+
+        let frames = game
+            .step_until(Duration::from_millis(10), 10, |world| {
+                !world.resource::<Events<AppExit>>().unwrap().is_empty()
+            })
+            .unwrap();
+        assert_eq!(frames, Some(3));
+    }
+
+    fn type_name_of<T>(_: T) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    #[test]
+    fn test_frame_stats() {
+        let mut timings = Timings::new(4);
+        for ms in [5, 1, 3, 2, 4] {
+            timings.record(Duration::from_millis(ms));
+        }
+        assert_eq!(timings.len(), 4);
+        assert_eq!(timings.min(), Duration::from_millis(1));
+        assert_eq!(timings.max(), Duration::from_millis(4));
+        assert_eq!(timings.average(), Duration::from_micros(2500));
+        assert_eq!(timings.percentile(50.0), Duration::from_millis(2));
+        assert_eq!(timings.percentile(100.0), Duration::from_millis(4));
+
+        let mut game = Game::new(World::default());
+        game.add_system_to_stage(Stage::PostUpdate, some_system);
+        // Closures from the same function are timed separately
+        let counter = || |_: &World| Ok(());
+        game.add_system(counter());
+        game.add_system(counter());
+        game.add_system(some_system).with_name("renamed");
+        game.add_system(some_system).with_name("renamed");
+        game.add_system(some_system).with_name("\"quoted\"\n");
+        game.world
+            .resource_mut::<FrameStats>()
+            .unwrap()
+            .start_trace(2);
+        for _ in 0..3 {
+            game.update().unwrap();
+        }
+        let stats = game.world.resource::<FrameStats>().unwrap();
+        assert_eq!(stats.frame, 3);
+        assert_eq!(stats.stage(Stage::PostUpdate).unwrap().len(), 3);
+        let mut names: Vec<_> = stats.systems().map(|(name, _)| name).collect();
+        names.sort();
+        assert_eq!(names.len(), 6);
+        assert!(names.iter().any(|name| name.ends_with("{{closure}}#2")));
+        assert_eq!(&names[4..], ["renamed", "renamed#2"]);
+        assert!(stats.systems().all(|(_, timings)| timings.len() == 3));
+        let trace = stats.chrome_trace();
+        assert!(trace.starts_with("{\"traceEvents\":["));
+        assert!(!trace.contains("frame 1\""));
+        assert!(trace.contains("frame 3\""));
+        assert!(trace.contains("some_system"));
+        assert!(trace.contains(r#""name":"\"quoted\"\u000a""#));
+        assert!(matches!(
+            game.world.try_resource_mut::<FrameStats>(),
+            Some(Err(BorrowError::ResourceConflict { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_query_state() {
+        let mut world = World::default();
+        let mut entity = Entity::default();
+        entity.add_component(Foo { x: 0 });
+        world.add_entity(entity);
+        world.add_entity(Entity::default());
+
+        let mut query = QueryState::<(Foo, Bar)>::new();
+        for mut entity in query.iter_mut(&world) {
+            entity.get_component_mut::<Foo>().unwrap().x += 1;
+        }
+        assert_eq!(query.len(), 1);
+
+        let generation = world.generation();
+        query.iter(&world).for_each(drop);
+        assert_eq!(world.generation(), generation);
+
+        world.entities[1]
+            .write()
+            .unwrap()
+            .add_component(Bar { x: 0 });
+        assert_ne!(world.generation(), generation);
+        assert_eq!(query.iter(&world).count(), 2);
+        assert_eq!(
+            query
+                .iter(&world)
+                .filter_map(|e| e.get_component::<Foo>().map(|f| f.x))
+                .sum::<i32>(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_nested_queries() {
+        let mut world = World::default();
+        let mut entity = Entity::default();
+        entity.add_component(Foo { x: 0 });
+        let id = world.add_entity(entity);
+
+        let outer = world.query::<(Foo,)>().next().unwrap();
+        assert_eq!(world.query::<(Foo,)>().count(), 1);
+        assert!(world.try_get(id).is_ok());
+        assert!(matches!(
+            world.try_get_mut(id),
+            Err(BorrowError::Conflict {
+                access: Access::Write,
+                ..
+            })
+        ));
+        drop(outer);
+
+        let outer = world.query_mut::<(Foo,)>().next().unwrap();
+        let error = world.try_query::<(Foo,)>().next().unwrap().unwrap_err();
+        assert_eq!(
+            error,
+            BorrowError::Conflict {
+                entity: id,
+                access: Access::Read,
+                query: std::any::type_name::<(Foo,)>(),
+                system: None,
+            }
+        );
+        assert!(world.try_query_mut::<(Foo,)>().next().unwrap().is_err());
+        // Entities that can't match a query don't conflict with it
+        assert!(world.try_query::<(Bar,)>().next().is_none());
+        drop(outer);
+        assert!(world.try_get_mut(id).is_ok());
+        assert!(matches!(
+            world.try_get(EntityId(1)),
+            Err(BorrowError::Missing { .. })
+        ));
+
+        let mut entity = Entity::default();
+        entity.add_component(Bar { x: 0 });
+        world.add_entity(entity);
+        let _outer = world.query_mut::<(Foo,)>().next().unwrap();
+        assert_eq!(world.query_mut::<(Bar,)>().count(), 1);
+        let mut state = QueryState::<(Bar,)>::new();
+        assert_eq!(state.iter(&world).count(), 1);
+    }
+
+    #[test]
+    fn test_borrow_from_other_thread_waits() {
+        let mut entity = Entity::default();
+        entity.add_component(Foo { x: 0 });
+        let mut world = World::default();
+        let id = world.add_entity(entity);
+        let guard = world.get_mut(id).unwrap();
+        std::thread::scope(|s| {
+            let reader = s.spawn(|| {
+                world
+                    .query::<(Foo,)>()
+                    .next()
+                    .unwrap()
+                    .get_component::<Foo>()
+                    .unwrap()
+                    .x
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            let mut guard = guard;
+            guard.get_components_mut::<Foo>().next().unwrap().x = 1;
+            drop(guard);
+            assert_eq!(reader.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_component_added_through_borrow() {
+        let mut world = World::default();
+        let id = world.add_entity(Entity::default());
+        let mut state = QueryState::<(Foo,)>::new();
+        assert_eq!(state.iter(&world).count(), 0);
+        world.get_mut(id).unwrap().add_component(Foo { x: 0 });
+        assert_eq!(world.query::<(Foo,)>().count(), 1);
+        assert_eq!(state.iter(&world).count(), 1);
+        world.add_entity(Entity::default());
+        assert_eq!(world.query::<(Foo,)>().count(), 1);
+    }
+
+    #[test]
+    fn test_conflict_names_system() {
+        fn nested_system(world: &World) {
+            let _outer = world.query_mut::<(Foo,)>().next();
+            let _inner = world.query::<(Foo,)>().next();
+        }
+
+        let mut entity = Entity::default();
+        entity.add_component(Foo { x: 0 });
+        let mut world = World::default();
+        world.add_entity(entity);
+        let mut game = Game::new(world);
+        game.add_system(nested_system);
+        let panic =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| game.update().unwrap()))
+                .unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.contains("nested_system"), "{message}");
+        assert!(message.contains("Foo"), "{message}");
+    }
+
+    #[test]
+    fn test_poisoned_entity() {
+        let mut entity = Entity::default();
+        entity.add_component(Foo { x: 0 });
+        let mut world = World::default();
+        let id = world.add_entity(entity);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = world.get_mut(id).unwrap();
+                panic!("system failed");
+            })
+            .join()
+            .unwrap_err();
+        });
+        assert!(matches!(
+            world.try_get(id),
+            Err(BorrowError::Poisoned { .. })
+        ));
+    }
+
+    #[derive(Debug)]
+    struct Parent(EntityId);
+    impl MapEntities for Parent {
+        fn map_entities(&mut self, map: &EntityMap) {
+            self.0 = map.get(self.0).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_merge_world() {
+        let mut world = World::default();
+        world.add_entity(Entity::default());
+        world.register_map_entities::<Parent>();
+
+        let mut loader = WorldLoader::spawn(|level| {
+            let root = level.add_entity(Entity::default());
+            for _ in 0..1000 {
+                let mut child = Entity::default();
+                child.add_component(Parent(root));
+                level.add_entity(child);
             }
-            if game.delta_time < highest {
-                highest = game.delta_time;
+        });
+        let level = loader.join().unwrap();
+        assert!(loader.poll().is_none());
+
+        let generation = world.generation();
+        let map = world.merge(level);
+        assert_eq!(map.len(), 1001);
+        assert_eq!(world.entities.len(), 1002);
+        assert_ne!(world.generation(), generation);
+        let root = map.get(EntityId(0)).unwrap();
+        assert_eq!(root, EntityId(1));
+        assert!(world
+            .query::<(Parent,)>()
+            .all(|e| e.get_component::<Parent>().unwrap().0 == root));
+    }
+
+    #[test]
+    fn test_error_policies() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        fn failing(calls: &Arc<AtomicU32>) -> impl FnMut(&World) -> Result<()> {
+            let calls = calls.clone();
+            move |_: &World| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(Error::other("nope"))
             }
-            if total_time > Duration::new(5, 0) {
-                break;
+        }
+
+        let logged = Arc::new(AtomicU32::new(0));
+        let retried = Arc::new(AtomicU32::new(0));
+        let disabled = Arc::new(AtomicU32::new(0));
+        let mut game = Game::new(World::default());
+        game.add_system(failing(&logged));
+        game.add_system(failing(&retried))
+            .with_error_policy(ErrorPolicy::Retry { attempts: 2 });
+        game.add_system(failing(&disabled))
+            .with_error_policy(ErrorPolicy::Disable);
+        game.add_system(some_system);
+        for _ in 0..2 {
+            game.update().unwrap();
+        }
+        assert_eq!(logged.load(Ordering::Relaxed), 2);
+        assert_eq!(retried.load(Ordering::Relaxed), 6);
+        assert_eq!(disabled.load(Ordering::Relaxed), 1);
+
+        let aborted = Arc::new(AtomicU32::new(0));
+        game.set_error_policy(ErrorPolicy::Abort);
+        game.add_system_to_stage(Stage::PreUpdate, failing(&aborted))
+            .with_name("aborting");
+        assert!(matches!(game.update(), Err(Error::Other(_))));
+        assert_eq!(logged.load(Ordering::Relaxed), 2);
+        let stats = game.world.resource::<FrameStats>().unwrap();
+        assert!(stats.system("aborting").is_some());
+    }
+
+    #[test]
+    fn test_events() {
+        #[derive(Debug, PartialEq)]
+        struct Ping(u32);
+
+        let mut world = World::default();
+        world.add_event::<Ping>();
+        let mut reader = EventReader::default();
+        world.send_event(Ping(0));
+        world.update_events();
+        world.send_event(Ping(1));
+        {
+            let events = world.resource::<Events<Ping>>().unwrap();
+            assert_eq!(
+                reader.read(&events).collect::<Vec<_>>(),
+                [&Ping(0), &Ping(1)]
+            );
+            assert_eq!(reader.read(&events).count(), 0);
+        }
+        world.update_events();
+        world.update_events();
+        assert!(world.resource::<Events<Ping>>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_headless_runner() {
+        let frames = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let mut game = Game::new(World::default());
+        let counter = frames.clone();
+        game.add_system(move |world: &World| {
+            if counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed) == 9 {
+                world.send_event(AppExit);
             }
+        });
+        game.set_runner(HeadlessRunner::frames(100));
+        game.run().unwrap();
+        assert_eq!(frames.load(std::sync::atomic::Ordering::Relaxed), 10);
+
+        let mut game = Game::new(World::default());
+        game.set_runner(HeadlessRunner::frames(3));
+        game.run().unwrap();
+    }
+
+    #[test]
+    fn test_frame_rate_cap() {
+        let clock = ManualClock::default();
+        let mut game = Game::new(World::default());
+        game.set_clock(clock.clone());
+        game.set_frame_rate_cap(FrameRateCap {
+            focused: Some(200.0),
+            unfocused: Some(20.0),
+            ..Default::default()
+        });
+        // The first frame starts the pacing, the next ones wait 5ms each
+        for _ in 0..11 {
+            game.update().unwrap();
         }
-        println!(
-            "Average: {}, lowest: {}, highest: {}, elapsed: {:?}",
-            1_000_000_000 / (total_time.as_nanos() / frames),
-            1_000_000_000 / lowest.as_nanos(),
-            1_000_000_000 / highest.as_nanos(),
-            total_time,
+        assert_eq!(clock.now(), Duration::from_millis(50));
+
+        game.set_focused(false);
+        let system_clock = clock.clone();
+        game.add_system(move |_: &World| system_clock.advance(Duration::from_millis(30)));
+        for _ in 0..3 {
+            game.update().unwrap();
+        }
+        assert_eq!(clock.now(), Duration::from_millis(50 + 3 * 50));
+        assert_eq!(game.delta_time, Duration::from_millis(50));
+        let stats = game.world.resource::<FrameStats>().unwrap();
+        assert_eq!(stats.missed_deadlines, 0);
+        drop(stats);
+
+        game.set_frame_rate_cap(FrameRateCap::fixed(1000.0));
+        game.update().unwrap();
+        let stats = game.world.resource::<FrameStats>().unwrap();
+        assert_eq!(stats.missed_deadlines, 1);
+    }
+
+    impl Snapshot for Foo {
+        fn save(&self, out: &mut Vec<u8>) {
+            write_u32(out, self.x as u32);
+        }
+        fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+            self.x = SnapshotReader::new(data).u32()? as i32;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_component_snapshot() {
+        let mut world = World::default();
+        world.register_snapshot_component::<Foo>();
+        for x in 0..3 {
+            let mut entity = Entity::default();
+            entity.add_component(Foo { x });
+            entity.add_component(Foo { x: x + 10 });
+            world.add_entity(entity);
+        }
+        let snapshot = world.snapshot();
+        assert_eq!(snapshot.components.values().next().unwrap().len(), 6);
+        let mut game = Game::new(world);
+        game.add_system(some_system);
+        game.step(Duration::ZERO).unwrap();
+        game.world.restore(&snapshot).unwrap();
+        let xs: Vec<_> = game
+            .world
+            .query::<(Foo,)>()
+            .flat_map(|e| e.get_components::<Foo>().map(|f| f.x).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(xs, [0, 10, 1, 11, 2, 12]);
+    }
+
+    fn transform3(position: [f32; 3], axis_angle: [f32; 3], scale: f32) -> Transform3<f32> {
+        Transform3 {
+            position: Vector3::from(position),
+            rotation: UnitQuaternion::new(Vector3::from(axis_angle)).into_inner(),
+            scale: Vector3::repeat(scale),
+        }
+    }
+
+    fn similarity3(transform: &Transform3<f32>) -> Similarity3<f32> {
+        Similarity3::from_parts(
+            transform.position.into(),
+            transform.unit_rotation(),
+            transform.scale.x,
+        )
+    }
+
+    #[test]
+    fn test_transform3() {
+        let identity = Transform3::<f32>::default();
+        assert_eq!(identity, Transform3::identity());
+        assert_eq!(identity.to_matrix(), Matrix4::identity());
+
+        let parent = transform3([1.0, 2.0, 3.0], [0.3, -0.2, 0.9], 2.0);
+        let child = transform3([-4.0, 0.5, 1.0], [1.1, 0.4, 0.0], 0.5);
+        let point = Point3::new(0.7, -1.3, 2.2);
+        let vector = Vector3::new(-0.1, 3.0, 0.6);
+        let reference = similarity3(&parent);
+        assert!((parent.to_matrix() - reference.to_homogeneous()).norm() < 1e-5);
+        assert!((parent.transform_point(&point) - reference * point).norm() < 1e-5);
+        assert!((parent.transform_vector(&vector) - reference * vector).norm() < 1e-5);
+
+        let composed = parent * child;
+        let reference = similarity3(&parent) * similarity3(&child);
+        assert!((composed.to_matrix() - reference.to_homogeneous()).norm() < 1e-5);
+        let inverse = parent.inverse();
+        let reference = similarity3(&parent).inverse();
+        assert!((inverse.to_matrix() - reference.to_homogeneous()).norm() < 1e-5);
+        assert!(((parent * inverse).to_matrix() - Matrix4::identity()).norm() < 1e-5);
+
+        // Non-uniform scale, without rotation
+        let scaled = Transform3 {
+            scale: Vector3::new(1.0, 2.0, 3.0),
+            ..Transform3::from_position(Vector3::new(1.0, 1.0, 1.0))
+        };
+        assert_eq!(
+            scaled.transform_point(&Point3::new(1.0, 1.0, 1.0)),
+            Point3::new(2.0, 3.0, 4.0)
+        );
+        assert_eq!(
+            Matrix4::from(scaled),
+            Matrix4::new_translation(&Vector3::repeat(1.0))
+                * Matrix4::new_nonuniform_scaling(&scaled.scale)
         );
+
+        let mut camera = Transform3::from_position(Vector3::new(1.0, 2.0, 5.0));
+        let target = Point3::new(-2.0, 0.0, 0.0);
+        camera.look_at(&target.coords, &Vector3::y());
+        let reference = Isometry3::look_at_rh(&camera.position.into(), &target, &Vector3::y());
+        assert!((camera.to_matrix() - reference.inverse().to_homogeneous()).norm() < 1e-5);
+
+        let mut moved = Transform3::identity();
+        moved.translate(&Vector3::new(1.0, 0.0, 0.0));
+        moved.rotate(&UnitQuaternion::new(Vector3::new(0.0, 0.5, 0.0)));
+        moved.rotate(&UnitQuaternion::new(Vector3::new(0.0, 0.5, 0.0)));
+        moved.scale_by(&Vector3::repeat(3.0));
+        let reference = Similarity3::new(Vector3::new(1.0, 0.0, 0.0), Vector3::y(), 3.0);
+        assert!((moved.to_matrix() - reference.to_homogeneous()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_transform2() {
+        let identity = Transform2::<f32>::default();
+        assert_eq!(identity, Transform2::identity());
+        assert_eq!(identity.to_matrix(), Matrix3::identity());
+
+        let parent = Transform2 {
+            position: Vector2::new(3.0, -1.0),
+            rotation: 0.8,
+            scale: Vector2::repeat(1.5),
+        };
+        let child = Transform2 {
+            position: Vector2::new(-2.0, 4.0),
+            rotation: -2.1,
+            scale: Vector2::repeat(0.25),
+        };
+        let similarity = |t: &Transform2<f32>| Similarity2::new(t.position, t.rotation, t.scale.x);
+        let point = Point2::new(0.5, 2.0);
+        assert!((parent.to_matrix() - similarity(&parent).to_homogeneous()).norm() < 1e-5);
+        assert!((parent.transform_point(&point) - similarity(&parent) * point).norm() < 1e-5);
+        let reference = similarity(&parent) * similarity(&child);
+        assert!(((parent * child).to_matrix() - reference.to_homogeneous()).norm() < 1e-5);
+        let reference = similarity(&parent).inverse();
+        assert!((Matrix3::from(parent.inverse()) - reference.to_homogeneous()).norm() < 1e-5);
+
+        let mut looking = Transform2::from_position(Vector2::new(1.0, 1.0));
+        looking.look_at(&Vector2::new(1.0, 3.0));
+        let direction = looking.transform_vector(&Vector2::x());
+        assert!((direction - Vector2::y()).norm() < 1e-5);
+
+        let mut moved = Transform2::identity();
+        moved.translate(&Vector2::new(2.0, 0.0));
+        moved.rotate(0.5);
+        moved.scale_by(&Vector2::new(2.0, 4.0));
+        let reference = Matrix3::new_translation(&Vector2::new(2.0, 0.0))
+            * Rotation2::new(0.5).to_homogeneous()
+            * Matrix3::new_nonuniform_scaling(&Vector2::new(2.0, 4.0));
+        assert!((moved.to_matrix() - reference).norm() < 1e-5);
+
+        // Through 3D and back
+        let lifted = Transform3::from(moved);
+        let point = Point2::new(-1.0, 0.5);
+        let expected = moved.transform_point(&point);
+        let actual = lifted.transform_point(&Point3::new(point.x, point.y, 0.0));
+        assert!((actual.xy() - expected).norm() < 1e-5);
+        let back = Transform2::from(lifted);
+        assert!((back.to_matrix() - moved.to_matrix()).norm() < 1e-5);
     }
 }
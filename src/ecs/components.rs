@@ -1,21 +1,212 @@
 use downcast_rs::Downcast;
-use nalgebra::{Quaternion, Vector2, Vector3};
+use nalgebra::{
+    Matrix3, Matrix4, Point2, Point3, Quaternion, RealField, Rotation2, UnitQuaternion, Vector2,
+    Vector3,
+};
 use num_traits::Float;
 use std::fmt::Debug;
+use std::ops::Mul;
 
 pub trait Component: Downcast + Debug + Send + Sync {}
 impl<T> Component for T where T: Downcast + Debug + Send + Sync {}
 
-#[derive(Default, Debug, Clone)]
+/// Position, rotation and scale in 3D, applied in the reverse order: scale, then rotation, then
+/// translation.
+///
+/// Composition (`parent * child`) and [`Transform3::inverse`] are exact as long as scales are
+/// uniform; with a non-uniform scale and a rotation, the result can't be expressed as a
+/// `Transform3` and the shear is dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transform3<T: 'static + Float + Debug> {
     pub position: Vector3<T>,
     pub rotation: Quaternion<T>,
     pub scale: Vector3<T>,
 }
+impl<T: 'static + Float + Debug> Default for Transform3<T> {
+    fn default() -> Self {
+        Self {
+            position: Vector3::repeat(T::zero()),
+            rotation: Quaternion::new(T::one(), T::zero(), T::zero(), T::zero()),
+            scale: Vector3::repeat(T::one()),
+        }
+    }
+}
+impl<T: 'static + Float + Debug> Transform3<T> {
+    pub fn identity() -> Self {
+        Self::default()
+    }
+    pub fn from_position(position: Vector3<T>) -> Self {
+        Self {
+            position,
+            ..Self::default()
+        }
+    }
+}
+impl<T: Float + RealField> Transform3<T> {
+    /// The rotation, normalized.
+    pub fn unit_rotation(&self) -> UnitQuaternion<T> {
+        UnitQuaternion::new_normalize(self.rotation)
+    }
+    pub fn to_matrix(&self) -> Matrix4<T> {
+        Matrix4::new_translation(&self.position)
+            * self.unit_rotation().to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+    pub fn transform_point(&self, point: &Point3<T>) -> Point3<T> {
+        Point3::from(self.transform_vector(&point.coords) + self.position)
+    }
+    /// Scales and rotates a vector, ignoring the translation.
+    pub fn transform_vector(&self, vector: &Vector3<T>) -> Vector3<T> {
+        self.unit_rotation() * vector.component_mul(&self.scale)
+    }
+    pub fn inverse(&self) -> Self {
+        let rotation = self.unit_rotation().inverse();
+        let scale = self.scale.map(|s| T::one() / s);
+        Self {
+            position: -(rotation * self.position).component_mul(&scale),
+            rotation: rotation.into_inner(),
+            scale,
+        }
+    }
+    /// Rotates the transform so that its forward axis (-Z) points at `target`, with its Y axis
+    /// as close to `up` as possible.
+    pub fn look_at(&mut self, target: &Vector3<T>, up: &Vector3<T>) {
+        let rotation = UnitQuaternion::face_towards(&(self.position - target), up);
+        self.rotation = rotation.into_inner();
+    }
+    pub fn translate(&mut self, translation: &Vector3<T>) {
+        self.position += translation;
+    }
+    /// Rotates the transform around its position, `rotation` being applied after the current
+    /// rotation.
+    pub fn rotate(&mut self, rotation: &UnitQuaternion<T>) {
+        self.rotation = (rotation * self.unit_rotation()).into_inner();
+    }
+    pub fn scale_by(&mut self, scale: &Vector3<T>) {
+        self.scale.component_mul_assign(scale);
+    }
+    /// Drops the Z axis, keeping only the rotation around it.
+    pub fn to_2d(&self) -> Transform2<T> {
+        Transform2 {
+            position: self.position.xy(),
+            rotation: self.unit_rotation().euler_angles().2,
+            scale: self.scale.xy(),
+        }
+    }
+}
+impl<T: Float + RealField> Mul for Transform3<T> {
+    type Output = Self;
+    /// `child` expressed in the space `self` is in.
+    fn mul(self, child: Self) -> Self {
+        Self {
+            position: self.transform_point(&Point3::from(child.position)).coords,
+            rotation: (self.unit_rotation() * child.unit_rotation()).into_inner(),
+            scale: self.scale.component_mul(&child.scale),
+        }
+    }
+}
+impl<T: Float + RealField> From<Transform3<T>> for Matrix4<T> {
+    fn from(transform: Transform3<T>) -> Self {
+        transform.to_matrix()
+    }
+}
+impl<T: Float + RealField> From<Transform2<T>> for Transform3<T> {
+    fn from(transform: Transform2<T>) -> Self {
+        Self {
+            position: transform.position.push(T::zero()),
+            rotation: UnitQuaternion::from_axis_angle(&Vector3::z_axis(), transform.rotation)
+                .into_inner(),
+            scale: transform.scale.push(T::one()),
+        }
+    }
+}
 
-#[derive(Default, Debug, Clone)]
+/// Position, rotation (in radians, counter-clockwise) and scale in 2D, applied in the reverse
+/// order like [`Transform3`].
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transform2<T: 'static + Float + Debug> {
     pub position: Vector2<T>,
     pub rotation: T,
     pub scale: Vector2<T>,
 }
+impl<T: 'static + Float + Debug> Default for Transform2<T> {
+    fn default() -> Self {
+        Self {
+            position: Vector2::repeat(T::zero()),
+            rotation: T::zero(),
+            scale: Vector2::repeat(T::one()),
+        }
+    }
+}
+impl<T: 'static + Float + Debug> Transform2<T> {
+    pub fn identity() -> Self {
+        Self::default()
+    }
+    pub fn from_position(position: Vector2<T>) -> Self {
+        Self {
+            position,
+            ..Self::default()
+        }
+    }
+    pub fn translate(&mut self, translation: &Vector2<T>) {
+        self.position.x = self.position.x + translation.x;
+        self.position.y = self.position.y + translation.y;
+    }
+    pub fn rotate(&mut self, angle: T) {
+        self.rotation = self.rotation + angle;
+    }
+    pub fn scale_by(&mut self, scale: &Vector2<T>) {
+        self.scale.x = self.scale.x * scale.x;
+        self.scale.y = self.scale.y * scale.y;
+    }
+}
+impl<T: Float + RealField> Transform2<T> {
+    pub fn to_matrix(&self) -> Matrix3<T> {
+        Matrix3::new_translation(&self.position)
+            * Rotation2::new(self.rotation).to_homogeneous()
+            * Matrix3::new_nonuniform_scaling(&self.scale)
+    }
+    pub fn transform_point(&self, point: &Point2<T>) -> Point2<T> {
+        Point2::from(self.transform_vector(&point.coords) + self.position)
+    }
+    /// Scales and rotates a vector, ignoring the translation.
+    pub fn transform_vector(&self, vector: &Vector2<T>) -> Vector2<T> {
+        Rotation2::new(self.rotation) * vector.component_mul(&self.scale)
+    }
+    pub fn inverse(&self) -> Self {
+        let rotation = Rotation2::new(-self.rotation);
+        let scale = self.scale.map(|s| T::one() / s);
+        Self {
+            position: -(rotation * self.position).component_mul(&scale),
+            rotation: -self.rotation,
+            scale,
+        }
+    }
+    /// Rotates the transform so that its X axis points at `target`.
+    pub fn look_at(&mut self, target: &Vector2<T>) {
+        let direction = target - self.position;
+        self.rotation = RealField::atan2(direction.y, direction.x);
+    }
+}
+impl<T: Float + RealField> Mul for Transform2<T> {
+    type Output = Self;
+    /// `child` expressed in the space `self` is in.
+    #[allow(clippy::suspicious_arithmetic_impl)] // Angles add up
+    fn mul(self, child: Self) -> Self {
+        Self {
+            position: self.transform_point(&Point2::from(child.position)).coords,
+            rotation: self.rotation + child.rotation,
+            scale: self.scale.component_mul(&child.scale),
+        }
+    }
+}
+impl<T: Float + RealField> From<Transform2<T>> for Matrix3<T> {
+    fn from(transform: Transform2<T>) -> Self {
+        transform.to_matrix()
+    }
+}
+impl<T: Float + RealField> From<Transform3<T>> for Transform2<T> {
+    fn from(transform: Transform3<T>) -> Self {
+        transform.to_2d()
+    }
+}
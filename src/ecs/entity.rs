@@ -1,16 +1,78 @@
 use crate::ecs::components::Component;
-use crate::rendering::camera::Camera2d;
-use downcast_rs::Downcast;
+use crate::ecs::world::{AddedTypes, Borrow};
 use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLockReadGuard, RwLockWriteGuard};
 
-#[derive(Default)]
+/// Handle to an entity inside a [`World`](crate::ecs::world::World).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId(pub(crate) usize);
+impl EntityId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+impl Display for EntityId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Maps the ids entities had in a merged world to the ids they got in the world they were merged
+/// into. Returned by [`World::merge`](crate::ecs::world::World::merge).
+#[derive(Default, Debug, Clone)]
+pub struct EntityMap {
+    map: HashMap<EntityId, EntityId>,
+}
+impl EntityMap {
+    pub fn get(&self, old: EntityId) -> Option<EntityId> {
+        self.map.get(&old).copied()
+    }
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, EntityId)> + '_ {
+        self.map.iter().map(|(old, new)| (*old, *new))
+    }
+    pub(crate) fn insert(&mut self, old: EntityId, new: EntityId) {
+        self.map.insert(old, new);
+    }
+}
+
+/// Components that hold [`EntityId`]s of other entities.
+///
+/// Register them with [`World::register_map_entities`](crate::ecs::world::World::register_map_entities)
+/// so their ids are rewritten when their world is merged into another one.
+pub trait MapEntities: Component {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+#[derive(Default, Debug)]
 pub struct Entity {
     components: Vec<Box<dyn Component>>,
+    pub(crate) generation: Option<Arc<AtomicU64>>,
+    /// Index of the entity in its world, and where the world expects the types of components
+    /// added while the entity is borrowed.
+    pub(crate) added_types: Option<(usize, AddedTypes)>,
 }
 impl Entity {
     pub fn add_component<T: Component>(&mut self, component: T) {
         self.components.push(Box::new(component));
+        if let Some((index, added_types)) = &self.added_types {
+            added_types
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push((*index, TypeId::of::<T>()));
+        }
+        if let Some(generation) = &self.generation {
+            generation.fetch_add(1, Ordering::Relaxed);
+        }
     }
     pub fn get_component<T: 'static>(&self) -> Option<&T> {
         self.get_components().next()
@@ -28,13 +90,57 @@ impl Entity {
             .iter_mut()
             .filter_map(|c| c.deref_mut().as_any_mut().downcast_mut())
     }
-    pub(crate) fn filter_typeid(&self, typeid: TypeId) -> impl Iterator<Item = &(dyn Component)> {
-        self.components.iter().filter_map(move |c| {
-            if c.deref().as_any().type_id() == typeid {
-                Some(c.deref())
-            } else {
-                None
-            }
-        })
+    pub(crate) fn types(&self) -> Vec<TypeId> {
+        self.components
+            .iter()
+            .map(|c| <dyn Any>::type_id(c.deref().as_any()))
+            .collect()
+    }
+}
+
+/// An entity borrowed from a [`World`](crate::ecs::world::World), until dropped.
+#[derive(Debug)]
+pub struct EntityRef<'w> {
+    guard: RwLockReadGuard<'w, Entity>,
+    _borrow: Borrow,
+}
+impl<'w> EntityRef<'w> {
+    pub(crate) fn new((guard, borrow): (RwLockReadGuard<'w, Entity>, Borrow)) -> Self {
+        Self {
+            guard,
+            _borrow: borrow,
+        }
+    }
+}
+impl Deref for EntityRef<'_> {
+    type Target = Entity;
+    fn deref(&self) -> &Entity {
+        &self.guard
+    }
+}
+
+/// An entity borrowed mutably from a [`World`](crate::ecs::world::World), until dropped.
+#[derive(Debug)]
+pub struct EntityMut<'w> {
+    guard: RwLockWriteGuard<'w, Entity>,
+    _borrow: Borrow,
+}
+impl<'w> EntityMut<'w> {
+    pub(crate) fn new((guard, borrow): (RwLockWriteGuard<'w, Entity>, Borrow)) -> Self {
+        Self {
+            guard,
+            _borrow: borrow,
+        }
+    }
+}
+impl Deref for EntityMut<'_> {
+    type Target = Entity;
+    fn deref(&self) -> &Entity {
+        &self.guard
+    }
+}
+impl DerefMut for EntityMut<'_> {
+    fn deref_mut(&mut self) -> &mut Entity {
+        &mut self.guard
     }
 }
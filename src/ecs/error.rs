@@ -0,0 +1,109 @@
+use crate::ecs::entity::EntityId;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+        }
+    }
+}
+
+/// Why an entity couldn't be borrowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BorrowError {
+    /// The entity is already borrowed by the same thread in a way that conflicts with `access`,
+    /// most likely by a guard that is still alive further up the same system. Waiting for it
+    /// would deadlock, unlike for borrows held by other threads.
+    Conflict {
+        entity: EntityId,
+        access: Access,
+        query: &'static str,
+        system: Option<Cow<'static, str>>,
+    },
+    /// There is no entity with this id in the world.
+    Missing { entity: EntityId },
+    /// A system panicked while it held a write borrow of the entity.
+    Poisoned {
+        entity: EntityId,
+        query: &'static str,
+        system: Option<Cow<'static, str>>,
+    },
+    /// The resource is already borrowed by the same thread in a way that conflicts with `access`.
+    ResourceConflict {
+        resource: &'static str,
+        access: Access,
+        system: Option<Cow<'static, str>>,
+    },
+    /// A system panicked while it held a write borrow of the resource.
+    ResourcePoisoned {
+        resource: &'static str,
+        system: Option<Cow<'static, str>>,
+    },
+}
+impl Display for BorrowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BorrowError::Conflict {
+                entity,
+                access,
+                query,
+                system,
+            } => {
+                write!(
+                    f,
+                    "{access} borrow of entity {entity} for `{query}` conflicts with a borrow that is still held"
+                )?;
+                if let Some(system) = system {
+                    write!(f, " (in system `{system}`)")?;
+                }
+                Ok(())
+            }
+            BorrowError::Missing { entity } => write!(f, "entity {entity} does not exist"),
+            BorrowError::Poisoned {
+                entity,
+                query,
+                system,
+            } => {
+                write!(
+                    f,
+                    "entity {entity} requested by `{query}` was poisoned by an earlier panic"
+                )?;
+                if let Some(system) = system {
+                    write!(f, " (in system `{system}`)")?;
+                }
+                Ok(())
+            }
+            BorrowError::ResourceConflict {
+                resource,
+                access,
+                system,
+            } => {
+                write!(
+                    f,
+                    "{access} borrow of resource `{resource}` conflicts with a borrow that is still held"
+                )?;
+                if let Some(system) = system {
+                    write!(f, " (in system `{system}`)")?;
+                }
+                Ok(())
+            }
+            BorrowError::ResourcePoisoned { resource, system } => {
+                write!(f, "resource `{resource}` was poisoned by an earlier panic")?;
+                if let Some(system) = system {
+                    write!(f, " (in system `{system}`)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+impl Error for BorrowError {}
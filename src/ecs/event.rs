@@ -0,0 +1,93 @@
+use crate::ecs::components::Component;
+use crate::ecs::world::CurrentSystem;
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::sync::PoisonError;
+
+/// Queue of events of type `T`, stored as a resource and registered with
+/// [`World::add_event`](crate::ecs::world::World::add_event).
+///
+/// Events stay readable for the frame they were sent in and the following one, so a system
+/// running before the sender still sees them. Each reader keeps an [`EventReader`] to only see
+/// every event once.
+#[derive(Debug)]
+pub struct Events<T: Component> {
+    /// Id, sending system and event.
+    events: Vec<(usize, Option<Cow<'static, str>>, T)>,
+    next_id: usize,
+    frame_start: usize,
+    /// Set by [`World::add_event`](crate::ecs::world::World::add_event) to attribute events to
+    /// the system sending them.
+    pub(crate) current_system: Option<CurrentSystem>,
+}
+impl<T: Component> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            next_id: 0,
+            frame_start: 0,
+            current_system: None,
+        }
+    }
+}
+impl<T: Component> Events<T> {
+    /// Sends `event` on behalf of the system currently running, see
+    /// [`EventReader::read_with_sender`].
+    pub fn send(&mut self, event: T) {
+        let system = self.current_system.as_ref().and_then(|system| {
+            system
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        });
+        self.events.push((self.next_id, system, event));
+        self.next_id += 1;
+    }
+    /// Every event still stored, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.events.iter().map(|(_, _, e)| e)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+    /// Drops the events of the previous frame. Called at the start of every frame by
+    /// [`Game::update`](crate::ecs::game::Game::update).
+    pub fn update(&mut self) {
+        let frame_start = self.frame_start;
+        self.events.retain(|(id, _, _)| *id >= frame_start);
+        self.frame_start = self.next_id;
+    }
+}
+
+/// Position of a reader in an [`Events`] queue.
+#[derive(Debug, Default, Clone)]
+pub struct EventReader {
+    next: usize,
+}
+impl EventReader {
+    /// Events sent since the last call, oldest first.
+    pub fn read<'e, T: Component>(&mut self, events: &'e Events<T>) -> impl Iterator<Item = &'e T> {
+        self.read_with_sender(events).map(|(_, e)| e)
+    }
+    /// Events sent since the last call with the system that sent them, or `None` when they were
+    /// sent from outside systems, like by a runner, or to a queue that wasn't registered with
+    /// [`World::add_event`](crate::ecs::world::World::add_event).
+    pub fn read_with_sender<'e, T: Component>(
+        &mut self,
+        events: &'e Events<T>,
+    ) -> impl Iterator<Item = (Option<&'e str>, &'e T)> {
+        let next = self.next;
+        self.next = events.next_id;
+        events
+            .events
+            .iter()
+            .filter(move |(id, _, _)| *id >= next)
+            .map(|(_, system, e)| (system.as_deref(), e))
+    }
+}
@@ -1,30 +1,330 @@
+use crate::ecs::event::Events;
+use crate::ecs::pacing::{FramePacer, FrameRateCap};
+use crate::ecs::stats::{FrameStats, FrameTimings};
+use crate::ecs::system::{ErrorPolicy, IntoSystem};
+use crate::ecs::time::{Clock, SystemClock, Time};
 use crate::ecs::world::World;
 use crate::ecs::System;
-use std::time::Duration;
+use crate::error::Result;
+use crate::random::{Rng, RngConfig};
+use crate::runner::{HeadlessRunner, Runner};
+use std::any::type_name;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Groups of systems, run in this order every frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+}
+
+/// Event asking the game to stop. [`Game::run`] returns once it is sent.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AppExit;
+
+struct SystemEntry {
+    name: Cow<'static, str>,
+    system: Box<System>,
+    policy: Option<ErrorPolicy>,
+    disabled: bool,
+}
+
+/// Returned when adding a system, to configure it further.
+pub struct SystemConfig<'a> {
+    entry: &'a mut SystemEntry,
+    names: &'a mut HashSet<Cow<'static, str>>,
+}
+impl SystemConfig<'_> {
+    /// Overrides the game's default [`ErrorPolicy`] for this system.
+    pub fn with_error_policy(self, policy: ErrorPolicy) -> Self {
+        self.entry.policy = Some(policy);
+        self
+    }
+    /// Name the system is reported under, in errors and in [`FrameStats`]. Names already taken
+    /// by another system get `#2`, `#3`, ... appended.
+    pub fn with_name(self, name: &'static str) -> Self {
+        self.names.remove(&self.entry.name);
+        self.entry.name = unique_name(self.names, name);
+        self
+    }
+}
 
 pub struct Game {
     pub world: World,
     pub delta_time: Duration,
-    systems: Vec<Box<System>>,
-    last_end: std::time::Instant,
+    stages: Vec<(Stage, Vec<SystemEntry>)>,
+    system_names: HashSet<Cow<'static, str>>,
+    error_policy: ErrorPolicy,
+    runner: Option<Box<dyn Runner>>,
+    clock: Box<dyn Clock>,
+    last_frame: Duration,
+    pacer: FramePacer,
+    focused: bool,
 }
 impl Game {
-    pub fn new(world: World) -> Self {
+    pub fn new(mut world: World) -> Self {
+        if !world.contains_resource::<FrameStats>() {
+            world.insert_resource(FrameStats::default());
+        }
+        if !world.contains_resource::<Time>() {
+            world.insert_resource(Time::default());
+        }
+        if !world.contains_resource::<Rng>() {
+            let config = world
+                .resource::<RngConfig>()
+                .map_or_else(RngConfig::default, |c| *c);
+            world.insert_resource(Rng::from_config(config));
+        }
+        world.register_snapshot_resource::<Rng>();
+        world.add_event::<AppExit>();
+        let clock = SystemClock::default();
         Game {
             world,
             delta_time: Duration::new(0, 0),
-            systems: Vec::new(),
-            last_end: std::time::Instant::now(),
+            stages: Stage::ALL.iter().map(|s| (*s, Vec::new())).collect(),
+            system_names: HashSet::new(),
+            error_policy: ErrorPolicy::default(),
+            runner: None,
+            last_frame: clock.now(),
+            clock: Box::new(clock),
+            pacer: FramePacer::default(),
+            focused: true,
+        }
+    }
+    /// Caps how often [`Game::update`] runs, by making it wait out the rest of each frame on its
+    /// clock.
+    ///
+    /// Frames that overrun the cap are counted in [`FrameStats::missed_deadlines`].
+    /// [`Game::step`] is never capped.
+    pub fn set_frame_rate_cap(&mut self, cap: FrameRateCap) {
+        self.pacer.cap = cap;
+    }
+    pub fn frame_rate_cap(&self) -> FrameRateCap {
+        self.pacer.cap
+    }
+    /// Whether the game has focus, choosing between the focused and unfocused frame rate caps.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+    /// Replaces the clock [`Game::update`] measures frame times with, for instance with a
+    /// [`ManualClock`](crate::ecs::time::ManualClock) in tests.
+    pub fn set_clock<C: 'static + Clock>(&mut self, clock: C) {
+        self.last_frame = clock.now();
+        self.clock = Box::new(clock);
+        self.pacer.reset();
+    }
+    /// Sets what drives the game when calling [`Game::run`].
+    pub fn set_runner<R: 'static + Runner>(&mut self, runner: R) {
+        self.runner = Some(Box::new(runner));
+    }
+    /// Hands the game over to its runner, by default a [`HeadlessRunner`] that updates it until
+    /// an [`AppExit`] event is sent.
+    pub fn run(mut self) -> Result<()> {
+        let runner = self
+            .runner
+            .take()
+            .unwrap_or_else(|| Box::new(HeadlessRunner::default()));
+        runner.run(self)
+    }
+    /// Whether an [`AppExit`] event was sent during this frame or the previous one.
+    pub fn exit_requested(&self) -> bool {
+        self.world
+            .resource::<Events<AppExit>>()
+            .is_some_and(|events| !events.is_empty())
+    }
+    /// Policy used for systems that weren't given their own.
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+    /// Adds a system to [`Stage::Update`].
+    pub fn add_system<M, S: IntoSystem<M>>(&mut self, system: S) -> SystemConfig<'_> {
+        self.add_system_to_stage(Stage::Update, system)
+    }
+    pub fn add_system_to_stage<M, S: IntoSystem<M>>(
+        &mut self,
+        stage: Stage,
+        system: S,
+    ) -> SystemConfig<'_> {
+        let entry = SystemEntry::new(system, &mut self.system_names);
+        let systems = stage_mut(&mut self.stages, stage);
+        systems.push(entry);
+        SystemConfig {
+            entry: systems.last_mut().unwrap(),
+            names: &mut self.system_names,
+        }
+    }
+    /// Adds a system that runs before every other system of `stage`, including the ones added
+    /// later with [`Game::add_system_to_stage`].
+    pub fn add_system_to_stage_start<M, S: IntoSystem<M>>(
+        &mut self,
+        stage: Stage,
+        system: S,
+    ) -> SystemConfig<'_> {
+        let entry = SystemEntry::new(system, &mut self.system_names);
+        let systems = stage_mut(&mut self.stages, stage);
+        systems.insert(0, entry);
+        SystemConfig {
+            entry: &mut systems[0],
+            names: &mut self.system_names,
+        }
+    }
+    /// Runs every system once, with the time elapsed on the clock since the previous frame as
+    /// delta time.
+    ///
+    /// Errors returned by systems are handled according to their [`ErrorPolicy`]; only
+    /// [`ErrorPolicy::Abort`] makes them end the frame early and be returned from here.
+    pub fn update(&mut self) -> Result<()> {
+        let now = self.clock.now();
+        let delta_time = now.saturating_sub(self.last_frame);
+        self.last_frame = now;
+        let result = self.step(delta_time);
+        if self.pacer.wait(self.clock.as_ref(), self.focused) {
+            if let Some(mut stats) = self.world.resource_mut::<FrameStats>() {
+                stats.missed_deadlines += 1;
+            }
+        }
+        result
+    }
+    /// Runs every system once with `delta_time` as delta time, regardless of the clock.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "update", skip_all))]
+    pub fn step(&mut self, delta_time: Duration) -> Result<()> {
+        self.delta_time = delta_time;
+        if let Some(mut time) = self.world.resource_mut::<Time>() {
+            time.delta = delta_time;
+            time.elapsed += delta_time;
+            time.frame += 1;
+        }
+        let mut timings = FrameTimings::new(Instant::now());
+        let mut result = Ok(());
+        self.world.update_events();
+        'stages: for (stage, systems) in &mut self.stages {
+            #[cfg(feature = "tracing")]
+            let _stage = tracing::info_span!("stage", stage = ?stage).entered();
+            let stage_start = Instant::now();
+            for entry in systems.iter_mut().filter(|e| !e.disabled) {
+                #[cfg(feature = "tracing")]
+                let _system = tracing::info_span!("system", name = &*entry.name).entered();
+                let start = Instant::now();
+                self.world.set_current_system(Some(entry.name.clone()));
+                let outcome = entry.run(&self.world, self.error_policy);
+                timings
+                    .systems
+                    .push((entry.name.clone(), start, start.elapsed()));
+                if let Err(e) = outcome {
+                    result = Err(e);
+                    timings
+                        .stages
+                        .push((*stage, stage_start, stage_start.elapsed()));
+                    break 'stages;
+                }
+            }
+            timings
+                .stages
+                .push((*stage, stage_start, stage_start.elapsed()));
         }
+        self.world.set_current_system(None);
+        timings.end = Instant::now();
+        if let Some(mut stats) = self.world.resource_mut::<FrameStats>() {
+            stats.record(&timings, self.delta_time);
+        }
+        result
+    }
+    /// Steps `frames` frames of `delta_time` each, stopping at the first error.
+    pub fn step_frames(&mut self, frames: u64, delta_time: Duration) -> Result<()> {
+        for _ in 0..frames {
+            self.step(delta_time)?;
+        }
+        Ok(())
     }
-    pub fn add_system<F: 'static + FnMut(&World)>(&mut self, system: F) {
-        self.systems.push(Box::new(system));
+    /// Steps frames of `delta_time` until `condition` holds for the world, at most `max_frames`
+    /// times. Returns the number of frames it took, or `None` if it never held.
+    pub fn step_until<F: FnMut(&World) -> bool>(
+        &mut self,
+        delta_time: Duration,
+        max_frames: u64,
+        mut condition: F,
+    ) -> Result<Option<u64>> {
+        for frame in 1..=max_frames {
+            self.step(delta_time)?;
+            if condition(&self.world) {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+}
+fn stage_mut(stages: &mut [(Stage, Vec<SystemEntry>)], stage: Stage) -> &mut Vec<SystemEntry> {
+    let (_, systems) = stages.iter_mut().find(|(s, _)| *s == stage).unwrap();
+    systems
+}
+
+/// Reserves `name` in `names`, appending `#2`, `#3`, ... if it is already taken, for instance by
+/// another closure returned by the same function.
+fn unique_name(names: &mut HashSet<Cow<'static, str>>, name: &'static str) -> Cow<'static, str> {
+    let name = match names.contains(name) {
+        true => (2..)
+            .map(|i| format!("{name}#{i}"))
+            .find(|unique| !names.contains(unique.as_str()))
+            .map(Cow::Owned)
+            .unwrap(),
+        false => Cow::Borrowed(name),
+    };
+    names.insert(name.clone());
+    name
+}
+
+impl SystemEntry {
+    fn new<M, S: IntoSystem<M>>(system: S, names: &mut HashSet<Cow<'static, str>>) -> Self {
+        Self {
+            name: unique_name(names, type_name::<S>()),
+            system: system.into_system(),
+            policy: None,
+            disabled: false,
+        }
     }
-    pub fn update(&mut self) {
-        for system in &mut self.systems {
-            system(&self.world);
+    /// Runs the system, applying its error policy. Only returns an error to abort the frame.
+    fn run(&mut self, world: &World, default_policy: ErrorPolicy) -> Result<()> {
+        let policy = self.policy.unwrap_or(default_policy);
+        let mut error = match (self.system)(world) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        match policy {
+            ErrorPolicy::LogAndContinue => log::error!("system `{}` failed: {error}", self.name),
+            ErrorPolicy::Retry { attempts } => {
+                for attempt in 1..=attempts {
+                    log::warn!(
+                        "system `{}` failed, retrying ({attempt}/{attempts}): {error}",
+                        self.name
+                    );
+                    match (self.system)(world) {
+                        Ok(()) => return Ok(()),
+                        Err(e) => error = e,
+                    }
+                }
+                log::error!("system `{}` failed: {error}", self.name);
+            }
+            ErrorPolicy::Disable => {
+                log::error!("system `{}` failed and was disabled: {error}", self.name);
+                self.disabled = true;
+            }
+            ErrorPolicy::Abort => return Err(error),
         }
-        self.delta_time = self.last_end.elapsed();
-        self.last_end = std::time::Instant::now();
+        Ok(())
     }
 }
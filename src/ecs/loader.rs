@@ -0,0 +1,67 @@
+use crate::ecs::world::World;
+use std::thread::JoinHandle;
+
+/// Builds a [`World`] on a worker thread.
+///
+/// ## Usage
+/// ```rust
+/// # use goosberry::ecs::entity::Entity;
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::loader::WorldLoader;
+/// # use goosberry::ecs::world::World;
+/// let mut game = Game::new(World::default());
+/// let mut loader = WorldLoader::spawn(|level| {
+///     for _ in 0..1000 {
+///         level.add_entity(Entity::default());
+///     }
+/// });
+/// // Keep updating the game until the level is ready, then merge it in one step.
+/// let level = loop {
+///     game.update().unwrap();
+///     if let Some(level) = loader.poll() {
+///         break level;
+///     }
+/// };
+/// game.world.merge(level);
+/// ```
+pub struct WorldLoader {
+    handle: Option<JoinHandle<World>>,
+}
+impl WorldLoader {
+    pub fn spawn<F: 'static + Send + FnOnce(&mut World)>(build: F) -> Self {
+        let handle = std::thread::spawn(move || {
+            let mut world = World::default();
+            build(&mut world);
+            world
+        });
+        Self {
+            handle: Some(handle),
+        }
+    }
+    pub fn is_finished(&self) -> bool {
+        match &self.handle {
+            Some(handle) => handle.is_finished(),
+            None => true,
+        }
+    }
+    /// Returns the world once it is built, without blocking.
+    ///
+    /// Returns `None` while loading is in progress and after the world has been taken. Panics if
+    /// the loading closure panicked.
+    pub fn poll(&mut self) -> Option<World> {
+        if self.handle.as_ref()?.is_finished() {
+            self.join()
+        } else {
+            None
+        }
+    }
+    /// Blocks until the world is built. Returns `None` if it has already been taken.
+    pub fn join(&mut self) -> Option<World> {
+        let handle = self.handle.take()?;
+        Some(
+            handle
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e)),
+        )
+    }
+}
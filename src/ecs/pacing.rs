@@ -0,0 +1,73 @@
+use crate::ecs::time::Clock;
+use std::time::Duration;
+
+/// Highest frame rates [`Game::update`](crate::ecs::game::Game::update) may run at, while the
+/// game is focused and while it isn't. `None` means uncapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRateCap {
+    pub focused: Option<f64>,
+    pub unfocused: Option<f64>,
+    /// How long before a frame's deadline to stop sleeping and spin instead. Sleeping is cheap
+    /// but only accurate to a millisecond or so on most platforms.
+    pub spin: Duration,
+}
+impl Default for FrameRateCap {
+    fn default() -> Self {
+        Self {
+            focused: None,
+            unfocused: None,
+            spin: Duration::from_millis(1),
+        }
+    }
+}
+impl FrameRateCap {
+    /// The same cap whether focused or not.
+    pub fn fixed(frames_per_second: f64) -> Self {
+        Self {
+            focused: Some(frames_per_second),
+            unfocused: Some(frames_per_second),
+            ..Default::default()
+        }
+    }
+    pub fn frame_time(&self, focused: bool) -> Option<Duration> {
+        let fps = if focused {
+            self.focused
+        } else {
+            self.unfocused
+        }?;
+        (fps > 0.0).then(|| Duration::from_secs_f64(1.0 / fps))
+    }
+}
+
+/// Waits out the remainder of each frame to hold a [`FrameRateCap`].
+#[derive(Debug, Default)]
+pub(crate) struct FramePacer {
+    pub cap: FrameRateCap,
+    /// When the previous frame ended, on the game's clock.
+    last: Option<Duration>,
+}
+impl FramePacer {
+    /// Blocks on `clock` until the end of the current frame. Returns whether the frame overran
+    /// its budget.
+    pub fn wait(&mut self, clock: &dyn Clock, focused: bool) -> bool {
+        let now = clock.now();
+        let (Some(frame_time), Some(last)) = (self.cap.frame_time(focused), self.last) else {
+            self.last = Some(now);
+            return false;
+        };
+        let deadline = last + frame_time;
+        if now > deadline {
+            self.last = Some(now);
+            return true;
+        }
+        clock.wait_until(deadline, self.cap.spin);
+        // Next deadline is relative to this one rather than to when we woke up, so that small
+        // oversleeps don't accumulate into drift.
+        self.last = Some(deadline);
+        false
+    }
+    /// Forgets the end of the previous frame, when the clock it was measured on is replaced.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
@@ -0,0 +1,86 @@
+use crate::ecs::entity::{EntityMut, EntityRef};
+use crate::ecs::world::{expect_borrow, World};
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+use tuple_unpack::TupleUnpack;
+
+/// A query that remembers which entities matched it.
+///
+/// The matching set is rebuilt only when the structure of the world changes (an entity or a
+/// component is added), so a system that keeps a `QueryState` around doesn't rescan every
+/// entity each frame.
+///
+/// ## Usage
+/// ```rust
+/// # use goosberry::ecs::entity::Entity;
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::query::QueryState;
+/// # use goosberry::ecs::world::World;
+/// # #[derive(Debug)]
+/// # struct Foo {
+/// #     x: i32,
+/// # }
+/// let mut game = Game::new(World::default());
+/// let mut foos = QueryState::<(Foo,)>::new();
+/// game.add_system(move |world: &World| {
+///     for mut entity in foos.iter_mut(world) {
+///         entity.get_component_mut::<Foo>().unwrap().x += 1;
+///     }
+/// });
+/// ```
+pub struct QueryState<T: 'static + TupleUnpack> {
+    types: Vec<TypeId>,
+    matches: Vec<usize>,
+    seen: Option<(u64, u64)>,
+    marker: PhantomData<fn() -> T>,
+}
+impl<T: 'static + TupleUnpack> QueryState<T> {
+    pub fn new() -> Self {
+        Self {
+            types: T::unpack_types(),
+            matches: Vec::new(),
+            seen: None,
+            marker: PhantomData,
+        }
+    }
+    /// Rebuilds the cached matches if `world` isn't the world they were built from, or if its
+    /// structure changed since.
+    pub fn update(&mut self, world: &World) {
+        let key = (world.id(), world.generation());
+        if self.seen == Some(key) {
+            return;
+        }
+        self.matches = world.matching(&self.types);
+        self.seen = Some(key);
+    }
+    /// Number of entities matched the last time the cache was rebuilt.
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+    pub fn iter<'s, 'w: 's>(
+        &'s mut self,
+        world: &'w World,
+    ) -> impl Iterator<Item = EntityRef<'w>> + 's {
+        self.update(world);
+        self.matches
+            .iter()
+            .map(move |&i| expect_borrow(world.read_entity(i, type_name::<T>())))
+    }
+    pub fn iter_mut<'s, 'w: 's>(
+        &'s mut self,
+        world: &'w World,
+    ) -> impl Iterator<Item = EntityMut<'w>> + 's {
+        self.update(world);
+        self.matches
+            .iter()
+            .map(move |&i| expect_borrow(world.write_entity(i, type_name::<T>())))
+    }
+}
+impl<T: 'static + TupleUnpack> Default for QueryState<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
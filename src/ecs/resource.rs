@@ -0,0 +1,55 @@
+use crate::ecs::components::Component;
+use crate::ecs::world::Borrow;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+/// Shared borrow of a resource, returned by [`World::resource`](crate::ecs::world::World::resource).
+pub struct Res<'w, T: Component> {
+    guard: RwLockReadGuard<'w, Box<dyn Component>>,
+    _borrow: Borrow,
+    marker: PhantomData<&'w T>,
+}
+impl<'w, T: Component> Res<'w, T> {
+    pub(crate) fn new((guard, borrow): (RwLockReadGuard<'w, Box<dyn Component>>, Borrow)) -> Self {
+        Self {
+            guard,
+            _borrow: borrow,
+            marker: PhantomData,
+        }
+    }
+}
+impl<T: Component> Deref for Res<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.deref().as_any().downcast_ref().unwrap()
+    }
+}
+
+/// Exclusive borrow of a resource, returned by
+/// [`World::resource_mut`](crate::ecs::world::World::resource_mut).
+pub struct ResMut<'w, T: Component> {
+    guard: RwLockWriteGuard<'w, Box<dyn Component>>,
+    _borrow: Borrow,
+    marker: PhantomData<&'w mut T>,
+}
+impl<'w, T: Component> ResMut<'w, T> {
+    pub(crate) fn new((guard, borrow): (RwLockWriteGuard<'w, Box<dyn Component>>, Borrow)) -> Self {
+        Self {
+            guard,
+            _borrow: borrow,
+            marker: PhantomData,
+        }
+    }
+}
+impl<T: Component> Deref for ResMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.guard.deref().as_any().downcast_ref().unwrap()
+    }
+}
+impl<T: Component> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.deref_mut().as_any_mut().downcast_mut().unwrap()
+    }
+}
@@ -0,0 +1,186 @@
+use crate::ecs::components::Component;
+use crate::ecs::entity::EntityId;
+use nalgebra::Vector2;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Resources and components whose state can be saved into, and restored from, a
+/// [`WorldSnapshot`].
+pub trait Snapshot: Component {
+    fn save(&self, out: &mut Vec<u8>);
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotError {
+    pub message: String,
+}
+impl SnapshotError {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid snapshot: {}", self.message)
+    }
+}
+impl std::error::Error for SnapshotError {}
+
+/// Saved state of every registered [`Snapshot`] resource and component of a world, see
+/// [`World::snapshot`](crate::ecs::world::World::snapshot).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorldSnapshot {
+    /// Saved resources, by type name.
+    pub resources: BTreeMap<String, Vec<u8>>,
+    /// Saved components, by type name, with the entity they belong to.
+    pub components: BTreeMap<String, Vec<(EntityId, Vec<u8>)>>,
+}
+impl WorldSnapshot {
+    /// Hash of the whole snapshot, stable across runs and platforms.
+    pub fn hash(&self) -> u64 {
+        let mut data = Vec::new();
+        for (name, resource) in &self.resources {
+            write_string(&mut data, name);
+            write_bytes(&mut data, resource);
+        }
+        for (name, components) in &self.components {
+            write_string(&mut data, name);
+            for (entity, component) in components {
+                write_varint(&mut data, entity.0 as u64);
+                write_bytes(&mut data, component);
+            }
+        }
+        fnv1a(&data)
+    }
+    pub fn save(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.resources.len() as u64);
+        for (name, resource) in &self.resources {
+            write_string(out, name);
+            write_bytes(out, resource);
+        }
+        write_varint(out, self.components.len() as u64);
+        for (name, components) in &self.components {
+            write_string(out, name);
+            write_varint(out, components.len() as u64);
+            for (entity, component) in components {
+                write_varint(out, entity.0 as u64);
+                write_bytes(out, component);
+            }
+        }
+    }
+    pub fn load(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError> {
+        let mut snapshot = WorldSnapshot::default();
+        for _ in 0..reader.varint()? {
+            let name = reader.string()?;
+            let len = reader.varint()? as usize;
+            snapshot.resources.insert(name, reader.bytes(len)?.to_vec());
+        }
+        for _ in 0..reader.varint()? {
+            let name = reader.string()?;
+            let mut components = Vec::new();
+            for _ in 0..reader.varint()? {
+                let entity = EntityId(reader.varint()? as usize);
+                let len = reader.varint()? as usize;
+                components.push((entity, reader.bytes(len)?.to_vec()));
+            }
+            snapshot.components.insert(name, components);
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Reads the little-endian values written by [`Snapshot::save`] implementations.
+pub struct SnapshotReader<'a> {
+    data: &'a [u8],
+}
+impl<'a> SnapshotReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < len {
+            return Err(SnapshotError::new("unexpected end of data"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+    pub fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    pub fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    pub fn f32(&mut self) -> Result<f32, SnapshotError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+    pub fn vector2(&mut self) -> Result<Vector2<f32>, SnapshotError> {
+        Ok(Vector2::new(self.f32()?, self.f32()?))
+    }
+    /// Reads an unsigned LEB128 integer, as written by [`write_varint`].
+    pub fn varint(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SnapshotError::new("varint too long"))
+    }
+    pub fn string(&mut self) -> Result<String, SnapshotError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|e| SnapshotError::new(e.to_string()))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+pub fn write_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+pub fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+pub fn write_f32(out: &mut Vec<u8>, value: f32) {
+    write_u32(out, value.to_bits());
+}
+pub fn write_vector2(out: &mut Vec<u8>, value: Vector2<f32>) {
+    write_f32(out, value.x);
+    write_f32(out, value.y);
+}
+/// Writes an unsigned LEB128 integer, one byte for values below 128.
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+/// Writes `value` prefixed with its length as a varint.
+pub fn write_bytes(out: &mut Vec<u8>, value: &[u8]) {
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+pub fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// FNV-1a hash, stable across runs and platforms unlike `DefaultHasher`.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,278 @@
+use crate::ecs::game::Stage;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Rolling window of durations.
+#[derive(Debug, Clone)]
+pub struct Timings {
+    history: VecDeque<Duration>,
+    capacity: usize,
+    total: Duration,
+}
+impl Timings {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            total: Duration::ZERO,
+        }
+    }
+    pub fn record(&mut self, duration: Duration) {
+        if self.history.len() == self.capacity {
+            if let Some(oldest) = self.history.pop_front() {
+                self.total -= oldest;
+            }
+        }
+        self.history.push_back(duration);
+        self.total += duration;
+    }
+    pub fn last(&self) -> Option<Duration> {
+        self.history.back().copied()
+    }
+    pub fn average(&self) -> Duration {
+        if self.history.is_empty() {
+            Duration::ZERO
+        } else {
+            self.total / self.history.len() as u32
+        }
+    }
+    pub fn min(&self) -> Duration {
+        self.history.iter().min().copied().unwrap_or_default()
+    }
+    pub fn max(&self) -> Duration {
+        self.history.iter().max().copied().unwrap_or_default()
+    }
+    /// Nearest-rank percentile of the window, `p` being in `0.0..=100.0`.
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.history.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut sorted: Vec<_> = self.history.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.saturating_sub(1)]
+    }
+    /// Recorded durations, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.history.iter().copied()
+    }
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+}
+
+/// What happened during one call to [`Game::update`](crate::ecs::game::Game::update).
+#[derive(Debug, Clone)]
+pub(crate) struct FrameTimings {
+    pub start: Instant,
+    pub end: Instant,
+    pub stages: Vec<(Stage, Instant, Duration)>,
+    pub systems: Vec<(Cow<'static, str>, Instant, Duration)>,
+}
+impl FrameTimings {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            end: start,
+            stages: Vec::new(),
+            systems: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TraceEvent {
+    name: String,
+    category: &'static str,
+    start: Duration,
+    duration: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct Trace {
+    start: Instant,
+    frames: VecDeque<Vec<TraceEvent>>,
+    capacity: usize,
+}
+
+/// Frame and system timings, recorded by [`Game::update`](crate::ecs::game::Game::update) into
+/// this resource every frame.
+///
+/// ## Usage
+/// ```rust
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::stats::FrameStats;
+/// # use goosberry::ecs::world::World;
+/// let mut game = Game::new(World::default());
+/// game.update().unwrap();
+/// let stats = game.world.resource::<FrameStats>().unwrap();
+/// println!("{:.1} fps, p99 {:?}", stats.fps(), stats.frame_time.percentile(99.0));
+/// for (system, timings) in stats.systems() {
+///     println!("{system}: {:?}", timings.average());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    /// Number of frames recorded so far.
+    pub frame: u64,
+    pub frame_time: Timings,
+    /// Number of frames that took longer than the frame rate cap allows, see
+    /// [`Game::set_frame_rate_cap`](crate::ecs::game::Game::set_frame_rate_cap).
+    pub missed_deadlines: u64,
+    stages: HashMap<Stage, Timings>,
+    systems: HashMap<Cow<'static, str>, Timings>,
+    history: usize,
+    trace: Option<Trace>,
+}
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::with_history(240)
+    }
+}
+impl FrameStats {
+    /// Keeps the last `history` frames for averages and percentiles.
+    pub fn with_history(history: usize) -> Self {
+        Self {
+            frame: 0,
+            frame_time: Timings::new(history),
+            missed_deadlines: 0,
+            stages: HashMap::new(),
+            systems: HashMap::new(),
+            history,
+            trace: None,
+        }
+    }
+    /// Frames per second, averaged over the history window.
+    pub fn fps(&self) -> f64 {
+        let average = self.frame_time.average().as_secs_f64();
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+    pub fn stage(&self, stage: Stage) -> Option<&Timings> {
+        self.stages.get(&stage)
+    }
+    /// Timings of a system, by the name it was registered with.
+    pub fn system(&self, name: &str) -> Option<&Timings> {
+        self.systems.get(name)
+    }
+    pub fn systems(&self) -> impl Iterator<Item = (&str, &Timings)> {
+        self.systems
+            .iter()
+            .map(|(name, timings)| (name.as_ref(), timings))
+    }
+    /// Starts keeping per-frame events of the last `frames` frames for
+    /// [`FrameStats::chrome_trace`].
+    pub fn start_trace(&mut self, frames: usize) {
+        self.trace = Some(Trace {
+            start: Instant::now(),
+            frames: VecDeque::with_capacity(frames),
+            capacity: frames.max(1),
+        });
+    }
+    pub fn stop_trace(&mut self) {
+        self.trace = None;
+    }
+    /// Traced events in the Chrome trace event format, which can be opened in `chrome://tracing`
+    /// or Perfetto. Empty if tracing wasn't started.
+    pub fn chrome_trace(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        let events = self.trace.iter().flat_map(|t| t.frames.iter().flatten());
+        for (i, event) in events.enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(
+                json,
+                "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":0}}",
+                escape(&event.name),
+                event.category,
+                event.start.as_secs_f64() * 1e6,
+                event.duration.as_secs_f64() * 1e6,
+            )
+            .unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+    pub fn write_chrome_trace<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.chrome_trace())
+    }
+    pub(crate) fn record(&mut self, frame: &FrameTimings, frame_time: Duration) {
+        self.frame += 1;
+        self.frame_time.record(frame_time);
+        let history = self.history;
+        for (stage, _, duration) in &frame.stages {
+            self.stages
+                .entry(*stage)
+                .or_insert_with(|| Timings::new(history))
+                .record(*duration);
+        }
+        for (name, _, duration) in &frame.systems {
+            match self.systems.get_mut(name) {
+                Some(timings) => timings.record(*duration),
+                None => {
+                    let mut timings = Timings::new(history);
+                    timings.record(*duration);
+                    self.systems.insert(name.clone(), timings);
+                }
+            }
+        }
+        if let Some(trace) = &mut self.trace {
+            let since = |instant: Instant| instant.saturating_duration_since(trace.start);
+            let mut events = vec![TraceEvent {
+                name: format!("frame {}", self.frame),
+                category: "frame",
+                start: since(frame.start),
+                duration: frame.end - frame.start,
+            }];
+            events.extend(
+                frame
+                    .stages
+                    .iter()
+                    .map(|(stage, start, duration)| TraceEvent {
+                        name: format!("{stage:?}"),
+                        category: "stage",
+                        start: since(*start),
+                        duration: *duration,
+                    }),
+            );
+            events.extend(
+                frame
+                    .systems
+                    .iter()
+                    .map(|(name, start, duration)| TraceEvent {
+                        name: name.to_string(),
+                        category: "system",
+                        start: since(*start),
+                        duration: *duration,
+                    }),
+            );
+            if trace.frames.len() == trace.capacity {
+                trace.frames.pop_front();
+            }
+            trace.frames.push_back(events);
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\u{0}'..='\u{1f}' => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
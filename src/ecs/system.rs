@@ -0,0 +1,39 @@
+use crate::ecs::world::World;
+use crate::ecs::System;
+use crate::error::Result;
+
+/// What [`Game::update`](crate::ecs::game::Game::update) does when a system returns an error.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Log the error and carry on with the next system.
+    #[default]
+    LogAndContinue,
+    /// Run the system again, up to `attempts` more times in the same frame, then log and
+    /// continue if it still fails.
+    Retry { attempts: u32 },
+    /// Log the error and never run the system again.
+    Disable,
+    /// Stop the frame and return the error from `Game::update`.
+    Abort,
+}
+
+/// Anything that can be added to a [`Game`](crate::ecs::game::Game) as a system: a function
+/// taking the world, returning either nothing or a [`Result`].
+///
+/// The `Marker` parameter only exists to tell both kinds apart and is always inferred.
+pub trait IntoSystem<Marker> {
+    fn into_system(self) -> Box<System>;
+}
+impl<F: 'static + FnMut(&World)> IntoSystem<()> for F {
+    fn into_system(mut self) -> Box<System> {
+        Box::new(move |world| {
+            self(world);
+            Ok(())
+        })
+    }
+}
+impl<F: 'static + FnMut(&World) -> Result<()>> IntoSystem<Result<()>> for F {
+    fn into_system(self) -> Box<System> {
+        Box::new(self)
+    }
+}
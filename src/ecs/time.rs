@@ -0,0 +1,78 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Source of time for [`Game::update`](crate::ecs::game::Game::update).
+pub trait Clock: Debug {
+    /// Time elapsed since an arbitrary, fixed origin. Must never go backwards.
+    fn now(&self) -> Duration;
+    /// Blocks until [`Clock::now`] reaches `deadline`, which a
+    /// [`FrameRateCap`](crate::ecs::pacing::FrameRateCap) waits for at the end of each frame.
+    ///
+    /// By default, sleeps until `spin` before the deadline, then spins.
+    fn wait_until(&self, deadline: Duration, spin: Duration) {
+        let now = self.now();
+        if let Some(sleep) = deadline.saturating_sub(now).checked_sub(spin) {
+            std::thread::sleep(sleep);
+        }
+        while self.now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// The wall clock.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    origin: Instant,
+}
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so one can be given to the
+/// game while another one is kept to advance it.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<Mutex<Duration>>,
+}
+impl ManualClock {
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+}
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Advances the clock to `deadline` instead of blocking.
+    fn wait_until(&self, deadline: Duration, _spin: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(PoisonError::into_inner);
+        *now = (*now).max(deadline);
+    }
+}
+
+/// Timing of the current frame, stored as a resource and updated before any system runs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Time {
+    /// Time since the previous frame.
+    pub delta: Duration,
+    /// Sum of every delta so far.
+    pub elapsed: Duration,
+    /// Number of the current frame, starting at 1.
+    pub frame: u64,
+}
+impl Time {
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+}
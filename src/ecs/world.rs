@@ -1,12 +1,46 @@
-use crate::ecs::entity::Entity;
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use crate::ecs::components::Component;
+use crate::ecs::entity::{Entity, EntityId, EntityMap, EntityMut, EntityRef, MapEntities};
+use crate::ecs::error::{Access, BorrowError};
+use crate::ecs::event::Events;
+use crate::ecs::resource::{Res, ResMut};
+use crate::ecs::snapshot::{Snapshot, SnapshotError, WorldSnapshot};
+use std::any::{type_name, TypeId};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
 use tuple_unpack::TupleUnpack;
 
+static NEXT_WORLD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Types of the components added to entities of a world while they were borrowed, by entity
+/// index.
+pub(crate) type AddedTypes = Arc<Mutex<Vec<(usize, TypeId)>>>;
+/// Name of the system a world's game is running, shared with the world's event queues.
+pub(crate) type CurrentSystem = Arc<Mutex<Option<Cow<'static, str>>>>;
+type EntityMapper = fn(&mut Entity, &EntityMap);
+
+#[derive(Copy, Clone)]
+struct SnapshotFns {
+    save: fn(&World, &mut WorldSnapshot),
+    restore: fn(&World, &WorldSnapshot) -> Result<(), SnapshotError>,
+}
+
 /// ## Usage
 /// ```rust
 /// # use goosberry::ecs::entity::Entity;
 /// # use goosberry::ecs::game::Game;
 /// # use goosberry::ecs::world::World;
+/// # #[derive(Debug)]
+/// # struct Foo {
+/// #     x: i32,
+/// # }
+/// # #[derive(Debug)]
+/// # struct Bar {
+/// #     x: i32,
+/// # }
+/// # fn some_system(_world: &World) {}
 /// let foo = Foo { x: 0 };
 /// let bar = Bar { x: 0 };
 ///
@@ -20,43 +54,480 @@ use tuple_unpack::TupleUnpack;
 /// let mut game = Game::new(world);
 /// game.add_system(some_system);
 /// ```
-#[derive(Default)]
+///
+/// Entities are borrowed through [`RwLock`]s. Borrows held by other threads are waited for, but a
+/// system that still holds a guard from [`World::query_mut`] and queries the same entity again
+/// gets a [`BorrowError`] instead of deadlocking. The panicking methods report it with the name
+/// of the running system, the `try_` variants return it. The same goes for resources, the
+/// singletons stored next to the entities.
 pub struct World {
     pub(crate) entities: Vec<RwLock<Entity>>,
+    /// The component types of every entity, readable while the entity is borrowed mutably.
+    component_types: Vec<Vec<TypeId>>,
+    /// Component types added through borrows, not yet moved into `component_types`.
+    added_types: AddedTypes,
+    id: u64,
+    generation: Arc<AtomicU64>,
+    current_system: CurrentSystem,
+    entity_mappers: HashMap<TypeId, EntityMapper>,
+    resources: HashMap<TypeId, RwLock<Box<dyn Component>>>,
+    event_updaters: HashMap<TypeId, fn(&World)>,
+    snapshots: HashMap<TypeId, SnapshotFns>,
+}
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            entities: Vec::new(),
+            component_types: Vec::new(),
+            added_types: AddedTypes::default(),
+            id: NEXT_WORLD_ID.fetch_add(1, Ordering::Relaxed),
+            generation: Arc::new(AtomicU64::new(0)),
+            current_system: CurrentSystem::default(),
+            entity_mappers: HashMap::new(),
+            resources: HashMap::new(),
+            event_updaters: HashMap::new(),
+            snapshots: HashMap::new(),
+        }
+    }
 }
 impl World {
-    pub fn add_entity(&mut self, entity: Entity) {
+    pub fn add_entity(&mut self, mut entity: Entity) -> EntityId {
+        entity.generation = Some(self.generation.clone());
+        entity.added_types = Some((self.entities.len(), self.added_types.clone()));
+        self.sync_component_types();
+        self.component_types.push(entity.types());
         self.entities.push(RwLock::new(entity));
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        EntityId(self.entities.len() - 1)
+    }
+    /// Stores a world-wide singleton, replacing any previous resource of the same type.
+    pub fn insert_resource<T: Component>(&mut self, resource: T) {
+        self.resources
+            .insert(TypeId::of::<T>(), RwLock::new(Box::new(resource)));
+    }
+    pub fn remove_resource<T: Component>(&mut self) -> Option<T> {
+        let resource = self.resources.remove(&TypeId::of::<T>())?;
+        let resource = resource
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        resource.into_any().downcast().ok().map(|r| *r)
+    }
+    pub fn contains_resource<T: Component>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+    /// Panics if the resource is currently borrowed mutably, see [`World::try_resource`].
+    pub fn resource<T: Component>(&self) -> Option<Res<'_, T>> {
+        self.try_resource().map(expect_borrow)
+    }
+    /// Panics if the resource is currently borrowed, see [`World::try_resource_mut`].
+    pub fn resource_mut<T: Component>(&self) -> Option<ResMut<'_, T>> {
+        self.try_resource_mut().map(expect_borrow)
+    }
+    pub fn try_resource<T: Component>(&self) -> Option<Result<Res<'_, T>, BorrowError>> {
+        let resource = self.resources.get(&TypeId::of::<T>())?;
+        Some(
+            read(resource)
+                .map(Res::new)
+                .map_err(|e| self.resource_error::<T, _>(e, Access::Read)),
+        )
+    }
+    pub fn try_resource_mut<T: Component>(&self) -> Option<Result<ResMut<'_, T>, BorrowError>> {
+        let resource = self.resources.get(&TypeId::of::<T>())?;
+        Some(
+            write(resource)
+                .map(ResMut::new)
+                .map_err(|e| self.resource_error::<T, _>(e, Access::Write)),
+        )
+    }
+    /// Inserts a resource and registers it to be included in [`World::snapshot`].
+    pub fn insert_snapshot_resource<T: Snapshot>(&mut self, resource: T) {
+        self.insert_resource(resource);
+        self.register_snapshot_resource::<T>();
+    }
+    /// Registers the resource of type `T`, if there is one, to be included in
+    /// [`World::snapshot`].
+    pub fn register_snapshot_resource<T: Snapshot>(&mut self) {
+        self.snapshots.insert(
+            TypeId::of::<T>(),
+            SnapshotFns {
+                save: save_resource::<T>,
+                restore: restore_resource::<T>,
+            },
+        );
+    }
+    /// Registers components of type `T` to be included in [`World::snapshot`].
+    pub fn register_snapshot_component<T: Snapshot>(&mut self) {
+        self.snapshots.insert(
+            TypeId::of::<T>(),
+            SnapshotFns {
+                save: save_component::<T>,
+                restore: restore_component::<T>,
+            },
+        );
+    }
+    /// Saves the state of every registered snapshot resource and component.
+    ///
+    /// Panics if one of them is currently borrowed mutably.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let mut snapshot = WorldSnapshot::default();
+        for fns in self.snapshots.values() {
+            (fns.save)(self, &mut snapshot);
+        }
+        snapshot
+    }
+    /// Restores the state saved by [`World::snapshot`]. Resources and components that aren't in
+    /// the snapshot, or no longer in the world, are left untouched.
+    ///
+    /// Panics if one of them is currently borrowed.
+    pub fn restore(&self, snapshot: &WorldSnapshot) -> Result<(), SnapshotError> {
+        for fns in self.snapshots.values() {
+            (fns.restore)(self, snapshot)?;
+        }
+        Ok(())
     }
-    pub fn query<T: 'static + TupleUnpack>(&self) -> impl Iterator<Item = RwLockReadGuard<Entity>> {
-        self.entities.iter().filter_map(|e| {
-            let read = e.read().unwrap();
-            let types = T::unpack_types();
-            if types
-                .iter()
-                .any(|t| read.filter_typeid(*t).next().is_some())
-            {
-                Some(read)
-            } else {
-                None
+    /// Registers `T` as an event type, storing its [`Events`] queue as a resource.
+    pub fn add_event<T: Component>(&mut self) {
+        if !self.contains_resource::<Events<T>>() {
+            self.insert_resource(Events::<T>::default());
+        }
+        self.resource_mut::<Events<T>>().unwrap().current_system =
+            Some(self.current_system.clone());
+        self.event_updaters.insert(TypeId::of::<T>(), |world| {
+            if let Some(mut events) = world.resource_mut::<Events<T>>() {
+                events.update();
+                // Attached again every frame, in case the queue was replaced or merged in
+                events.current_system = Some(world.current_system.clone());
             }
-        })
+        });
+    }
+    /// Sends an event of a type registered with [`World::add_event`].
+    ///
+    /// Panics if it wasn't registered, or if its queue is currently borrowed.
+    pub fn send_event<T: Component>(&self, event: T) {
+        self.resource_mut::<Events<T>>()
+            .unwrap_or_else(|| panic!("event `{}` was not added to the world", type_name::<T>()))
+            .send(event);
+    }
+    /// Moves every entity of `other` into this world, and returns where each of them ended up.
+    ///
+    /// Components registered with [`World::register_map_entities`] in either world have their
+    /// entity ids rewritten to the new ones. This lets a level be built in its own world, for
+    /// instance on a worker thread with [`WorldLoader`](crate::ecs::loader::WorldLoader), and
+    /// then be added to the live world in one step.
+    pub fn merge(&mut self, other: World) -> EntityMap {
+        self.entity_mappers.extend(other.entity_mappers);
+        self.event_updaters.extend(other.event_updaters);
+        self.snapshots.extend(other.snapshots);
+        for (type_id, resource) in other.resources {
+            self.resources.entry(type_id).or_insert(resource);
+        }
+        let mut map = EntityMap::default();
+        let offset = self.entities.len();
+        for (i, entity) in other.entities.into_iter().enumerate() {
+            let entity = entity.into_inner().unwrap_or_else(PoisonError::into_inner);
+            map.insert(EntityId(i), self.add_entity(entity));
+        }
+        for entity in &mut self.entities[offset..] {
+            let entity = entity.get_mut().unwrap_or_else(PoisonError::into_inner);
+            for mapper in self.entity_mappers.values() {
+                mapper(entity, &map);
+            }
+        }
+        map
+    }
+    /// Makes [`World::merge`] rewrite the entity ids held by components of type `T`.
+    pub fn register_map_entities<T: MapEntities>(&mut self) {
+        self.entity_mappers
+            .insert(TypeId::of::<T>(), |entity, map| {
+                for component in entity.get_components_mut::<T>() {
+                    component.map_entities(map);
+                }
+            });
+    }
+    pub(crate) fn update_events(&self) {
+        for update in self.event_updaters.values() {
+            update(self);
+        }
+    }
+    /// Unique identifier of this world, used to tell worlds apart in cached queries.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /// Counter bumped on every structural change: adding an entity, or adding a component to an
+    /// entity that lives in this world.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+    /// Name of the system [`Game::update`](crate::ecs::game::Game::update) is currently running.
+    pub fn current_system(&self) -> Option<Cow<'static, str>> {
+        self.current_system
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+    pub(crate) fn set_current_system(&self, system: Option<Cow<'static, str>>) {
+        *self
+            .current_system
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = system;
+    }
+    pub fn get(&self, id: EntityId) -> Option<EntityRef<'_>> {
+        match self.try_get(id) {
+            Err(BorrowError::Missing { .. }) => None,
+            result => Some(expect_borrow(result)),
+        }
+    }
+    pub fn get_mut(&self, id: EntityId) -> Option<EntityMut<'_>> {
+        match self.try_get_mut(id) {
+            Err(BorrowError::Missing { .. }) => None,
+            result => Some(expect_borrow(result)),
+        }
+    }
+    pub fn try_get(&self, id: EntityId) -> Result<EntityRef<'_>, BorrowError> {
+        self.read_entity(id.0, "get")
+    }
+    pub fn try_get_mut(&self, id: EntityId) -> Result<EntityMut<'_>, BorrowError> {
+        self.write_entity(id.0, "get_mut")
+    }
+    /// Iterates over entities with at least one component of a type in `T`.
+    ///
+    /// Panics if one of them is already borrowed mutably, see [`World::try_query`].
+    pub fn query<T: 'static + TupleUnpack>(&self) -> impl Iterator<Item = EntityRef<'_>> {
+        self.try_query::<T>().map(expect_borrow)
+    }
+    /// Iterates over entities with at least one component of a type in `T`.
+    ///
+    /// Panics if one of them is already borrowed, see [`World::try_query_mut`].
+    pub fn query_mut<T: 'static + TupleUnpack>(&self) -> impl Iterator<Item = EntityMut<'_>> {
+        self.try_query_mut::<T>().map(expect_borrow)
+    }
+    pub fn try_query<T: 'static + TupleUnpack>(
+        &self,
+    ) -> impl Iterator<Item = Result<EntityRef<'_>, BorrowError>> {
+        let query = type_name::<T>();
+        self.matching(&T::unpack_types())
+            .into_iter()
+            .map(move |i| self.read_entity(i, query))
+    }
+    pub fn try_query_mut<T: 'static + TupleUnpack>(
+        &self,
+    ) -> impl Iterator<Item = Result<EntityMut<'_>, BorrowError>> {
+        let query = type_name::<T>();
+        self.matching(&T::unpack_types())
+            .into_iter()
+            .map(move |i| self.write_entity(i, query))
+    }
+    /// Indices of the entities with a component of at least one of `types`, found without
+    /// borrowing them so that queries don't conflict with borrows of entities they can't match.
+    pub(crate) fn matching(&self, types: &[TypeId]) -> Vec<usize> {
+        let added_types = self
+            .added_types
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let has = |index: usize, t: &TypeId| {
+            self.component_types[index].contains(t) || added_types.contains(&(index, *t))
+        };
+        (0..self.entities.len())
+            .filter(|&i| types.iter().any(|t| has(i, t)))
+            .collect()
+    }
+    /// Moves the types of components added through borrows into `component_types`.
+    fn sync_component_types(&mut self) {
+        let mut added_types = self
+            .added_types
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (index, t) in added_types.drain(..) {
+            self.component_types[index].push(t);
+        }
+    }
+    pub(crate) fn read_entity(
+        &self,
+        index: usize,
+        query: &'static str,
+    ) -> Result<EntityRef<'_>, BorrowError> {
+        let entity = self.entities.get(index).ok_or(BorrowError::Missing {
+            entity: EntityId(index),
+        })?;
+        read(entity)
+            .map(EntityRef::new)
+            .map_err(|e| self.borrow_error(e, index, Access::Read, query))
     }
-    pub fn query_mut<T: 'static + TupleUnpack>(
+    pub(crate) fn write_entity(
         &self,
-    ) -> impl Iterator<Item = RwLockWriteGuard<Entity>> {
-        self.entities.iter().filter_map(|e| {
-            let read = e.read().unwrap();
-            let types = T::unpack_types();
-            if types
-                .iter()
-                .any(|t| read.filter_typeid(*t).next().is_some())
-            {
-                drop(read);
-                Some(e.write().unwrap())
-            } else {
-                None
+        index: usize,
+        query: &'static str,
+    ) -> Result<EntityMut<'_>, BorrowError> {
+        let entity = self.entities.get(index).ok_or(BorrowError::Missing {
+            entity: EntityId(index),
+        })?;
+        write(entity)
+            .map(EntityMut::new)
+            .map_err(|e| self.borrow_error(e, index, Access::Write, query))
+    }
+    fn resource_error<T: Component, G>(
+        &self,
+        error: TryLockError<G>,
+        access: Access,
+    ) -> BorrowError {
+        let resource = type_name::<T>();
+        let system = self.current_system();
+        match error {
+            TryLockError::WouldBlock => BorrowError::ResourceConflict {
+                resource,
+                access,
+                system,
+            },
+            TryLockError::Poisoned(_) => BorrowError::ResourcePoisoned { resource, system },
+        }
+    }
+    fn borrow_error<G>(
+        &self,
+        error: TryLockError<G>,
+        index: usize,
+        access: Access,
+        query: &'static str,
+    ) -> BorrowError {
+        let entity = EntityId(index);
+        let system = self.current_system();
+        match error {
+            TryLockError::WouldBlock => BorrowError::Conflict {
+                entity,
+                access,
+                query,
+                system,
+            },
+            TryLockError::Poisoned(_) => BorrowError::Poisoned {
+                entity,
+                query,
+                system,
+            },
+        }
+    }
+}
+
+thread_local! {
+    /// Addresses of the locks the current thread holds a [`Borrow`] of.
+    static BORROWED: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Marks a lock as borrowed by the current thread until dropped, so that borrowing it again from
+/// the same thread is reported as a conflict instead of waited for.
+#[derive(Debug)]
+pub(crate) struct Borrow(usize);
+impl Borrow {
+    fn new<T>(lock: &RwLock<T>) -> Self {
+        let address = lock as *const RwLock<T> as usize;
+        BORROWED.with(|borrowed| borrowed.borrow_mut().push(address));
+        Self(address)
+    }
+    fn is_held<T>(lock: &RwLock<T>) -> bool {
+        let address = lock as *const RwLock<T> as usize;
+        BORROWED.with(|borrowed| borrowed.borrow().contains(&address))
+    }
+}
+impl Drop for Borrow {
+    fn drop(&mut self) {
+        // The thread local may already be destroyed if the guard is dropped as the thread exits
+        let _ = BORROWED.try_with(|borrowed| {
+            let mut borrowed = borrowed.borrow_mut();
+            if let Some(i) = borrowed.iter().rposition(|&address| address == self.0) {
+                borrowed.swap_remove(i);
             }
-        })
+        });
     }
 }
+
+/// Read-locks `lock`, waiting for other threads but failing if the current thread already
+/// borrows it, as waiting for itself would deadlock.
+pub(crate) fn read<T>(
+    lock: &RwLock<T>,
+) -> Result<(RwLockReadGuard<'_, T>, Borrow), TryLockError<RwLockReadGuard<'_, T>>> {
+    let guard = match lock.try_read() {
+        Err(TryLockError::WouldBlock) if !Borrow::is_held(lock) => lock.read()?,
+        result => result?,
+    };
+    Ok((guard, Borrow::new(lock)))
+}
+
+/// Write-locks `lock`, waiting for other threads but failing if the current thread already
+/// borrows it, as waiting for itself would deadlock.
+pub(crate) fn write<T>(
+    lock: &RwLock<T>,
+) -> Result<(RwLockWriteGuard<'_, T>, Borrow), TryLockError<RwLockWriteGuard<'_, T>>> {
+    let guard = match lock.try_write() {
+        Err(TryLockError::WouldBlock) if !Borrow::is_held(lock) => lock.write()?,
+        result => result?,
+    };
+    Ok((guard, Borrow::new(lock)))
+}
+
+fn save_resource<T: Snapshot>(world: &World, snapshot: &mut WorldSnapshot) {
+    if let Some(resource) = world.resource::<T>() {
+        let mut data = Vec::new();
+        resource.save(&mut data);
+        snapshot
+            .resources
+            .insert(type_name::<T>().to_string(), data);
+    }
+}
+
+fn restore_resource<T: Snapshot>(
+    world: &World,
+    snapshot: &WorldSnapshot,
+) -> Result<(), SnapshotError> {
+    match (
+        snapshot.resources.get(type_name::<T>()),
+        world.resource_mut::<T>(),
+    ) {
+        (Some(data), Some(mut resource)) => resource.restore(data),
+        _ => Ok(()),
+    }
+}
+
+fn save_component<T: Snapshot>(world: &World, snapshot: &mut WorldSnapshot) {
+    let mut saved = Vec::new();
+    for i in 0..world.entities.len() {
+        let entity = expect_borrow(world.read_entity(i, type_name::<T>()));
+        for component in entity.get_components::<T>() {
+            let mut data = Vec::new();
+            component.save(&mut data);
+            saved.push((EntityId(i), data));
+        }
+    }
+    snapshot
+        .components
+        .insert(type_name::<T>().to_string(), saved);
+}
+
+fn restore_component<T: Snapshot>(
+    world: &World,
+    snapshot: &WorldSnapshot,
+) -> Result<(), SnapshotError> {
+    let Some(saved) = snapshot.components.get(type_name::<T>()) else {
+        return Ok(());
+    };
+    let mut saved = saved.iter().peekable();
+    while let Some((id, _)) = saved.peek() {
+        let id = *id;
+        let mut entity = match world.write_entity(id.0, type_name::<T>()) {
+            Err(BorrowError::Missing { .. }) => {
+                saved.next();
+                continue;
+            }
+            result => expect_borrow(result),
+        };
+        for component in entity.get_components_mut::<T>() {
+            match saved.next_if(|(i, _)| *i == id) {
+                Some((_, data)) => component.restore(data)?,
+                None => break,
+            }
+        }
+        while saved.next_if(|(i, _)| *i == id).is_some() {}
+    }
+    Ok(())
+}
+
+pub(crate) fn expect_borrow<G>(result: Result<G, BorrowError>) -> G {
+    result.unwrap_or_else(|e| panic!("{e}"))
+}
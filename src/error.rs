@@ -0,0 +1,66 @@
+use crate::ecs::error::BorrowError;
+use crate::ecs::snapshot::SnapshotError;
+use crate::rendering::renderer::RenderError;
+use std::fmt::{Display, Formatter};
+use wgpu::SurfaceError;
+
+/// Errors that can stop a system, and the engine, from doing its job.
+#[derive(Debug)]
+pub enum Error {
+    Borrow(BorrowError),
+    Surface(SurfaceError),
+    Snapshot(SnapshotError),
+    Render(RenderError),
+    /// Any other error raised by a system.
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+impl Error {
+    pub fn other<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Self {
+        Error::Other(error.into())
+    }
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Borrow(e) => write!(f, "{e}"),
+            Error::Surface(e) => write!(f, "surface error: {e}"),
+            Error::Snapshot(e) => write!(f, "{e}"),
+            Error::Render(e) => write!(f, "render error: {e}"),
+            Error::Other(e) => write!(f, "{e}"),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Borrow(e) => Some(e),
+            Error::Surface(e) => Some(e),
+            Error::Snapshot(e) => Some(e),
+            Error::Render(e) => Some(e),
+            Error::Other(e) => Some(e.as_ref()),
+        }
+    }
+}
+impl From<BorrowError> for Error {
+    fn from(e: BorrowError) -> Self {
+        Error::Borrow(e)
+    }
+}
+impl From<SurfaceError> for Error {
+    fn from(e: SurfaceError) -> Self {
+        Error::Surface(e)
+    }
+}
+
+impl From<SnapshotError> for Error {
+    fn from(e: SnapshotError) -> Self {
+        Error::Snapshot(e)
+    }
+}
+impl From<RenderError> for Error {
+    fn from(e: RenderError) -> Self {
+        Error::Render(e)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,13 +1,15 @@
 use goosberry::ecs::components::Transform3;
 use goosberry::ecs::entity::Entity;
-use goosberry::ecs::game::Game;
+use goosberry::ecs::game::{Game, Stage};
+use goosberry::ecs::pacing::FrameRateCap;
+use goosberry::ecs::stats::FrameStats;
 use goosberry::ecs::world::World;
-use goosberry::rendering::camera::{Camera2d, CameraOptions};
-use goosberry::rendering::render_2d;
+use goosberry::rendering::add_render_2d;
+use goosberry::rendering::camera::{Camera2d, CameraTarget};
+use goosberry::rendering::renderer::{Renderer, RendererOptions};
+use goosberry::runner::winit::WinitRunner;
 use nalgebra::Vector2;
-use std::time::Duration;
 use wgpu::Color;
-use winit::event::{Event, WindowEvent};
 use winit::event_loop::EventLoop;
 use winit::window::{Fullscreen, WindowBuilder};
 
@@ -19,6 +21,11 @@ pub fn example_system(world: &World) {
     }
 }
 
+pub fn framerate_system(world: &World) {
+    let stats = world.resource::<FrameStats>().unwrap();
+    print!("\rAvg. Framerate: {:.1}", stats.fps());
+}
+
 #[tokio::main]
 async fn main() {
     let wb = WindowBuilder::new()
@@ -30,60 +37,37 @@ async fn main() {
     let mut entity = Entity::default();
     entity.add_component(Transform3::<f32>::default());
 
+    let (renderer, surface) = Renderer::for_window(
+        &window,
+        window.id().into(),
+        Vector2::new(window.inner_size().width, window.inner_size().height),
+        RendererOptions {
+            vsync: false,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
     let mut camera = Entity::default();
-    camera.add_component(
-        Camera2d::new(
-            &window,
-            Vector2::new(window.inner_size().width, window.inner_size().height),
-            CameraOptions {
-                vsync: false,
-                clear_color: Color::BLUE,
-            },
-        )
-        .await,
-    );
+    let mut camera_2d = Camera2d::new(CameraTarget::Window(surface));
+    camera_2d.clear_color = Some(Color::BLUE);
+    camera.add_component(camera_2d);
 
     let mut world = World::default();
     world.add_entity(entity);
     world.add_entity(camera);
+    world.insert_resource(renderer);
 
     let mut game = Game::new(world);
-    game.add_system(example_system);
-    game.add_system(render_2d);
-
-    let mut total = Duration::new(0, 0);
-    let mut frames = 0;
-
-    event_loop.run(move |e, target, control_flow| {
-        control_flow.set_poll();
-        match e {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => {
-                *control_flow = winit::event_loop::ControlFlow::Exit;
-            }
-            Event::WindowEvent {
-                event: WindowEvent::Resized(size),
-                ..
-            } => {
-                let mut entity = game.world.query_mut::<(Camera2d,)>().next().unwrap();
-                let camera = entity.get_component_mut::<Camera2d>().unwrap();
-                camera.resize(Vector2::new(size.width, size.height));
-            }
-            Event::MainEventsCleared => {
-                window.request_redraw();
-            }
-            Event::RedrawRequested(_) => {
-                game.update();
-                total += game.delta_time;
-                frames += 1;
-                print!(
-                    "\rAvg. Framerate: {}",
-                    1.0 / (total.as_secs_f32() / frames as f32)
-                );
-            }
-            _ => {}
-        }
+    game.set_frame_rate_cap(FrameRateCap {
+        focused: Some(144.0),
+        unfocused: Some(30.0),
+        ..Default::default()
     });
+    add_render_2d(&mut game);
+    game.add_system(example_system);
+    game.add_system_to_stage(Stage::PostUpdate, framerate_system);
+    game.set_runner(WinitRunner::new(event_loop, window));
+    game.run().unwrap();
 }
@@ -0,0 +1,582 @@
+use crate::ecs::event::{EventReader, Events};
+use crate::ecs::game::{Game, Stage};
+use crate::ecs::world::World;
+use crate::input::button::Input;
+use crate::input::gamepad::{GamepadAxis, GamepadButton, GamepadId, Gamepads};
+use crate::input::keyboard::KeyCode;
+use crate::input::mouse::{Cursor, MouseButton, Scroll};
+use crate::input::touch::{TouchPhase, Touches};
+use nalgebra::Vector2;
+
+pub mod action;
+pub mod button;
+pub mod gamepad;
+pub mod keyboard;
+pub mod mouse;
+pub mod replay;
+pub mod touch;
+#[cfg(feature = "winit")]
+pub mod winit;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ButtonState {
+    Pressed,
+    Released,
+}
+
+/// Raw input, from whatever backend the game runs on.
+///
+/// Send them as events into a world set up with [`add_input`], and they are applied to the
+/// input resources at the start of the next frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key {
+        key: KeyCode,
+        state: ButtonState,
+    },
+    MouseButton {
+        button: MouseButton,
+        state: ButtonState,
+    },
+    CursorMoved {
+        position: Vector2<f32>,
+    },
+    CursorLeft,
+    MouseMotion {
+        delta: Vector2<f32>,
+    },
+    Scroll {
+        delta: Vector2<f32>,
+    },
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        position: Vector2<f32>,
+    },
+    GamepadConnected {
+        gamepad: GamepadId,
+    },
+    GamepadDisconnected {
+        gamepad: GamepadId,
+    },
+    GamepadButton {
+        gamepad: GamepadId,
+        button: GamepadButton,
+        state: ButtonState,
+    },
+    GamepadAxis {
+        gamepad: GamepadId,
+        axis: GamepadAxis,
+        value: f32,
+    },
+    /// The game lost focus: every button is released.
+    FocusLost,
+}
+
+/// Sets up the input resources, `Input<KeyCode>`, `Input<MouseButton>`, [`Cursor`], [`Scroll`],
+/// [`Touches`] and [`Gamepads`], and the [`input_system`] keeping them up to date from
+/// [`InputEvent`]s.
+///
+/// The input resources are included in world snapshots, so that a
+/// [recording](crate::input::replay) started while a button is held replays with it held.
+///
+/// ## Usage
+/// ```rust
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::world::World;
+/// # use goosberry::input::button::Input;
+/// # use goosberry::input::keyboard::KeyCode;
+/// # use goosberry::input::{add_input, ButtonState, InputEvent};
+/// # use std::time::Duration;
+/// let mut game = Game::new(World::default());
+/// add_input(&mut game);
+/// game.world.send_event(InputEvent::Key {
+///     key: KeyCode::Space,
+///     state: ButtonState::Pressed,
+/// });
+/// game.step(Duration::ZERO).unwrap();
+/// let keys = game.world.resource::<Input<KeyCode>>().unwrap();
+/// assert!(keys.just_pressed(KeyCode::Space));
+/// ```
+pub fn add_input(game: &mut Game) {
+    let world = &mut game.world;
+    world.add_event::<InputEvent>();
+    world.insert_snapshot_resource(Input::<KeyCode>::default());
+    world.insert_snapshot_resource(Input::<MouseButton>::default());
+    world.insert_snapshot_resource(Cursor::default());
+    world.insert_snapshot_resource(Scroll::default());
+    world.insert_snapshot_resource(Touches::default());
+    world.insert_snapshot_resource(Gamepads::default());
+    game.add_system_to_stage(Stage::PreUpdate, input_system())
+        .with_name("goosberry::input::input_system");
+}
+
+/// Clears the per-frame input state, then applies the [`InputEvent`]s sent since the last frame.
+pub fn input_system() -> impl FnMut(&World) {
+    let mut reader = EventReader::default();
+    move |world: &World| {
+        let (Some(mut keys), Some(mut buttons), Some(mut cursor), Some(mut scroll)) = (
+            world.resource_mut::<Input<KeyCode>>(),
+            world.resource_mut::<Input<MouseButton>>(),
+            world.resource_mut::<Cursor>(),
+            world.resource_mut::<Scroll>(),
+        ) else {
+            return;
+        };
+        let (Some(mut touches), Some(mut gamepads), Some(events)) = (
+            world.resource_mut::<Touches>(),
+            world.resource_mut::<Gamepads>(),
+            world.resource::<Events<InputEvent>>(),
+        ) else {
+            return;
+        };
+        keys.clear_just();
+        buttons.clear_just();
+        touches.clear_just();
+        gamepads.buttons.clear_just();
+        cursor.delta = Vector2::zeros();
+        cursor.motion = Vector2::zeros();
+        scroll.delta = Vector2::zeros();
+        for event in reader.read(&events) {
+            match *event {
+                InputEvent::Key { key, state } => match state {
+                    ButtonState::Pressed => keys.press(key),
+                    ButtonState::Released => keys.release(key),
+                },
+                InputEvent::MouseButton { button, state } => match state {
+                    ButtonState::Pressed => buttons.press(button),
+                    ButtonState::Released => buttons.release(button),
+                },
+                InputEvent::CursorMoved { position } => {
+                    if let Some(previous) = cursor.position {
+                        cursor.delta += position - previous;
+                    }
+                    cursor.position = Some(position);
+                }
+                InputEvent::CursorLeft => cursor.position = None,
+                InputEvent::MouseMotion { delta } => cursor.motion += delta,
+                InputEvent::Scroll { delta } => scroll.delta += delta,
+                InputEvent::Touch {
+                    id,
+                    phase,
+                    position,
+                } => touches.update(id, phase, position),
+                InputEvent::GamepadConnected { gamepad } => gamepads.connect(gamepad),
+                InputEvent::GamepadDisconnected { gamepad } => gamepads.disconnect(gamepad),
+                InputEvent::GamepadButton {
+                    gamepad,
+                    button,
+                    state,
+                } => match state {
+                    ButtonState::Pressed => gamepads.buttons.press((gamepad, button)),
+                    ButtonState::Released => gamepads.buttons.release((gamepad, button)),
+                },
+                InputEvent::GamepadAxis {
+                    gamepad,
+                    axis,
+                    value,
+                } => gamepads.set_axis(gamepad, axis, value),
+                InputEvent::FocusLost => {
+                    keys.release_all();
+                    buttons.release_all();
+                    gamepads.buttons.release_all();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::event::{EventReader, Events};
+    use crate::ecs::game::Game;
+    use crate::ecs::world::World;
+    use crate::error::Error;
+    use crate::input::action::{add_actions, ActionMap, ActionMapError, ActionState, Binding};
+    use crate::input::button::Input;
+    use crate::input::gamepad::{GamepadAxis, GamepadButton, GamepadId, Gamepads};
+    use crate::input::keyboard::KeyCode;
+    use crate::input::mouse::{Cursor, MouseButton};
+    use crate::input::replay::{start_recording, InputRecorder, RecordedFrame, Recording, Replay};
+    use crate::input::touch::{TouchPhase, Touches};
+    use crate::input::{add_input, ButtonState, InputEvent};
+    use crate::random::Rng;
+    use nalgebra::Vector2;
+    use std::time::Duration;
+
+    fn frame(game: &mut Game, events: &[InputEvent]) {
+        for event in events {
+            game.world.send_event(*event);
+        }
+        game.step(Duration::ZERO).unwrap();
+    }
+
+    fn key(key: KeyCode, state: ButtonState) -> InputEvent {
+        InputEvent::Key { key, state }
+    }
+
+    #[test]
+    fn test_keyboard() {
+        let mut game = Game::new(World::default());
+        add_input(&mut game);
+        frame(&mut game, &[key(KeyCode::W, ButtonState::Pressed)]);
+        {
+            let keys = game.world.resource::<Input<KeyCode>>().unwrap();
+            assert!(keys.pressed(KeyCode::W) && keys.just_pressed(KeyCode::W));
+        }
+        // Key repeat doesn't press it again
+        frame(&mut game, &[key(KeyCode::W, ButtonState::Pressed)]);
+        {
+            let keys = game.world.resource::<Input<KeyCode>>().unwrap();
+            assert!(keys.pressed(KeyCode::W) && !keys.just_pressed(KeyCode::W));
+        }
+        frame(&mut game, &[key(KeyCode::W, ButtonState::Released)]);
+        {
+            let keys = game.world.resource::<Input<KeyCode>>().unwrap();
+            assert!(!keys.pressed(KeyCode::W) && keys.just_released(KeyCode::W));
+        }
+        frame(&mut game, &[]);
+        let keys = game.world.resource::<Input<KeyCode>>().unwrap();
+        assert!(!keys.just_released(KeyCode::W));
+    }
+
+    #[test]
+    fn test_mouse_touch_and_gamepad() {
+        let mut game = Game::new(World::default());
+        add_input(&mut game);
+        let pad = GamepadId(0);
+        frame(
+            &mut game,
+            &[
+                InputEvent::CursorMoved {
+                    position: Vector2::new(10.0, 10.0),
+                },
+                InputEvent::CursorMoved {
+                    position: Vector2::new(14.0, 7.0),
+                },
+                InputEvent::MouseButton {
+                    button: MouseButton::Left,
+                    state: ButtonState::Pressed,
+                },
+                InputEvent::Touch {
+                    id: 3,
+                    phase: TouchPhase::Started,
+                    position: Vector2::new(1.0, 2.0),
+                },
+                InputEvent::GamepadConnected { gamepad: pad },
+                InputEvent::GamepadButton {
+                    gamepad: pad,
+                    button: GamepadButton::South,
+                    state: ButtonState::Pressed,
+                },
+                InputEvent::GamepadAxis {
+                    gamepad: pad,
+                    axis: GamepadAxis::LeftStickX,
+                    value: -0.5,
+                },
+            ],
+        );
+        {
+            let cursor = game.world.resource::<Cursor>().unwrap();
+            assert_eq!(cursor.position, Some(Vector2::new(14.0, 7.0)));
+            assert_eq!(cursor.delta, Vector2::new(4.0, -3.0));
+            let touches = game.world.resource::<Touches>().unwrap();
+            assert_eq!(touches.just_started().count(), 1);
+            let gamepads = game.world.resource::<Gamepads>().unwrap();
+            assert!(gamepads.just_pressed(pad, GamepadButton::South));
+            assert_eq!(gamepads.axis(pad, GamepadAxis::LeftStickX), -0.5);
+        }
+        frame(
+            &mut game,
+            &[
+                InputEvent::FocusLost,
+                InputEvent::Touch {
+                    id: 3,
+                    phase: TouchPhase::Ended,
+                    position: Vector2::new(5.0, 2.0),
+                },
+                InputEvent::GamepadDisconnected { gamepad: pad },
+            ],
+        );
+        let buttons = game.world.resource::<Input<MouseButton>>().unwrap();
+        assert!(buttons.just_released(MouseButton::Left));
+        let cursor = game.world.resource::<Cursor>().unwrap();
+        assert_eq!(cursor.delta, Vector2::zeros());
+        let touches = game.world.resource::<Touches>().unwrap();
+        assert_eq!(touches.iter().count(), 0);
+        assert_eq!(
+            touches.just_ended().next().unwrap().start,
+            Vector2::new(1.0, 2.0)
+        );
+        let gamepads = game.world.resource::<Gamepads>().unwrap();
+        assert!(!gamepads.is_connected(pad));
+        assert_eq!(gamepads.axis(pad, GamepadAxis::LeftStickX), 0.0);
+    }
+
+    #[test]
+    fn test_action_map_config() {
+        let config = "
+            # Platformer controls, separated by spaces or tabs
+            action jump = Key(Space), Gamepad(South)
+            action\tfire = Mouse(Left), Mouse(4)
+            axis move_x = Keys(A, D), Stick(LeftStickX, 0.2)
+            axis look_y = Stick(RightStickY, 0.1, invert)
+        ";
+        let map: ActionMap = config.parse().unwrap();
+        assert_eq!(
+            map.action_bindings("fire"),
+            [
+                Binding::Mouse(MouseButton::Left),
+                Binding::Mouse(MouseButton::Other(4))
+            ]
+        );
+        assert_eq!(map.to_string().parse::<ActionMap>().unwrap(), map);
+
+        let error = "action jump = Key(Spacebar)"
+            .parse::<ActionMap>()
+            .unwrap_err();
+        assert!(matches!(error, ActionMapError::Parse { line: 1, .. }));
+        assert!("axis x = Keys(A)".parse::<ActionMap>().is_err());
+    }
+
+    #[test]
+    fn test_actions() {
+        let mut game = Game::new(World::default());
+        add_input(&mut game);
+        let map = "action jump = Key(Space)\naxis move_x = Keys(A, D), Stick(LeftStickX, 0.2)";
+        add_actions(&mut game, map.parse().unwrap());
+        let pad = GamepadId(1);
+        frame(
+            &mut game,
+            &[
+                key(KeyCode::Space, ButtonState::Pressed),
+                key(KeyCode::D, ButtonState::Pressed),
+                InputEvent::GamepadConnected { gamepad: pad },
+                InputEvent::GamepadAxis {
+                    gamepad: pad,
+                    axis: GamepadAxis::LeftStickX,
+                    value: -0.6,
+                },
+            ],
+        );
+        {
+            let actions = game.world.resource::<ActionState>().unwrap();
+            assert!(actions.just_pressed("jump"));
+            assert!((actions.axis("move_x") - 0.5).abs() < 1e-6);
+        }
+        game.world
+            .resource_mut::<ActionMap>()
+            .unwrap()
+            .rebind_action("jump", vec![Binding::Key(KeyCode::W)]);
+        frame(&mut game, &[]);
+        let actions = game.world.resource::<ActionState>().unwrap();
+        assert!(actions.just_released("jump"));
+        assert!(!actions.pressed("jump"));
+    }
+
+    /// A game that rolls a die whenever Space is pressed, or every frame when `always`.
+    fn dice_game(always: bool) -> Game {
+        let mut game = Game::new(World::default());
+        add_input(&mut game);
+        game.world.insert_snapshot_resource(Rng::new(7));
+        game.add_system(move |world: &World| {
+            let keys = world.resource::<Input<KeyCode>>().unwrap();
+            if always || keys.just_pressed(KeyCode::Space) {
+                world
+                    .resource_mut::<Rng>()
+                    .unwrap()
+                    .stream("dice")
+                    .range(1..7);
+            }
+        });
+        game
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut game = dice_game(false);
+        start_recording(&mut game, 4);
+        for i in 0..20 {
+            let state = if i % 3 == 0 {
+                ButtonState::Pressed
+            } else {
+                ButtonState::Released
+            };
+            game.world.send_event(key(KeyCode::Space, state));
+            game.world.send_event(InputEvent::CursorMoved {
+                position: Vector2::new(i as f32, 2.5),
+            });
+            game.step(Duration::from_millis(16 + i)).unwrap();
+        }
+        let recording = game.world.resource_mut::<InputRecorder>().unwrap().finish();
+        assert_eq!(recording.frames.len(), 20);
+        assert_eq!(recording.frames[3].delta, Duration::from_millis(19));
+        assert_eq!(recording.checkpoints.len(), 5);
+
+        let path = std::env::temp_dir().join("goosberry_test_replay.gbrp");
+        recording.save(&path).unwrap();
+        let loaded = Recording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, recording);
+
+        let mut replayed = dice_game(false);
+        let report = Replay::new(loaded).run(&mut replayed).unwrap();
+        assert_eq!(report.frames, 20);
+        assert_eq!(report.divergence, None);
+        assert_eq!(
+            replayed.world.snapshot().hash(),
+            game.world.snapshot().hash()
+        );
+
+        let report = Replay::new(recording).run(&mut dice_game(true)).unwrap();
+        let divergence = report.divergence.unwrap();
+        assert_eq!(divergence.frame, 4);
+        assert_ne!(divergence.expected, divergence.actual);
+        assert!(matches!(
+            Recording::from_bytes(b"nope"),
+            Err(Error::Snapshot(_))
+        ));
+
+        // Mouse buttons past `u16::MAX` are rejected rather than truncated
+        let mut recording = Recording::default();
+        let button = |button| InputEvent::MouseButton {
+            button,
+            state: ButtonState::Pressed,
+        };
+        recording.frames.push(RecordedFrame {
+            delta: Duration::ZERO,
+            events: vec![button(MouseButton::Other(u16::MAX))],
+        });
+        let mut data = recording.to_bytes();
+        let loaded = Recording::from_bytes(&data).unwrap();
+        assert_eq!(
+            loaded.frames[0].events,
+            [button(MouseButton::Other(u16::MAX))]
+        );
+        // The button's varint, u16::MAX + 3, spans three bytes before the state and checkpoints
+        let code = data.len() - 5;
+        assert_eq!(&data[code..code + 3], [0x82, 0x80, 0x04]);
+        data[code] += 1;
+        assert!(matches!(
+            Recording::from_bytes(&data),
+            Err(Error::Snapshot(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_system_events() {
+        // Scrolls when Space is pressed, and rolls a die for every scroll
+        let scroll_game = |through_resource: bool| {
+            let mut game = dice_game(false);
+            game.add_system(move |world: &World| {
+                let keys = world.resource::<Input<KeyCode>>().unwrap();
+                if keys.just_pressed(KeyCode::Space) {
+                    let scroll = InputEvent::Scroll {
+                        delta: Vector2::new(0.0, 1.0),
+                    };
+                    match through_resource {
+                        true => world
+                            .resource_mut::<Events<InputEvent>>()
+                            .unwrap()
+                            .send(scroll),
+                        false => world.send_event(scroll),
+                    }
+                }
+            });
+            let mut reader = EventReader::default();
+            game.add_system(move |world: &World| {
+                let events = world.resource::<Events<InputEvent>>().unwrap();
+                for event in reader.read(&events) {
+                    if let InputEvent::Scroll { .. } = event {
+                        world
+                            .resource_mut::<Rng>()
+                            .unwrap()
+                            .stream("scroll")
+                            .range(1..7);
+                    }
+                }
+            });
+            game
+        };
+        for through_resource in [false, true] {
+            let mut game = scroll_game(through_resource);
+            start_recording(&mut game, 1);
+            frame(&mut game, &[key(KeyCode::Space, ButtonState::Pressed)]);
+            for _ in 0..3 {
+                frame(&mut game, &[]);
+            }
+            let recording = game.world.resource_mut::<InputRecorder>().unwrap().finish();
+            let recorded: Vec<_> = recording.frames.iter().flat_map(|f| &f.events).collect();
+            assert_eq!(recorded, [&key(KeyCode::Space, ButtonState::Pressed)]);
+
+            let mut replayed = scroll_game(through_resource);
+            let report = Replay::new(recording).run(&mut replayed).unwrap();
+            assert_eq!(report.divergence, None);
+            assert_eq!(
+                replayed.world.snapshot().hash(),
+                game.world.snapshot().hash()
+            );
+        }
+    }
+
+    #[test]
+    fn test_record_mid_press() {
+        // Rolls a die every frame the roll action is held
+        let held_game = || {
+            let mut game = Game::new(World::default());
+            add_input(&mut game);
+            add_actions(&mut game, "action roll = Key(Space)".parse().unwrap());
+            game.world.insert_snapshot_resource(Rng::new(7));
+            game.add_system(|world: &World| {
+                if world.resource::<ActionState>().unwrap().pressed("roll") {
+                    world
+                        .resource_mut::<Rng>()
+                        .unwrap()
+                        .stream("dice")
+                        .range(1..7);
+                }
+            });
+            game
+        };
+        let mut game = held_game();
+        let gamepad = GamepadId(2);
+        frame(
+            &mut game,
+            &[
+                key(KeyCode::Space, ButtonState::Pressed),
+                InputEvent::CursorMoved {
+                    position: Vector2::new(3.0, 4.0),
+                },
+                InputEvent::GamepadConnected { gamepad },
+                InputEvent::GamepadAxis {
+                    gamepad,
+                    axis: GamepadAxis::LeftStickX,
+                    value: 0.5,
+                },
+            ],
+        );
+        start_recording(&mut game, 2);
+        for _ in 0..6 {
+            frame(&mut game, &[]);
+        }
+        let recording = game.world.resource_mut::<InputRecorder>().unwrap().finish();
+
+        let mut replayed = held_game();
+        let report = Replay::new(recording).run(&mut replayed).unwrap();
+        assert_eq!(report.divergence, None);
+        assert_eq!(
+            replayed.world.snapshot().hash(),
+            game.world.snapshot().hash()
+        );
+        let world = &replayed.world;
+        assert!(world
+            .resource::<Input<KeyCode>>()
+            .unwrap()
+            .pressed(KeyCode::Space));
+        assert!(world.resource::<ActionState>().unwrap().pressed("roll"));
+        let cursor = world.resource::<Cursor>().unwrap();
+        assert_eq!(cursor.position, Some(Vector2::new(3.0, 4.0)));
+        let gamepads = world.resource::<Gamepads>().unwrap();
+        assert_eq!(gamepads.axis(gamepad, GamepadAxis::LeftStickX), 0.5);
+    }
+}
@@ -0,0 +1,464 @@
+use crate::ecs::game::{Game, Stage};
+use crate::ecs::snapshot::{
+    write_f32, write_string, write_varint, Snapshot, SnapshotError, SnapshotReader,
+};
+use crate::ecs::world::World;
+use crate::input::button::Input;
+use crate::input::gamepad::{GamepadAxis, GamepadButton, Gamepads};
+use crate::input::keyboard::KeyCode;
+use crate::input::mouse::MouseButton;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+/// A physical button an action can be bound to. Gamepad bindings listen to every connected
+/// gamepad.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+/// A source of values in `-1.0..=1.0` an axis can be bound to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AxisBinding {
+    Keys {
+        negative: KeyCode,
+        positive: KeyCode,
+    },
+    GamepadButtons {
+        negative: GamepadButton,
+        positive: GamepadButton,
+    },
+    /// Values closer to zero than `dead_zone` read as zero, and the rest of the range is
+    /// rescaled so the axis still goes smoothly from zero to one.
+    Stick {
+        axis: GamepadAxis,
+        dead_zone: f32,
+        invert: bool,
+    },
+}
+
+/// Named actions and axes, and what they are bound to.
+///
+/// Gameplay systems read [`ActionState`] instead of physical keys, so the bindings can be loaded
+/// from a config file and changed at runtime by editing this resource.
+///
+/// ## Config file
+/// ```text
+/// # One binding list per line
+/// action jump = Key(Space), Gamepad(South)
+/// action fire = Mouse(Left)
+/// axis move_x = Keys(A, D), Stick(LeftStickX, 0.2)
+/// axis look_y = Stick(RightStickY, 0.1, invert)
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActionMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+impl ActionMap {
+    pub fn bind_action(&mut self, action: &str, binding: Binding) {
+        self.actions
+            .entry(action.to_string())
+            .or_default()
+            .push(binding);
+    }
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+    }
+    /// Replaces every binding of `action`.
+    pub fn rebind_action(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.actions.insert(action.to_string(), bindings);
+    }
+    /// Replaces every binding of `axis`.
+    pub fn rebind_axis(&mut self, axis: &str, bindings: Vec<AxisBinding>) {
+        self.axes.insert(axis.to_string(), bindings);
+    }
+    pub fn action_bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map_or(&[], Vec::as_slice)
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ActionMapError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ActionMapError> {
+        Ok(std::fs::write(path, self.to_string())?)
+    }
+}
+impl Display for ActionMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (action, bindings) in &self.actions {
+            write!(f, "action {action} =")?;
+            write_list(f, bindings)?;
+        }
+        for (axis, bindings) in &self.axes {
+            write!(f, "axis {axis} =")?;
+            write_list(f, bindings)?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for ActionMap {
+    type Err = ActionMapError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = ActionMap::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| ActionMapError::Parse {
+                line: i + 1,
+                message: message.to_string(),
+            };
+            let (kind, rest) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error("expected a name"))?;
+            let (name, bindings) = rest
+                .split_once('=')
+                .ok_or_else(|| error("expected `=` after the name"))?;
+            let name = name.trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(error("names can't be empty or contain spaces"));
+            }
+            let bindings = split_args(bindings).into_iter().filter(|b| !b.is_empty());
+            match kind {
+                "action" => {
+                    let bindings = bindings
+                        .map(parse_binding)
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| error(&e))?;
+                    map.rebind_action(name, bindings);
+                }
+                "axis" => {
+                    let bindings = bindings
+                        .map(parse_axis_binding)
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| error(&e))?;
+                    map.rebind_axis(name, bindings);
+                }
+                _ => return Err(error("expected `action` or `axis`")),
+            }
+        }
+        Ok(map)
+    }
+}
+
+#[derive(Debug)]
+pub enum ActionMapError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+impl Display for ActionMapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionMapError::Io(e) => write!(f, "{e}"),
+            ActionMapError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+impl std::error::Error for ActionMapError {}
+impl From<std::io::Error> for ActionMapError {
+    fn from(e: std::io::Error) -> Self {
+        ActionMapError::Io(e)
+    }
+}
+
+fn write_list<T: Display>(f: &mut Formatter<'_>, items: &[T]) -> std::fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        let separator = if i == 0 { " " } else { ", " };
+        write!(f, "{separator}{item}")?;
+    }
+    writeln!(f)
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "Key({})", key.name()),
+            Binding::Mouse(MouseButton::Other(button)) => write!(f, "Mouse({button})"),
+            Binding::Mouse(button) => write!(f, "Mouse({button:?})"),
+            Binding::Gamepad(button) => write!(f, "Gamepad({button:?})"),
+        }
+    }
+}
+impl Display for AxisBinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AxisBinding::Keys { negative, positive } => {
+                write!(f, "Keys({}, {})", negative.name(), positive.name())
+            }
+            AxisBinding::GamepadButtons { negative, positive } => {
+                write!(f, "GamepadButtons({negative:?}, {positive:?})")
+            }
+            AxisBinding::Stick {
+                axis,
+                dead_zone,
+                invert,
+            } => {
+                write!(f, "Stick({axis:?}, {dead_zone}")?;
+                if *invert {
+                    write!(f, ", invert")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Splits `a, B(c, d), e` on the commas outside of parentheses.
+fn split_args(s: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(s[start..].trim());
+    args
+}
+
+/// Splits `Name(a, b)` into `Name` and `[a, b]`.
+fn parse_call(s: &str) -> Result<(&str, Vec<&str>), String> {
+    let (name, args) = s
+        .strip_suffix(')')
+        .and_then(|s| s.split_once('('))
+        .ok_or_else(|| format!("expected `Kind(...)`, found `{s}`"))?;
+    Ok((name.trim(), split_args(args)))
+}
+
+fn parse_named<T: Copy + Debug>(all: &[T], name: &str) -> Result<T, String> {
+    all.iter()
+        .find(|item| format!("{item:?}") == name)
+        .copied()
+        .ok_or_else(|| format!("unknown button or axis `{name}`"))
+}
+
+fn parse_key(name: &str) -> Result<KeyCode, String> {
+    KeyCode::ALL
+        .iter()
+        .find(|key| key.name() == name)
+        .copied()
+        .ok_or_else(|| format!("unknown key `{name}`"))
+}
+
+fn parse_binding(s: &str) -> Result<Binding, String> {
+    match parse_call(s)? {
+        ("Key", args) if args.len() == 1 => Ok(Binding::Key(parse_key(args[0])?)),
+        ("Mouse", args) if args.len() == 1 => Ok(Binding::Mouse(match args[0] {
+            "Left" => MouseButton::Left,
+            "Right" => MouseButton::Right,
+            "Middle" => MouseButton::Middle,
+            other => MouseButton::Other(
+                other
+                    .parse()
+                    .map_err(|_| format!("unknown mouse button `{other}`"))?,
+            ),
+        })),
+        ("Gamepad", args) if args.len() == 1 => {
+            Ok(Binding::Gamepad(parse_named(GamepadButton::ALL, args[0])?))
+        }
+        _ => Err(format!("invalid action binding `{s}`")),
+    }
+}
+
+fn parse_axis_binding(s: &str) -> Result<AxisBinding, String> {
+    match parse_call(s)? {
+        ("Keys", args) if args.len() == 2 => Ok(AxisBinding::Keys {
+            negative: parse_key(args[0])?,
+            positive: parse_key(args[1])?,
+        }),
+        ("GamepadButtons", args) if args.len() == 2 => Ok(AxisBinding::GamepadButtons {
+            negative: parse_named(GamepadButton::ALL, args[0])?,
+            positive: parse_named(GamepadButton::ALL, args[1])?,
+        }),
+        ("Stick", args) if (2..=3).contains(&args.len()) => Ok(AxisBinding::Stick {
+            axis: parse_named(GamepadAxis::ALL, args[0])?,
+            dead_zone: args[1]
+                .parse()
+                .map_err(|_| format!("invalid dead zone `{}`", args[1]))?,
+            invert: match args.get(2) {
+                None => false,
+                Some(&"invert") => true,
+                Some(other) => return Err(format!("expected `invert`, found `{other}`")),
+            },
+        }),
+        _ => Err(format!("invalid axis binding `{s}`")),
+    }
+}
+
+/// State of the actions and axes of the [`ActionMap`], updated every frame by
+/// [`action_system`].
+#[derive(Clone, Debug, Default)]
+pub struct ActionState {
+    pressed: HashSet<String>,
+    just_pressed: HashSet<String>,
+    just_released: HashSet<String>,
+    axes: HashMap<String, f32>,
+}
+impl ActionState {
+    pub fn pressed(&self, action: &str) -> bool {
+        self.pressed.contains(action)
+    }
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.just_pressed.contains(action)
+    }
+    pub fn just_released(&self, action: &str) -> bool {
+        self.just_released.contains(action)
+    }
+    /// Value of the axis in `-1.0..=1.0`, zero if it isn't bound.
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.axes.get(axis).copied().unwrap_or(0.0)
+    }
+}
+impl Snapshot for ActionState {
+    fn save(&self, out: &mut Vec<u8>) {
+        for set in [&self.pressed, &self.just_pressed, &self.just_released] {
+            let mut actions: Vec<_> = set.iter().collect();
+            actions.sort();
+            write_varint(out, actions.len() as u64);
+            for action in actions {
+                write_string(out, action);
+            }
+        }
+        let mut axes: Vec<_> = self.axes.iter().collect();
+        axes.sort_by(|a, b| a.0.cmp(b.0));
+        write_varint(out, axes.len() as u64);
+        for (axis, value) in axes {
+            write_string(out, axis);
+            write_f32(out, *value);
+        }
+    }
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data);
+        let mut read_set = || -> Result<HashSet<String>, SnapshotError> {
+            (0..reader.varint()?).map(|_| reader.string()).collect()
+        };
+        self.pressed = read_set()?;
+        self.just_pressed = read_set()?;
+        self.just_released = read_set()?;
+        self.axes = (0..reader.varint()?)
+            .map(|_| Ok((reader.string()?, reader.f32()?)))
+            .collect::<Result<_, SnapshotError>>()?;
+        Ok(())
+    }
+}
+
+/// Inserts `map` and an [`ActionState`] as resources, and adds the [`action_system`]. The
+/// `ActionState` is included in world snapshots.
+///
+/// Call it after [`add_input`](crate::input::add_input), so the actions see this frame's input.
+///
+/// ## Usage
+/// ```rust
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::world::World;
+/// # use goosberry::input::action::{add_actions, ActionMap, ActionState};
+/// # use goosberry::input::add_input;
+/// let mut game = Game::new(World::default());
+/// add_input(&mut game);
+/// let map: ActionMap = "action jump = Key(Space), Gamepad(South)".parse().unwrap();
+/// add_actions(&mut game, map);
+/// game.add_system(|world: &World| {
+///     if world.resource::<ActionState>().unwrap().just_pressed("jump") {
+///         // ...
+///     }
+/// });
+/// ```
+pub fn add_actions(game: &mut Game, map: ActionMap) {
+    game.world.insert_resource(map);
+    game.world.insert_snapshot_resource(ActionState::default());
+    game.add_system_to_stage(Stage::PreUpdate, action_system);
+}
+
+pub fn action_system(world: &World) {
+    let (Some(map), Some(mut state)) = (
+        world.resource::<ActionMap>(),
+        world.resource_mut::<ActionState>(),
+    ) else {
+        return;
+    };
+    let keys = world.resource::<Input<KeyCode>>();
+    let buttons = world.resource::<Input<MouseButton>>();
+    let gamepads = world.resource::<Gamepads>();
+    let key = |key: KeyCode| keys.as_ref().is_some_and(|k| k.pressed(key));
+    let gamepad_button = |button: GamepadButton| {
+        gamepads
+            .as_ref()
+            .is_some_and(|g| g.connected().any(|id| g.pressed(id, button)))
+    };
+    let pressed: HashSet<String> = map
+        .actions
+        .iter()
+        .filter(|(_, bindings)| {
+            bindings.iter().any(|binding| match *binding {
+                Binding::Key(k) => key(k),
+                Binding::Mouse(b) => buttons.as_ref().is_some_and(|m| m.pressed(b)),
+                Binding::Gamepad(b) => gamepad_button(b),
+            })
+        })
+        .map(|(action, _)| action.clone())
+        .collect();
+    state.just_pressed = pressed.difference(&state.pressed).cloned().collect();
+    state.just_released = state.pressed.difference(&pressed).cloned().collect();
+    state.pressed = pressed;
+
+    let direction =
+        |negative: bool, positive: bool| positive as i32 as f32 - negative as i32 as f32;
+    state.axes = map
+        .axes
+        .iter()
+        .map(|(axis, bindings)| {
+            let value: f32 = bindings
+                .iter()
+                .map(|binding| match *binding {
+                    AxisBinding::Keys { negative, positive } => {
+                        direction(key(negative), key(positive))
+                    }
+                    AxisBinding::GamepadButtons { negative, positive } => {
+                        direction(gamepad_button(negative), gamepad_button(positive))
+                    }
+                    AxisBinding::Stick {
+                        axis,
+                        dead_zone,
+                        invert,
+                    } => {
+                        let value = gamepads.as_ref().map_or(0.0, |g| {
+                            g.connected()
+                                .map(|id| g.axis(id, axis))
+                                .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                                .unwrap_or(0.0)
+                        });
+                        let value = apply_dead_zone(value, dead_zone);
+                        if invert {
+                            -value
+                        } else {
+                            value
+                        }
+                    }
+                })
+                .sum();
+            (axis.clone(), value.clamp(-1.0, 1.0))
+        })
+        .collect();
+}
+
+fn apply_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    if value.abs() <= dead_zone || dead_zone >= 1.0 {
+        0.0
+    } else {
+        value.signum() * (value.abs() - dead_zone) / (1.0 - dead_zone)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::ecs::snapshot::{write_varint, Snapshot, SnapshotError, SnapshotReader};
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// Buttons, axes and gamepads that can be written into snapshots and recordings, and read back.
+pub trait InputCode: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError>;
+}
+
+/// State of a set of buttons: keys, mouse buttons, gamepad buttons...
+///
+/// `just_pressed` and `just_released` only hold for the frame the change happened in.
+#[derive(Debug, Clone)]
+pub struct Input<T: 'static + Copy + Eq + Hash + Debug + Send + Sync> {
+    pressed: HashSet<T>,
+    just_pressed: HashSet<T>,
+    just_released: HashSet<T>,
+}
+impl<T: 'static + Copy + Eq + Hash + Debug + Send + Sync> Default for Input<T> {
+    fn default() -> Self {
+        Self {
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
+    }
+}
+impl<T: 'static + Copy + Eq + Hash + Debug + Send + Sync> Input<T> {
+    /// Marks `button` as pressed. Pressing a button that is already held, as key repeat does,
+    /// doesn't make it `just_pressed` again.
+    pub fn press(&mut self, button: T) {
+        if self.pressed.insert(button) {
+            self.just_pressed.insert(button);
+        }
+    }
+    pub fn release(&mut self, button: T) {
+        if self.pressed.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+    /// Releases every pressed button, for instance when the window loses focus.
+    pub fn release_all(&mut self) {
+        self.just_released.extend(self.pressed.drain());
+    }
+    pub fn pressed(&self, button: T) -> bool {
+        self.pressed.contains(&button)
+    }
+    pub fn just_pressed(&self, button: T) -> bool {
+        self.just_pressed.contains(&button)
+    }
+    pub fn just_released(&self, button: T) -> bool {
+        self.just_released.contains(&button)
+    }
+    pub fn any_pressed<I: IntoIterator<Item = T>>(&self, buttons: I) -> bool {
+        buttons.into_iter().any(|b| self.pressed(b))
+    }
+    pub fn any_just_pressed<I: IntoIterator<Item = T>>(&self, buttons: I) -> bool {
+        buttons.into_iter().any(|b| self.just_pressed(b))
+    }
+    pub fn get_pressed(&self) -> impl Iterator<Item = T> + '_ {
+        self.pressed.iter().copied()
+    }
+    pub fn get_just_pressed(&self) -> impl Iterator<Item = T> + '_ {
+        self.just_pressed.iter().copied()
+    }
+    pub fn get_just_released(&self) -> impl Iterator<Item = T> + '_ {
+        self.just_released.iter().copied()
+    }
+    /// Forgets which buttons changed, at the start of every frame.
+    pub fn clear_just(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+impl<T> Snapshot for Input<T>
+where
+    T: 'static + Copy + Eq + Hash + Debug + Send + Sync + Ord + InputCode,
+{
+    fn save(&self, out: &mut Vec<u8>) {
+        for set in [&self.pressed, &self.just_pressed, &self.just_released] {
+            // Sorted, as the hash of a snapshot must not depend on the order of the set
+            let mut buttons: Vec<_> = set.iter().collect();
+            buttons.sort();
+            write_varint(out, buttons.len() as u64);
+            for button in buttons {
+                button.write(out);
+            }
+        }
+    }
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data);
+        let mut read_set = || -> Result<HashSet<T>, SnapshotError> {
+            (0..reader.varint()?)
+                .map(|_| T::read(&mut reader))
+                .collect()
+        };
+        self.pressed = read_set()?;
+        self.just_pressed = read_set()?;
+        self.just_released = read_set()?;
+        Ok(())
+    }
+}
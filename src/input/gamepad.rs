@@ -0,0 +1,201 @@
+use crate::ecs::snapshot::{
+    write_bytes, write_f32, write_varint, Snapshot, SnapshotError, SnapshotReader,
+};
+use crate::input::button::{Input, InputCode};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GamepadId(pub u32);
+
+/// Buttons named after their position, `South` being A on an Xbox controller and cross on a
+/// PlayStation one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+impl GamepadButton {
+    pub const ALL: &'static [GamepadButton] = &[
+        GamepadButton::South,
+        GamepadButton::East,
+        GamepadButton::North,
+        GamepadButton::West,
+        GamepadButton::LeftBumper,
+        GamepadButton::RightBumper,
+        GamepadButton::LeftTrigger,
+        GamepadButton::RightTrigger,
+        GamepadButton::Select,
+        GamepadButton::Start,
+        GamepadButton::Mode,
+        GamepadButton::LeftStick,
+        GamepadButton::RightStick,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+        GamepadButton::DPadRight,
+    ];
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+impl GamepadAxis {
+    pub const ALL: &'static [GamepadAxis] = &[
+        GamepadAxis::LeftStickX,
+        GamepadAxis::LeftStickY,
+        GamepadAxis::RightStickX,
+        GamepadAxis::RightStickY,
+        GamepadAxis::LeftTrigger,
+        GamepadAxis::RightTrigger,
+    ];
+}
+
+/// Connected gamepads and the state of their buttons and axes.
+#[derive(Clone, Debug, Default)]
+pub struct Gamepads {
+    connected: BTreeSet<GamepadId>,
+    pub buttons: Input<(GamepadId, GamepadButton)>,
+    axes: HashMap<(GamepadId, GamepadAxis), f32>,
+}
+impl Gamepads {
+    pub fn connect(&mut self, gamepad: GamepadId) {
+        self.connected.insert(gamepad);
+    }
+    /// Forgets a gamepad, releasing its buttons and centering its axes.
+    pub fn disconnect(&mut self, gamepad: GamepadId) {
+        self.connected.remove(&gamepad);
+        let held: Vec<_> = self
+            .buttons
+            .get_pressed()
+            .filter(|(g, _)| *g == gamepad)
+            .collect();
+        for button in held {
+            self.buttons.release(button);
+        }
+        self.axes.retain(|(g, _), _| *g != gamepad);
+    }
+    pub fn connected(&self) -> impl Iterator<Item = GamepadId> + '_ {
+        self.connected.iter().copied()
+    }
+    pub fn is_connected(&self, gamepad: GamepadId) -> bool {
+        self.connected.contains(&gamepad)
+    }
+    pub fn pressed(&self, gamepad: GamepadId, button: GamepadButton) -> bool {
+        self.buttons.pressed((gamepad, button))
+    }
+    pub fn just_pressed(&self, gamepad: GamepadId, button: GamepadButton) -> bool {
+        self.buttons.just_pressed((gamepad, button))
+    }
+    pub fn just_released(&self, gamepad: GamepadId, button: GamepadButton) -> bool {
+        self.buttons.just_released((gamepad, button))
+    }
+    /// Sticks go from -1.0 to 1.0, Y pointing up, and triggers from 0.0 to 1.0.
+    pub fn axis(&self, gamepad: GamepadId, axis: GamepadAxis) -> f32 {
+        self.axes.get(&(gamepad, axis)).copied().unwrap_or(0.0)
+    }
+    pub fn set_axis(&mut self, gamepad: GamepadId, axis: GamepadAxis, value: f32) {
+        self.axes.insert((gamepad, axis), value);
+    }
+}
+
+fn read_nth<T: Copy>(
+    all: &[T],
+    reader: &mut SnapshotReader<'_>,
+    what: &str,
+) -> Result<T, SnapshotError> {
+    let index = reader.varint()?;
+    all.get(index as usize)
+        .copied()
+        .ok_or_else(|| SnapshotError::new(format!("unknown gamepad {what} {index}")))
+}
+
+impl InputCode for GamepadId {
+    fn write(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.0 as u64);
+    }
+    fn read(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError> {
+        Ok(GamepadId(reader.varint()? as u32))
+    }
+}
+impl InputCode for GamepadButton {
+    fn write(&self, out: &mut Vec<u8>) {
+        write_varint(out, *self as u64);
+    }
+    fn read(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError> {
+        read_nth(GamepadButton::ALL, reader, "button")
+    }
+}
+impl InputCode for GamepadAxis {
+    fn write(&self, out: &mut Vec<u8>) {
+        write_varint(out, *self as u64);
+    }
+    fn read(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError> {
+        read_nth(GamepadAxis::ALL, reader, "axis")
+    }
+}
+impl<A: InputCode, B: InputCode> InputCode for (A, B) {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.0.write(out);
+        self.1.write(out);
+    }
+    fn read(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError> {
+        Ok((A::read(reader)?, B::read(reader)?))
+    }
+}
+
+impl Snapshot for Gamepads {
+    fn save(&self, out: &mut Vec<u8>) {
+        write_varint(out, self.connected.len() as u64);
+        for gamepad in &self.connected {
+            gamepad.write(out);
+        }
+        let mut buttons = Vec::new();
+        self.buttons.save(&mut buttons);
+        write_bytes(out, &buttons);
+        let mut axes: Vec<_> = self.axes.iter().collect();
+        axes.sort_by_key(|(key, _)| **key);
+        write_varint(out, axes.len() as u64);
+        for (key, value) in axes {
+            key.write(out);
+            write_f32(out, *value);
+        }
+    }
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data);
+        self.connected = (0..reader.varint()?)
+            .map(|_| GamepadId::read(&mut reader))
+            .collect::<Result<_, _>>()?;
+        let len = reader.varint()? as usize;
+        self.buttons.restore(reader.bytes(len)?)?;
+        self.axes = (0..reader.varint()?)
+            .map(|_| {
+                Ok((
+                    <(GamepadId, GamepadAxis)>::read(&mut reader)?,
+                    reader.f32()?,
+                ))
+            })
+            .collect::<Result<_, SnapshotError>>()?;
+        Ok(())
+    }
+}
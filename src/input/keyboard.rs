@@ -0,0 +1,134 @@
+use crate::ecs::snapshot::{write_varint, SnapshotError, SnapshotReader};
+use crate::input::button::InputCode;
+
+macro_rules! key_codes {
+    ($($key:ident),* $(,)?) => {
+        /// A key on the keyboard, identified by its position on a US layout.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum KeyCode {
+            $($key,)*
+        }
+        impl KeyCode {
+            pub const ALL: &'static [KeyCode] = &[$(KeyCode::$key,)*];
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(KeyCode::$key => stringify!($key),)*
+                }
+            }
+        }
+    };
+}
+
+key_codes!(
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    Key0,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Escape,
+    Enter,
+    Space,
+    Tab,
+    Backspace,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Left,
+    Right,
+    Up,
+    Down,
+    LShift,
+    RShift,
+    LControl,
+    RControl,
+    LAlt,
+    RAlt,
+    LSuper,
+    RSuper,
+    Minus,
+    Equals,
+    Comma,
+    Period,
+    Slash,
+    Backslash,
+    Semicolon,
+    Apostrophe,
+    LBracket,
+    RBracket,
+    Grave,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadAdd,
+    NumpadSubtract,
+    NumpadMultiply,
+    NumpadDivide,
+    NumpadDecimal,
+    NumpadEnter,
+);
+
+impl InputCode for KeyCode {
+    fn write(&self, out: &mut Vec<u8>) {
+        write_varint(out, *self as u64);
+    }
+    fn read(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError> {
+        let code = reader.varint()?;
+        KeyCode::ALL
+            .get(code as usize)
+            .copied()
+            .ok_or_else(|| SnapshotError::new(format!("unknown key {code}")))
+    }
+}
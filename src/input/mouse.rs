@@ -0,0 +1,86 @@
+use crate::ecs::snapshot::{
+    write_u8, write_varint, write_vector2, Snapshot, SnapshotError, SnapshotReader,
+};
+use crate::input::button::InputCode;
+use nalgebra::Vector2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+impl InputCode for MouseButton {
+    fn write(&self, out: &mut Vec<u8>) {
+        let code = match *self {
+            MouseButton::Left => 0,
+            MouseButton::Right => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Other(other) => 3 + other as u64,
+        };
+        write_varint(out, code);
+    }
+    fn read(reader: &mut SnapshotReader<'_>) -> Result<Self, SnapshotError> {
+        Ok(match reader.varint()? {
+            0 => MouseButton::Left,
+            1 => MouseButton::Right,
+            2 => MouseButton::Middle,
+            code => u16::try_from(code - 3)
+                .map(MouseButton::Other)
+                .map_err(|_| SnapshotError::new(format!("unknown mouse button {code}")))?,
+        })
+    }
+}
+
+/// Position of the cursor in physical pixels from the top-left corner of the window.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Cursor {
+    /// `None` while the cursor is outside the window.
+    pub position: Option<Vector2<f32>>,
+    /// How far the cursor moved this frame.
+    pub delta: Vector2<f32>,
+    /// Raw mouse motion this frame, unaffected by the cursor hitting the window's edges.
+    pub motion: Vector2<f32>,
+}
+
+/// Scrolling this frame, in lines.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Scroll {
+    pub delta: Vector2<f32>,
+}
+impl Snapshot for Cursor {
+    fn save(&self, out: &mut Vec<u8>) {
+        match self.position {
+            Some(position) => {
+                write_u8(out, 1);
+                write_vector2(out, position);
+            }
+            None => write_u8(out, 0),
+        }
+        write_vector2(out, self.delta);
+        write_vector2(out, self.motion);
+    }
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data);
+        let position = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.vector2()?),
+        };
+        *self = Cursor {
+            position,
+            delta: reader.vector2()?,
+            motion: reader.vector2()?,
+        };
+        Ok(())
+    }
+}
+impl Snapshot for Scroll {
+    fn save(&self, out: &mut Vec<u8>) {
+        write_vector2(out, self.delta);
+    }
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        self.delta = SnapshotReader::new(data).vector2()?;
+        Ok(())
+    }
+}
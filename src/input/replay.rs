@@ -0,0 +1,397 @@
+use crate::ecs::event::{EventReader, Events};
+use crate::ecs::game::{Game, Stage};
+use crate::ecs::snapshot::{
+    write_f32, write_u64, write_u8, write_varint, write_vector2, SnapshotError, SnapshotReader,
+    WorldSnapshot,
+};
+use crate::ecs::time::Time;
+use crate::ecs::world::World;
+use crate::error::{Error, Result};
+use crate::input::button::InputCode;
+use crate::input::gamepad::{GamepadAxis, GamepadButton, GamepadId};
+use crate::input::keyboard::KeyCode;
+use crate::input::mouse::MouseButton;
+use crate::input::touch::TouchPhase;
+use crate::input::{ButtonState, InputEvent};
+use std::path::Path;
+use std::time::Duration;
+
+const MAGIC: &[u8; 4] = b"GBRP";
+const VERSION: u8 = 1;
+
+/// Input events of one frame, and how long it lasted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordedFrame {
+    pub delta: Duration,
+    pub events: Vec<InputEvent>,
+}
+
+/// A recorded session: the state of the world when recording started, then every frame's input.
+///
+/// Checkpoints hold the [`WorldSnapshot::hash`] of the world at the start of some frames, to
+/// tell where a replay diverged from the original session.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    pub initial: Option<WorldSnapshot>,
+    pub frames: Vec<RecordedFrame>,
+    /// Frame index and world hash.
+    pub checkpoints: Vec<(u64, u64)>,
+}
+impl Recording {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_bytes()).map_err(Error::other)
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = std::fs::read(path).map_err(Error::other)?;
+        Self::from_bytes(&data)
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        write_u8(&mut out, VERSION);
+        match &self.initial {
+            Some(snapshot) => {
+                write_u8(&mut out, 1);
+                snapshot.save(&mut out);
+            }
+            None => write_u8(&mut out, 0),
+        }
+        write_varint(&mut out, self.frames.len() as u64);
+        for frame in &self.frames {
+            write_varint(&mut out, frame.delta.as_nanos() as u64);
+            write_varint(&mut out, frame.events.len() as u64);
+            for event in &frame.events {
+                write_event(&mut out, event);
+            }
+        }
+        write_varint(&mut out, self.checkpoints.len() as u64);
+        for (frame, hash) in &self.checkpoints {
+            write_varint(&mut out, *frame);
+            write_u64(&mut out, *hash);
+        }
+        out
+    }
+    /// Reads a recording written by [`Recording::to_bytes`], failing with an
+    /// [`Error::Snapshot`] if it is invalid.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = SnapshotReader::new(data);
+        if reader.bytes(4)? != MAGIC {
+            return Err(SnapshotError::new("not an input recording").into());
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(
+                SnapshotError::new(format!("unsupported recording version {version}")).into(),
+            );
+        }
+        let initial = match reader.u8()? {
+            0 => None,
+            _ => Some(WorldSnapshot::load(&mut reader)?),
+        };
+        let mut frames = Vec::new();
+        for _ in 0..reader.varint()? {
+            let delta = Duration::from_nanos(reader.varint()?);
+            let mut events = Vec::new();
+            for _ in 0..reader.varint()? {
+                events.push(read_event(&mut reader)?);
+            }
+            frames.push(RecordedFrame { delta, events });
+        }
+        let mut checkpoints = Vec::new();
+        for _ in 0..reader.varint()? {
+            checkpoints.push((reader.varint()?, reader.u64()?));
+        }
+        Ok(Self {
+            initial,
+            frames,
+            checkpoints,
+        })
+    }
+}
+
+/// Records the input of every frame while it is a resource of the world.
+///
+/// Add it with [`start_recording`], and take the recording back with
+/// [`InputRecorder::finish`].
+#[derive(Debug, Default)]
+pub struct InputRecorder {
+    recording: Recording,
+    checkpoint_every: u64,
+}
+impl InputRecorder {
+    /// Stops recording and returns what was recorded so far.
+    pub fn finish(&mut self) -> Recording {
+        std::mem::take(&mut self.recording)
+    }
+    pub fn frames(&self) -> usize {
+        self.recording.frames.len()
+    }
+}
+
+/// Starts recording the game's [`InputEvent`]s and frame times, with a world hash checkpoint
+/// every `checkpoint_every` frames.
+///
+/// Only resources and components registered for [snapshots](crate::ecs::snapshot) are saved
+/// and hashed, so register everything gameplay depends on. Only events sent from outside
+/// systems, like by the runner, are recorded: the ones systems send are sent again by the same
+/// systems when replaying.
+pub fn start_recording(game: &mut Game, checkpoint_every: u64) {
+    game.world.add_event::<InputEvent>();
+    game.world.insert_resource(InputRecorder {
+        recording: Recording::default(),
+        checkpoint_every: checkpoint_every.max(1),
+    });
+    // Runs first, so that the snapshots and hashes are taken before anything changes the world
+    // this frame, like they are by `Replay::run`.
+    let mut reader = EventReader::default();
+    game.add_system_to_stage_start(Stage::PreUpdate, move |world: &World| {
+        let Some(mut recorder) = world.resource_mut::<InputRecorder>() else {
+            return;
+        };
+        let events = world.resource::<Events<InputEvent>>().unwrap();
+        let events = reader
+            .read_with_sender(&events)
+            .filter(|(system, _)| system.is_none())
+            .map(|(_, event)| *event)
+            .collect();
+        let delta = world.resource::<Time>().map_or(Duration::ZERO, |t| t.delta);
+        let frame = recorder.recording.frames.len() as u64;
+        let since_checkpoint = frame % recorder.checkpoint_every;
+        if since_checkpoint == 0 {
+            let snapshot = world.snapshot();
+            recorder
+                .recording
+                .checkpoints
+                .push((frame, snapshot.hash()));
+            if recorder.recording.initial.is_none() {
+                recorder.recording.initial = Some(snapshot);
+            }
+        }
+        recorder
+            .recording
+            .frames
+            .push(RecordedFrame { delta, events });
+    })
+    .with_name("goosberry::input::replay::recorder");
+}
+
+/// Where a replay stopped matching its recording.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub frame: u64,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Number of frames replayed.
+    pub frames: u64,
+    /// First checkpoint that didn't match. The replay stops there.
+    pub divergence: Option<Divergence>,
+}
+
+/// Plays a [`Recording`] back through a game, frame by frame with [`Game::step`].
+///
+/// ## Usage
+/// ```rust
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::world::World;
+/// # use goosberry::input::add_input;
+/// # use goosberry::input::replay::{start_recording, InputRecorder, Replay};
+/// # fn setup() -> Game {
+/// #     let mut game = Game::new(World::default());
+/// #     add_input(&mut game);
+/// #     game
+/// # }
+/// let mut game = setup();
+/// start_recording(&mut game, 60);
+/// for _ in 0..10 {
+///     game.update().unwrap();
+/// }
+/// let recording = game.world.resource_mut::<InputRecorder>().unwrap().finish();
+///
+/// let mut replayed = setup();
+/// let report = Replay::new(recording).run(&mut replayed).unwrap();
+/// assert_eq!(report.divergence, None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Replay {
+    recording: Recording,
+}
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self { recording }
+    }
+    /// Restores the initial snapshot of the recording into the game's world, then steps it
+    /// through every recorded frame, checking the world hash at each checkpoint.
+    ///
+    /// The game must have been set up the same way as the recorded one, with
+    /// [`add_input`](crate::input::add_input) and the same systems.
+    pub fn run(&self, game: &mut Game) -> Result<ReplayReport> {
+        game.world.add_event::<InputEvent>();
+        if let Some(initial) = &self.recording.initial {
+            game.world.restore(initial)?;
+        }
+        let mut checkpoints = self.recording.checkpoints.iter().peekable();
+        for (frame, recorded) in self.recording.frames.iter().enumerate() {
+            let frame = frame as u64;
+            if let Some((_, expected)) = checkpoints.next_if(|(f, _)| *f == frame) {
+                let actual = game.world.snapshot().hash();
+                if actual != *expected {
+                    return Ok(ReplayReport {
+                        frames: frame,
+                        divergence: Some(Divergence {
+                            frame,
+                            expected: *expected,
+                            actual,
+                        }),
+                    });
+                }
+            }
+            for event in &recorded.events {
+                game.world.send_event(*event);
+            }
+            game.step(recorded.delta)?;
+        }
+        Ok(ReplayReport {
+            frames: self.recording.frames.len() as u64,
+            divergence: None,
+        })
+    }
+}
+
+fn write_state(out: &mut Vec<u8>, state: ButtonState) {
+    write_u8(out, (state == ButtonState::Pressed) as u8);
+}
+
+fn write_event(out: &mut Vec<u8>, event: &InputEvent) {
+    match *event {
+        InputEvent::Key { key, state } => {
+            write_u8(out, 0);
+            key.write(out);
+            write_state(out, state);
+        }
+        InputEvent::MouseButton { button, state } => {
+            write_u8(out, 1);
+            button.write(out);
+            write_state(out, state);
+        }
+        InputEvent::CursorMoved { position } => {
+            write_u8(out, 2);
+            write_vector2(out, position);
+        }
+        InputEvent::CursorLeft => write_u8(out, 3),
+        InputEvent::MouseMotion { delta } => {
+            write_u8(out, 4);
+            write_vector2(out, delta);
+        }
+        InputEvent::Scroll { delta } => {
+            write_u8(out, 5);
+            write_vector2(out, delta);
+        }
+        InputEvent::Touch {
+            id,
+            phase,
+            position,
+        } => {
+            write_u8(out, 6);
+            write_varint(out, id);
+            write_u8(
+                out,
+                match phase {
+                    TouchPhase::Started => 0,
+                    TouchPhase::Moved => 1,
+                    TouchPhase::Ended => 2,
+                    TouchPhase::Cancelled => 3,
+                },
+            );
+            write_vector2(out, position);
+        }
+        InputEvent::GamepadConnected { gamepad } => {
+            write_u8(out, 7);
+            gamepad.write(out);
+        }
+        InputEvent::GamepadDisconnected { gamepad } => {
+            write_u8(out, 8);
+            gamepad.write(out);
+        }
+        InputEvent::GamepadButton {
+            gamepad,
+            button,
+            state,
+        } => {
+            write_u8(out, 9);
+            gamepad.write(out);
+            button.write(out);
+            write_state(out, state);
+        }
+        InputEvent::GamepadAxis {
+            gamepad,
+            axis,
+            value,
+        } => {
+            write_u8(out, 10);
+            gamepad.write(out);
+            axis.write(out);
+            write_f32(out, value);
+        }
+        InputEvent::FocusLost => write_u8(out, 11),
+    }
+}
+
+fn read_event(reader: &mut SnapshotReader<'_>) -> Result<InputEvent, SnapshotError> {
+    fn state(reader: &mut SnapshotReader<'_>) -> Result<ButtonState, SnapshotError> {
+        Ok(match reader.u8()? {
+            0 => ButtonState::Released,
+            _ => ButtonState::Pressed,
+        })
+    }
+    Ok(match reader.u8()? {
+        0 => InputEvent::Key {
+            key: KeyCode::read(reader)?,
+            state: state(reader)?,
+        },
+        1 => InputEvent::MouseButton {
+            button: MouseButton::read(reader)?,
+            state: state(reader)?,
+        },
+        2 => InputEvent::CursorMoved {
+            position: reader.vector2()?,
+        },
+        3 => InputEvent::CursorLeft,
+        4 => InputEvent::MouseMotion {
+            delta: reader.vector2()?,
+        },
+        5 => InputEvent::Scroll {
+            delta: reader.vector2()?,
+        },
+        6 => InputEvent::Touch {
+            id: reader.varint()?,
+            phase: match reader.u8()? {
+                0 => TouchPhase::Started,
+                1 => TouchPhase::Moved,
+                2 => TouchPhase::Ended,
+                _ => TouchPhase::Cancelled,
+            },
+            position: reader.vector2()?,
+        },
+        7 => InputEvent::GamepadConnected {
+            gamepad: GamepadId::read(reader)?,
+        },
+        8 => InputEvent::GamepadDisconnected {
+            gamepad: GamepadId::read(reader)?,
+        },
+        9 => InputEvent::GamepadButton {
+            gamepad: GamepadId::read(reader)?,
+            button: GamepadButton::read(reader)?,
+            state: state(reader)?,
+        },
+        10 => InputEvent::GamepadAxis {
+            gamepad: GamepadId::read(reader)?,
+            axis: GamepadAxis::read(reader)?,
+            value: reader.f32()?,
+        },
+        11 => InputEvent::FocusLost,
+        tag => return Err(SnapshotError::new(format!("unknown input event {tag}"))),
+    })
+}
@@ -0,0 +1,103 @@
+use crate::ecs::snapshot::{write_varint, write_vector2, Snapshot, SnapshotError, SnapshotReader};
+use nalgebra::Vector2;
+use std::collections::BTreeMap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Touch {
+    pub id: u64,
+    pub start: Vector2<f32>,
+    pub position: Vector2<f32>,
+}
+
+/// Fingers currently on the screen, in physical pixels.
+#[derive(Clone, Debug, Default)]
+pub struct Touches {
+    active: BTreeMap<u64, Touch>,
+    just_started: Vec<Touch>,
+    just_ended: Vec<Touch>,
+}
+impl Touches {
+    pub fn get(&self, id: u64) -> Option<&Touch> {
+        self.active.get(&id)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Touch> {
+        self.active.values()
+    }
+    pub fn just_started(&self) -> impl Iterator<Item = &Touch> {
+        self.just_started.iter()
+    }
+    /// Touches that ended or were cancelled this frame.
+    pub fn just_ended(&self) -> impl Iterator<Item = &Touch> {
+        self.just_ended.iter()
+    }
+    pub fn update(&mut self, id: u64, phase: TouchPhase, position: Vector2<f32>) {
+        match phase {
+            TouchPhase::Started => {
+                let touch = Touch {
+                    id,
+                    start: position,
+                    position,
+                };
+                self.active.insert(id, touch);
+                self.just_started.push(touch);
+            }
+            TouchPhase::Moved => {
+                if let Some(touch) = self.active.get_mut(&id) {
+                    touch.position = position;
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                if let Some(mut touch) = self.active.remove(&id) {
+                    touch.position = position;
+                    self.just_ended.push(touch);
+                }
+            }
+        }
+    }
+    pub fn clear_just(&mut self) {
+        self.just_started.clear();
+        self.just_ended.clear();
+    }
+}
+impl Snapshot for Touches {
+    fn save(&self, out: &mut Vec<u8>) {
+        for touches in [
+            self.active.values().copied().collect(),
+            self.just_started.clone(),
+            self.just_ended.clone(),
+        ] {
+            write_varint(out, touches.len() as u64);
+            for touch in touches {
+                write_varint(out, touch.id);
+                write_vector2(out, touch.start);
+                write_vector2(out, touch.position);
+            }
+        }
+    }
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data);
+        let mut read_touches = || -> Result<Vec<Touch>, SnapshotError> {
+            (0..reader.varint()?)
+                .map(|_| {
+                    Ok(Touch {
+                        id: reader.varint()?,
+                        start: reader.vector2()?,
+                        position: reader.vector2()?,
+                    })
+                })
+                .collect()
+        };
+        self.active = read_touches()?.into_iter().map(|t| (t.id, t)).collect();
+        self.just_started = read_touches()?;
+        self.just_ended = read_touches()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::input::keyboard::KeyCode;
+use crate::input::mouse::MouseButton;
+use crate::input::touch::TouchPhase;
+use crate::input::{ButtonState, InputEvent};
+use nalgebra::Vector2;
+use winit::event::{DeviceEvent, ElementState, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+/// Pixels per line, to convert touchpad scrolling into the lines [`Scroll`](crate::input::mouse::Scroll) counts.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Translates a winit window event into an [`InputEvent`], if it is one.
+pub fn convert_window_event(event: &WindowEvent<'_>) -> Option<InputEvent> {
+    Some(match event {
+        WindowEvent::KeyboardInput { input, .. } => InputEvent::Key {
+            key: convert_key(input.virtual_keycode?)?,
+            state: convert_state(input.state),
+        },
+        WindowEvent::MouseInput { state, button, .. } => InputEvent::MouseButton {
+            button: match button {
+                winit::event::MouseButton::Left => MouseButton::Left,
+                winit::event::MouseButton::Right => MouseButton::Right,
+                winit::event::MouseButton::Middle => MouseButton::Middle,
+                winit::event::MouseButton::Other(other) => MouseButton::Other(*other),
+            },
+            state: convert_state(*state),
+        },
+        WindowEvent::CursorMoved { position, .. } => InputEvent::CursorMoved {
+            position: Vector2::new(position.x as f32, position.y as f32),
+        },
+        WindowEvent::CursorLeft { .. } => InputEvent::CursorLeft,
+        WindowEvent::MouseWheel { delta, .. } => InputEvent::Scroll {
+            delta: match delta {
+                MouseScrollDelta::LineDelta(x, y) => Vector2::new(*x, *y),
+                MouseScrollDelta::PixelDelta(p) => {
+                    Vector2::new(p.x as f32, p.y as f32) / PIXELS_PER_LINE
+                }
+            },
+        },
+        WindowEvent::Touch(touch) => InputEvent::Touch {
+            id: touch.id,
+            phase: match touch.phase {
+                winit::event::TouchPhase::Started => TouchPhase::Started,
+                winit::event::TouchPhase::Moved => TouchPhase::Moved,
+                winit::event::TouchPhase::Ended => TouchPhase::Ended,
+                winit::event::TouchPhase::Cancelled => TouchPhase::Cancelled,
+            },
+            position: Vector2::new(touch.location.x as f32, touch.location.y as f32),
+        },
+        WindowEvent::Focused(false) => InputEvent::FocusLost,
+        _ => return None,
+    })
+}
+
+/// Translates a winit device event into an [`InputEvent`], if it is one.
+pub fn convert_device_event(event: &DeviceEvent) -> Option<InputEvent> {
+    match event {
+        DeviceEvent::MouseMotion { delta: (x, y) } => Some(InputEvent::MouseMotion {
+            delta: Vector2::new(*x as f32, *y as f32),
+        }),
+        _ => None,
+    }
+}
+
+fn convert_state(state: ElementState) -> ButtonState {
+    match state {
+        ElementState::Pressed => ButtonState::Pressed,
+        ElementState::Released => ButtonState::Released,
+    }
+}
+
+pub fn convert_key(key: VirtualKeyCode) -> Option<KeyCode> {
+    use VirtualKeyCode as V;
+    Some(match key {
+        V::A => KeyCode::A,
+        V::B => KeyCode::B,
+        V::C => KeyCode::C,
+        V::D => KeyCode::D,
+        V::E => KeyCode::E,
+        V::F => KeyCode::F,
+        V::G => KeyCode::G,
+        V::H => KeyCode::H,
+        V::I => KeyCode::I,
+        V::J => KeyCode::J,
+        V::K => KeyCode::K,
+        V::L => KeyCode::L,
+        V::M => KeyCode::M,
+        V::N => KeyCode::N,
+        V::O => KeyCode::O,
+        V::P => KeyCode::P,
+        V::Q => KeyCode::Q,
+        V::R => KeyCode::R,
+        V::S => KeyCode::S,
+        V::T => KeyCode::T,
+        V::U => KeyCode::U,
+        V::V => KeyCode::V,
+        V::W => KeyCode::W,
+        V::X => KeyCode::X,
+        V::Y => KeyCode::Y,
+        V::Z => KeyCode::Z,
+        V::Key0 => KeyCode::Key0,
+        V::Key1 => KeyCode::Key1,
+        V::Key2 => KeyCode::Key2,
+        V::Key3 => KeyCode::Key3,
+        V::Key4 => KeyCode::Key4,
+        V::Key5 => KeyCode::Key5,
+        V::Key6 => KeyCode::Key6,
+        V::Key7 => KeyCode::Key7,
+        V::Key8 => KeyCode::Key8,
+        V::Key9 => KeyCode::Key9,
+        V::F1 => KeyCode::F1,
+        V::F2 => KeyCode::F2,
+        V::F3 => KeyCode::F3,
+        V::F4 => KeyCode::F4,
+        V::F5 => KeyCode::F5,
+        V::F6 => KeyCode::F6,
+        V::F7 => KeyCode::F7,
+        V::F8 => KeyCode::F8,
+        V::F9 => KeyCode::F9,
+        V::F10 => KeyCode::F10,
+        V::F11 => KeyCode::F11,
+        V::F12 => KeyCode::F12,
+        V::Escape => KeyCode::Escape,
+        V::Return => KeyCode::Enter,
+        V::Space => KeyCode::Space,
+        V::Tab => KeyCode::Tab,
+        V::Back => KeyCode::Backspace,
+        V::Insert => KeyCode::Insert,
+        V::Delete => KeyCode::Delete,
+        V::Home => KeyCode::Home,
+        V::End => KeyCode::End,
+        V::PageUp => KeyCode::PageUp,
+        V::PageDown => KeyCode::PageDown,
+        V::Left => KeyCode::Left,
+        V::Right => KeyCode::Right,
+        V::Up => KeyCode::Up,
+        V::Down => KeyCode::Down,
+        V::LShift => KeyCode::LShift,
+        V::RShift => KeyCode::RShift,
+        V::LControl => KeyCode::LControl,
+        V::RControl => KeyCode::RControl,
+        V::LAlt => KeyCode::LAlt,
+        V::RAlt => KeyCode::RAlt,
+        V::LWin => KeyCode::LSuper,
+        V::RWin => KeyCode::RSuper,
+        V::Minus => KeyCode::Minus,
+        V::Equals => KeyCode::Equals,
+        V::Comma => KeyCode::Comma,
+        V::Period => KeyCode::Period,
+        V::Slash => KeyCode::Slash,
+        V::Backslash => KeyCode::Backslash,
+        V::Semicolon => KeyCode::Semicolon,
+        V::Apostrophe => KeyCode::Apostrophe,
+        V::LBracket => KeyCode::LBracket,
+        V::RBracket => KeyCode::RBracket,
+        V::Grave => KeyCode::Grave,
+        V::Numpad0 => KeyCode::Numpad0,
+        V::Numpad1 => KeyCode::Numpad1,
+        V::Numpad2 => KeyCode::Numpad2,
+        V::Numpad3 => KeyCode::Numpad3,
+        V::Numpad4 => KeyCode::Numpad4,
+        V::Numpad5 => KeyCode::Numpad5,
+        V::Numpad6 => KeyCode::Numpad6,
+        V::Numpad7 => KeyCode::Numpad7,
+        V::Numpad8 => KeyCode::Numpad8,
+        V::Numpad9 => KeyCode::Numpad9,
+        V::NumpadAdd => KeyCode::NumpadAdd,
+        V::NumpadSubtract => KeyCode::NumpadSubtract,
+        V::NumpadMultiply => KeyCode::NumpadMultiply,
+        V::NumpadDivide => KeyCode::NumpadDivide,
+        V::NumpadDecimal => KeyCode::NumpadDecimal,
+        V::NumpadEnter => KeyCode::NumpadEnter,
+        _ => return None,
+    })
+}
@@ -1,5 +1,10 @@
 #![doc = include_str!("../README.md")]
 
 pub mod ecs;
+pub mod error;
+pub mod input;
+pub mod random;
 pub mod rendering;
+pub mod runner;
+pub mod window;
 pub use nalgebra;
@@ -0,0 +1,218 @@
+use crate::ecs::snapshot::{
+    fnv1a, write_string, write_u32, write_u64, Snapshot, SnapshotError, SnapshotReader,
+};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How to seed the [`Rng`] resource, read by [`Game::new`](crate::ecs::game::Game::new) from
+/// the world it is given.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RngConfig {
+    /// Fixed seed, for reproducible runs. `None` picks one from the system time, which can be
+    /// read back with [`Rng::seed`] to reproduce the run later.
+    pub seed: Option<u64>,
+}
+
+/// Deterministic random numbers, split into independent named streams.
+///
+/// Every stream is seeded from the global seed and its name only, so drawing from the `"ai"`
+/// stream never changes what the `"loot"` stream produces.
+///
+/// [`Game::new`](crate::ecs::game::Game::new) inserts it, seeded from the world's
+/// [`RngConfig`] resource if it has one, unless the world already has an `Rng`, and includes it
+/// in world snapshots either way.
+///
+/// ## Usage
+/// ```rust
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::world::World;
+/// # use goosberry::random::{Rng, RngConfig};
+/// let mut world = World::default();
+/// world.insert_resource(RngConfig { seed: Some(42) });
+/// let game = Game::new(world);
+///
+/// let mut rng = game.world.resource_mut::<Rng>().unwrap();
+/// let damage = rng.stream("loot").range(10..20);
+/// let wander = rng.stream("ai").chance(0.25);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Rng {
+    seed: u64,
+    streams: BTreeMap<String, RngStream>,
+}
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            streams: BTreeMap::new(),
+        }
+    }
+    pub fn from_config(config: RngConfig) -> Self {
+        Self::new(config.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+        }))
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// The stream called `name`, created on first use.
+    pub fn stream(&mut self, name: &str) -> &mut RngStream {
+        if !self.streams.contains_key(name) {
+            let stream = RngStream::new(self.seed ^ fnv1a(name.as_bytes()));
+            self.streams.insert(name.to_string(), stream);
+        }
+        self.streams.get_mut(name).unwrap()
+    }
+}
+impl Snapshot for Rng {
+    fn save(&self, out: &mut Vec<u8>) {
+        write_u64(out, self.seed);
+        write_u32(out, self.streams.len() as u32);
+        for (name, stream) in &self.streams {
+            write_string(out, name);
+            for word in stream.state {
+                write_u64(out, word);
+            }
+        }
+    }
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data);
+        let seed = reader.u64()?;
+        let mut streams = BTreeMap::new();
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let mut state = [0; 4];
+            for word in &mut state {
+                *word = reader.u64()?;
+            }
+            streams.insert(name, RngStream { state });
+        }
+        self.seed = seed;
+        self.streams = streams;
+        Ok(())
+    }
+}
+
+/// A single xoshiro256++ generator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RngStream {
+    state: [u64; 4],
+}
+impl RngStream {
+    pub fn new(seed: u64) -> Self {
+        let mut seed = seed;
+        let mut state = [0; 4];
+        for word in &mut state {
+            *word = split_mix(&mut seed);
+        }
+        Self { state }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.state;
+        let result = s0.wrapping_add(*s3).rotate_left(23).wrapping_add(*s0);
+        let t = *s1 << 17;
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+        result
+    }
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+    /// Uniform in `range`, which must not be empty.
+    pub fn range(&mut self, range: Range<i64>) -> i64 {
+        assert!(range.start < range.end, "empty range");
+        let span = range.end.wrapping_sub(range.start) as u64;
+        // Rejection sampling, to avoid the bias of a plain modulo
+        let zone = u64::MAX - u64::MAX % span;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return range.start.wrapping_add((value % span) as i64);
+            }
+        }
+    }
+    /// Uniform in `range`.
+    pub fn range_f32(&mut self, range: Range<f32>) -> f32 {
+        range.start + self.next_f32() * (range.end - range.start)
+    }
+    /// `true` with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            items.get(self.range(0..items.len() as i64) as usize)
+        }
+    }
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.range(0..i as i64 + 1) as usize);
+        }
+    }
+}
+
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::game::Game;
+    use crate::ecs::world::World;
+    use crate::random::{Rng, RngConfig};
+
+    #[test]
+    fn test_streams_are_independent() {
+        let mut a = Rng::new(7);
+        let mut b = Rng::new(7);
+        for _ in 0..10 {
+            a.stream("ai").next_u64();
+        }
+        let loot_a: Vec<_> = (0..8).map(|_| a.stream("loot").range(0..100)).collect();
+        let loot_b: Vec<_> = (0..8).map(|_| b.stream("loot").range(0..100)).collect();
+        assert_eq!(loot_a, loot_b);
+        assert!(loot_a.iter().all(|v| (0..100).contains(v)));
+        let mut c = Rng::new(8);
+        let loot_c: Vec<_> = (0..8).map(|_| c.stream("loot").range(0..100)).collect();
+        assert_ne!(loot_c, loot_a);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut world = World::default();
+        world.insert_resource(RngConfig { seed: Some(1) });
+        let world = Game::new(world).world;
+        assert_eq!(world.resource::<Rng>().unwrap().seed(), 1);
+        world.resource_mut::<Rng>().unwrap().stream("ai").next_u64();
+        let snapshot = world.snapshot();
+        let expected: Vec<_> = {
+            let mut rng = world.resource_mut::<Rng>().unwrap();
+            (0..4).map(|_| rng.stream("ai").next_u64()).collect()
+        };
+        world.restore(&snapshot).unwrap();
+        let mut rng = world.resource_mut::<Rng>().unwrap();
+        let replayed: Vec<_> = (0..4).map(|_| rng.stream("ai").next_u64()).collect();
+        assert_eq!(expected, replayed);
+    }
+}
@@ -1,13 +1,1354 @@
+use crate::ecs::components::Transform2;
+use crate::ecs::event::{EventReader, Events};
+use crate::ecs::game::{Game, Stage};
+use crate::ecs::time::Time;
 use crate::ecs::world::World;
+use crate::error::Result;
 use crate::rendering::camera::Camera2d;
+use crate::rendering::renderer::Renderer;
+use crate::rendering::screenshot::{take_screenshots, FrameCapture, Screenshot};
+use crate::rendering::sprite::Sprite;
+use crate::rendering::stats::RenderStats;
+use crate::window::WindowEvent;
 
 pub mod camera;
+pub mod material;
+pub mod projection;
 mod render;
+pub mod renderer;
+pub mod screenshot;
+pub mod shader;
 pub mod sprite;
+pub mod stats;
 pub mod texture;
 
-pub fn render_2d(world: &World) {
-    let mut entity = world.query_mut::<(Camera2d,)>().next().unwrap();
-    let camera = entity.get_component_mut::<Camera2d>().unwrap();
-    camera.render().expect("Rendering error");
+/// Sets the game up to render its [`Camera2d`]s every frame with the [`Renderer`] resource,
+/// resizing window surfaces along with their windows, taking [`Screenshot`]s and keeping
+/// [`RenderStats`].
+pub fn add_render_2d(game: &mut Game) {
+    game.world.add_event::<WindowEvent>();
+    game.world.add_event::<Screenshot>();
+    game.world.insert_resource(RenderStats::default());
+    game.add_system_to_stage(Stage::PreUpdate, resize_surfaces())
+        .with_name("goosberry::rendering::resize_surfaces");
+    game.add_system_to_stage_start(Stage::Render, take_screenshots())
+        .with_name("goosberry::rendering::take_screenshots");
+    game.add_system_to_stage(Stage::Render, render_2d);
+}
+
+/// Resizes the [`Renderer`]'s surface of each window on its [`WindowEvent::Resized`] and
+/// [`WindowEvent::ScaleFactorChanged`] events.
+pub fn resize_surfaces() -> impl FnMut(&World) -> Result<()> {
+    let mut reader = EventReader::default();
+    move |world: &World| {
+        let Some(events) = world.resource::<Events<WindowEvent>>() else {
+            return Ok(());
+        };
+        let Some(mut renderer) = world.resource_mut::<Renderer>() else {
+            return Ok(());
+        };
+        for event in reader.read(&events) {
+            match *event {
+                WindowEvent::Resized { window, size } => {
+                    if let Some(surface) = renderer.surface(window) {
+                        renderer.resize(surface, size);
+                    }
+                }
+                WindowEvent::ScaleFactorChanged {
+                    window,
+                    scale_factor,
+                    size,
+                } => {
+                    if let Some(surface) = renderer.surface(window) {
+                        renderer.resize(surface, size);
+                        renderer.set_scale_factor(surface, scale_factor);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders the [`Sprite`]s with every [`Camera2d`], if there is a [`Renderer`], capturing the
+/// frames of those with a [`FrameCapture`].
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn render_2d(world: &World) -> Result<()> {
+    let Some(mut renderer) = world.resource_mut::<Renderer>() else {
+        return Ok(());
+    };
+    if let Some(time) = world.resource::<Time>() {
+        renderer.time = time.elapsed.as_secs_f32();
+    }
+    let sprites = world
+        .try_query::<(Sprite,)>()
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut entities = world
+        .try_query_mut::<(Camera2d,)>()
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut cameras = Vec::with_capacity(entities.len());
+    for entity in &mut entities {
+        let view = entity
+            .get_component::<Transform2<f32>>()
+            .copied()
+            .unwrap_or_default();
+        let capture = entity
+            .get_component_mut::<FrameCapture>()
+            .map(FrameCapture::next_path);
+        if let Some(camera) = entity.get_component_mut::<Camera2d>() {
+            camera.view = view;
+            if let Some(path) = capture {
+                camera.screenshot(path);
+            }
+            cameras.push(camera);
+        }
+    }
+    let stats = renderer.render(&mut cameras, &sprites)?;
+    if let Some(mut total) = world.resource_mut::<RenderStats>() {
+        *total = stats;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::components::Transform2;
+    use crate::ecs::entity::Entity;
+    use crate::ecs::event::Events;
+    use crate::ecs::game::Game;
+    use crate::ecs::world::World;
+    use crate::error::Error;
+    use crate::rendering::camera::{Camera2d, CameraTarget, RenderLayers};
+    use crate::rendering::material::{
+        AdditiveMaterial, Material, MaterialCache, MaterialLayout, OutlineMaterial, ShaderMaterial,
+        TintedMaterial, UnlitMaterial, SPRITE_WGSL,
+    };
+    use crate::rendering::projection::{Projection, ScalingMode};
+    use crate::rendering::renderer::{RenderError, Renderer, RendererOptions};
+    use crate::rendering::screenshot::{take_screenshots, FrameCapture, FrameSaver, Screenshot};
+    use crate::rendering::shader::{PipelineCache, ShaderLibrary, ShaderLocation};
+    use crate::rendering::sprite::{Sprite, UvRect};
+    use crate::rendering::stats::RenderStats;
+    use crate::rendering::texture::{TextureCache, TextureHandle, Tilemap};
+    use crate::rendering::two_d::render::{
+        self, CameraUniform, RenderObject, RenderTarget, SpriteBuffers,
+    };
+    use crate::rendering::{add_render_2d, render_2d};
+    use crate::window::{WindowEvent, WindowId};
+    use image::{ImageBuffer, Rgba, RgbaImage};
+    use nalgebra::{Point2, Vector2};
+    use std::time::Duration;
+    use wgpu::{BindGroupLayout, Color, Device, Queue, TextureFormat};
+
+    const SIZE: u32 = 64;
+    const EXTENT: wgpu::Extent3d = wgpu::Extent3d {
+        width: SIZE,
+        height: SIZE,
+        depth_or_array_layers: 1,
+    };
+
+    /// The rendering tests run on the software adapter: lavapipe (Mesa's llvmpipe Vulkan driver)
+    /// on Linux, WARP on Windows. Machines without one fail them, unless `GOOSBERRY_SKIP_GPU_TESTS`
+    /// is set to skip them.
+    const NO_ADAPTER: &str = "no fallback GPU adapter to run rendering tests with";
+    const SKIP_GPU_TESTS: &str = "GOOSBERRY_SKIP_GPU_TESTS";
+
+    /// Fails the test that found no adapter, unless the rendering tests are explicitly skipped.
+    fn no_adapter() {
+        assert!(
+            std::env::var_os(SKIP_GPU_TESTS).is_some(),
+            "{NO_ADAPTER}, set {SKIP_GPU_TESTS} to skip the rendering tests"
+        );
+        eprintln!("{NO_ADAPTER}, skipping");
+    }
+
+    /// Renders worlds into a texture, through the fallback adapter.
+    struct Headless {
+        device: Device,
+        queue: Queue,
+        textures: TextureCache,
+        materials: MaterialCache,
+        pipelines: PipelineCache,
+        camera_layout: BindGroupLayout,
+        camera: CameraUniform,
+        buffers: SpriteBuffers,
+        projection: Projection,
+        view: Transform2<f32>,
+        clear_color: Option<Color>,
+        viewport: (Vector2<u32>, Vector2<u32>),
+        y_sort: bool,
+        keep_order: bool,
+        target: wgpu::Texture,
+        stats: RenderStats,
+    }
+    impl Headless {
+        /// `None` if there is no fallback adapter and the rendering tests are skipped.
+        async fn new() -> Option<Self> {
+            let instance = wgpu::Instance::new(wgpu::Backends::all());
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::LowPower,
+                    force_fallback_adapter: true,
+                    compatible_surface: None,
+                })
+                .await;
+            let Some(adapter) = adapter else {
+                no_adapter();
+                return None;
+            };
+            let (device, queue) = adapter
+                .request_device(
+                    &wgpu::DeviceDescriptor {
+                        label: None,
+                        features: wgpu::Features::empty(),
+                        limits: wgpu::Limits::downlevel_webgl2_defaults(),
+                    },
+                    None,
+                )
+                .await
+                .unwrap();
+            let textures = TextureCache::new(&device);
+            let camera_layout = CameraUniform::create_layout(&device);
+            let camera = CameraUniform::new(&device, &camera_layout);
+            let buffers = SpriteBuffers::new(&device);
+            let target = device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: EXTENT,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            });
+            Some(Self {
+                device,
+                queue,
+                textures,
+                materials: MaterialCache::new(),
+                pipelines: PipelineCache::new(),
+                camera_layout,
+                camera,
+                buffers,
+                projection: Projection::default(),
+                view: Transform2::default(),
+                clear_color: Some(Color::BLUE),
+                viewport: (Vector2::zeros(), Vector2::new(SIZE, SIZE)),
+                y_sort: false,
+                keep_order: false,
+                target,
+                stats: RenderStats::default(),
+            })
+        }
+        /// Renders the sprites of `world`, on a blue background by default, and reads the
+        /// pixels back.
+        fn render(&mut self, world: &World) -> Vec<[u8; 4]> {
+            self.draw(world);
+            self.read()
+        }
+        fn draw(&mut self, world: &World) {
+            let view = self.target.create_view(&Default::default());
+            let entities: Vec<_> = world.query::<(Sprite,)>().collect();
+            if self.viewport != (Vector2::zeros(), Vector2::new(SIZE, SIZE)) {
+                render::create_clear_pipeline(
+                    &self.device,
+                    TextureFormat::Rgba8Unorm,
+                    &mut self.pipelines,
+                );
+            }
+            let objects = RenderObject::from_entities(
+                &entities,
+                &self.device,
+                &self.queue,
+                TextureFormat::Rgba8Unorm,
+                &mut self.textures,
+                &mut self.materials,
+                &self.camera_layout,
+                &mut self.pipelines,
+            );
+            self.stats = render::draw(
+                &self.device,
+                &self.queue,
+                &self.textures,
+                &self.materials,
+                &self.pipelines,
+                &mut self.buffers,
+                RenderTarget {
+                    view: &view,
+                    format: TextureFormat::Rgba8Unorm,
+                    size: Vector2::new(SIZE, SIZE),
+                    clear_color: self.clear_color,
+                    viewport: self.viewport,
+                    camera: &self.camera,
+                    view_projection: self.projection.view_projection(&self.view, self.viewport.1),
+                    y_sort: self.y_sort,
+                    keep_order: self.keep_order,
+                    time: 0.0,
+                },
+                &objects.iter().collect::<Vec<_>>(),
+            );
+            self.textures.end_frame();
+            self.materials.end_frame();
+        }
+        fn read(&self) -> Vec<[u8; 4]> {
+            let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: (SIZE * SIZE * 4) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            encoder.copy_texture_to_buffer(
+                self.target.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(SIZE * 4),
+                        rows_per_image: None,
+                    },
+                },
+                EXTENT,
+            );
+            self.queue.submit(Some(encoder.finish()));
+            buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, |r| r.unwrap());
+            self.device.poll(wgpu::Maintain::Wait);
+            let pixels = buffer.slice(..).get_mapped_range();
+            pixels.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect()
+        }
+    }
+
+    /// Pixel `x` pixels right and `y` up from the center.
+    fn pixel(pixels: &[[u8; 4]], x: i32, y: i32) -> [u8; 4] {
+        let half = SIZE as i32 / 2;
+        pixels[((half - y) * SIZE as i32 + half + x) as usize]
+    }
+
+    fn sprite(color: [f32; 4], width: u32, height: u32) -> Sprite {
+        Sprite::new(ImageBuffer::from_pixel(width, height, Rgba(color)))
+    }
+
+    #[tokio::test]
+    async fn test_render_sprites() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        let mut left = Entity::default();
+        left.add_component(sprite([1.0, 0.0, 0.0, 1.0], 8, 8));
+        left.add_component(Transform2::<f32>::from_position(Vector2::new(-16.0, 0.0)));
+        world.add_entity(left);
+        // Twice as wide, and above
+        let mut right = Entity::default();
+        right.add_component(sprite([0.0, 1.0, 0.0, 1.0], 8, 8));
+        right.add_component(Transform2::<f32> {
+            position: Vector2::new(16.0, 16.0),
+            rotation: 0.0,
+            scale: Vector2::new(2.0, 1.0),
+        });
+        world.add_entity(right);
+
+        let pixels = headless.render(&world);
+        assert_eq!(headless.pipelines.len(), 1);
+        assert_eq!(pixel(&pixels, -16, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, -16 + 3, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, -16 + 5, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 16, 16), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 16 + 7, 16), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 16 + 9, 16), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 16, 16 - 5), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, -32, 32), [0, 0, 255, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_texture_cache() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        let gray = RgbaImage::from_pixel(4, 4, Rgba([188, 188, 188, 255]));
+        let mut entity = Entity::default();
+        entity.add_component(Sprite::new(gray.clone()));
+        let sprite = world.add_entity(entity);
+        // Two more sprites, sharing a single texture
+        let handle = TextureHandle::new();
+        let mut shared = Vec::new();
+        for x in [-16.0, 16.0] {
+            let mut entity = Entity::default();
+            entity.add_component(Sprite::with_handle(handle, gray.clone()));
+            entity.add_component(Transform2::<f32>::from_position(Vector2::new(x, 0.0)));
+            shared.push(world.add_entity(entity));
+        }
+
+        let pixels = headless.render(&world);
+        assert_eq!(headless.textures.uploads(), 2);
+        assert_eq!(headless.textures.len(), 2);
+        // sRGB 188 is about half as bright in linear
+        let [r, g, b, a] = pixel(&pixels, 0, 0);
+        assert!(r.abs_diff(128) <= 2 && r == g && r == b && a == 255);
+        assert_eq!(pixel(&pixels, -16, 0), pixel(&pixels, 0, 0));
+
+        headless.render(&world);
+        assert_eq!(headless.textures.uploads(), 2);
+
+        {
+            let mut entity = world.get_mut(sprite).unwrap();
+            let sprite = entity.get_component_mut::<Sprite>().unwrap();
+            sprite.set_texture(RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])));
+        }
+        let pixels = headless.render(&world);
+        assert_eq!(headless.textures.uploads(), 3);
+        assert_eq!(pixel(&pixels, 0, 0), [255, 0, 0, 255]);
+
+        // Marking one of the sprites sharing a texture dirty changes it for both, once
+        world
+            .get_mut(shared[1])
+            .unwrap()
+            .get_component_mut::<Sprite>()
+            .unwrap()
+            .set_texture(RgbaImage::from_pixel(4, 4, Rgba([0, 255, 0, 255])));
+        for _ in 0..4 {
+            let pixels = headless.render(&world);
+            assert_eq!(pixel(&pixels, -16, 0), [0, 255, 0, 255]);
+            assert_eq!(pixel(&pixels, 16, 0), [0, 255, 0, 255]);
+        }
+        assert_eq!(headless.textures.uploads(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_batching() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        // Left half red, right half green
+        let atlas = RgbaImage::from_fn(4, 2, |x, _| match x {
+            0 | 1 => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 255, 0, 255]),
+        });
+        let (atlas_handle, white_handle) = (TextureHandle::new(), TextureHandle::new());
+        let white = RgbaImage::from_pixel(2, 2, Rgba([255, 255, 255, 255]));
+        for i in 0..40 {
+            let mut entity = Entity::default();
+            let mut sprite = match i % 2 {
+                0 => Sprite::with_handle(atlas_handle, atlas.clone()),
+                _ => Sprite::with_handle(white_handle, white.clone()),
+            };
+            if i == 0 {
+                sprite.region =
+                    UvRect::from_pixels(Vector2::new(2, 0), Vector2::new(2, 2), Vector2::new(4, 2));
+            } else if i == 1 {
+                sprite.tint = Color {
+                    r: 1.0,
+                    g: 0.0,
+                    b: 1.0,
+                    a: 1.0,
+                };
+            }
+            entity.add_component(sprite);
+            entity.add_component(Transform2::<f32> {
+                position: match i {
+                    0 => Vector2::new(-16.0, 16.0),
+                    1 => Vector2::new(16.0, 16.0),
+                    _ => Vector2::new(i as f32 - 20.0, -16.0),
+                },
+                rotation: 0.0,
+                scale: Vector2::repeat(if i < 2 { 4.0 } else { 1.0 }),
+            });
+            world.add_entity(entity);
+        }
+        let pixels = headless.render(&world);
+        assert_eq!(
+            headless.stats,
+            RenderStats {
+                sprites: 40,
+                batches: 2,
+                draw_calls: 2,
+                texture_uploads: 0,
+            }
+        );
+        // A region as large as its pixels, here 2x2 scaled 4 times
+        assert_eq!(pixel(&pixels, -16, 16), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, -16 - 3, 16 + 3), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, -16 - 5, 16), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 16 + 3, 16 - 3), [255, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 0, -16), [255, 255, 255, 255]);
+
+        // The instance buffer is kept, and only grows when there are more sprites than fit
+        assert_eq!(headless.buffers.capacity(), 64);
+        for _ in 0..60 {
+            let mut entity = Entity::default();
+            entity.add_component(Sprite::with_handle(white_handle, white.clone()));
+            entity.add_component(Transform2::<f32>::from_position(Vector2::new(1000.0, 0.0)));
+            world.add_entity(entity);
+        }
+        let pixels = headless.render(&world);
+        assert_eq!(headless.stats.sprites, 100);
+        assert_eq!(headless.buffers.capacity(), 128);
+        assert_eq!(pixel(&pixels, -16, 16), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 0, -16), [255, 255, 255, 255]);
+
+        // Kept in order, the sprites take a batch every time the texture changes
+        headless.keep_order = true;
+        headless.render(&world);
+        assert_eq!(headless.stats.batches, 40);
+    }
+
+    #[test]
+    fn test_projection() {
+        let size = Vector2::new(800, 600);
+        let mut projection = Projection::default();
+        assert_eq!(projection.area(size), Vector2::new(800.0, 600.0));
+        projection.zoom = 2.0;
+        assert_eq!(projection.area(size), Vector2::new(400.0, 300.0));
+        projection.zoom = 1.0;
+        projection.scaling = ScalingMode::FixedWidth(16.0);
+        assert_eq!(projection.area(size), Vector2::new(16.0, 12.0));
+        projection.scaling = ScalingMode::FixedHeight(9.0);
+        assert_eq!(projection.area(size), Vector2::new(12.0, 9.0));
+        // 320x180 fits twice in 800x600 (but not three times), showing more than asked for
+        projection.scaling = ScalingMode::PixelPerfect {
+            width: 320,
+            height: 180,
+        };
+        assert_eq!(projection.area(size), Vector2::new(400.0, 300.0));
+        projection.scaling = ScalingMode::PixelPerfect {
+            width: 1920,
+            height: 1080,
+        };
+        assert_eq!(projection.area(size), Vector2::new(800.0, 600.0));
+
+        let projection = Projection {
+            scaling: ScalingMode::WindowSize,
+            zoom: 2.0,
+        };
+        let view = Transform2 {
+            position: Vector2::new(100.0, 50.0),
+            rotation: std::f32::consts::FRAC_PI_2,
+            scale: Vector2::repeat(1.0),
+        };
+        let center = projection.world_to_screen(&view, size, &Point2::new(100.0, 50.0));
+        assert!((center - Point2::new(400.0, 300.0)).norm() < 1e-3);
+        // Turned a quarter counter-clockwise, the camera sees the world's X axis going down
+        let right = projection.world_to_screen(&view, size, &Point2::new(110.0, 50.0));
+        assert!((right - Point2::new(400.0, 320.0)).norm() < 1e-3);
+        let screen = Point2::new(123.0, 456.0);
+        let world = projection.screen_to_world(&view, size, &screen);
+        assert!((projection.world_to_screen(&view, size, &world) - screen).norm() < 1e-3);
+
+        // Stretched and turned, the camera still sees its own axes as the screen's
+        let view = Transform2 {
+            position: Vector2::new(100.0, 50.0),
+            rotation: 0.5,
+            scale: Vector2::new(2.0, 1.0),
+        };
+        let corner = view.transform_point(&Point2::new(10.0, 10.0));
+        let corner = projection.world_to_screen(&view, size, &corner);
+        assert!(
+            (corner - Point2::new(420.0, 280.0)).norm() < 1e-3,
+            "{corner}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_camera_view() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        let mut entity = Entity::default();
+        entity.add_component(sprite([1.0, 0.0, 0.0, 1.0], 8, 8));
+        entity.add_component(Transform2::<f32>::from_position(Vector2::new(100.0, 0.0)));
+        world.add_entity(entity);
+        headless.view = Transform2::from_position(Vector2::new(100.0, 8.0));
+        headless.projection.zoom = 2.0;
+        let pixels = headless.render(&world);
+        // 16 pixels large, 16 pixels below the center
+        assert_eq!(pixel(&pixels, 0, -16), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 7, -16 - 7), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 9, -16), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 0, 0), [0, 0, 255, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_empty_sprites() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        let mut entity = Entity::default();
+        entity.add_component(sprite([1.0, 0.0, 0.0, 1.0], 0, 0));
+        world.add_entity(entity);
+        let mut entity = Entity::default();
+        entity.add_component(Sprite::new(Tilemap::new(Vector2::new(8, 8))));
+        world.add_entity(entity);
+        let mut entity = Entity::default();
+        entity.add_component(sprite([0.0, 1.0, 0.0, 1.0], 8, 0));
+        world.add_entity(entity);
+        let pixels = headless.render(&world);
+        assert_eq!(pixel(&pixels, 0, 0), [0, 0, 255, 255]);
+        assert_eq!(headless.stats.draw_calls, 0);
+    }
+
+    #[tokio::test]
+    async fn test_split_screen() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        let mut entity = Entity::default();
+        entity.add_component(sprite([1.0, 0.0, 0.0, 1.0], 8, 8));
+        world.add_entity(entity);
+        let half = Vector2::new(SIZE / 2, SIZE);
+        headless.viewport = (Vector2::zeros(), half);
+        headless.draw(&world);
+        // The right half looks further right, and clears only its own viewport
+        headless.viewport = (Vector2::new(SIZE / 2, 0), half);
+        headless.view = Transform2::from_position(Vector2::new(16.0, 0.0));
+        headless.draw(&world);
+        let pixels = headless.read();
+        // Centered in the left half, 16 pixels left of the center
+        assert_eq!(pixel(&pixels, -16, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, -16 + 3, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, -16 + 5, 0), [0, 0, 255, 255]);
+        // On the left edge of the right half, cut by its viewport
+        assert_eq!(pixel(&pixels, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 3, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, -1, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 5, 0), [0, 0, 255, 255]);
+    }
+
+    /// `None` if there is no fallback adapter and the rendering tests are skipped.
+    async fn renderer() -> Option<Renderer> {
+        let options = RendererOptions {
+            power_preference: wgpu::PowerPreference::LowPower,
+            force_fallback_adapter: true,
+            ..Default::default()
+        };
+        match Renderer::new(options).await {
+            Ok(renderer) => Some(renderer),
+            Err(Error::Render(RenderError::NoAdapter)) => {
+                no_adapter();
+                None
+            }
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_window_events() {
+        let Some(mut renderer) = renderer().await else {
+            return;
+        };
+        let (first, second) = (WindowId(1), WindowId(2));
+        let window = renderer.add_test_window(first, Vector2::new(64, 48));
+        let other = renderer.add_test_window(second, Vector2::new(32, 32));
+        let mut world = World::default();
+        world.insert_resource(renderer);
+        let mut camera = Camera2d::new(CameraTarget::Window(window));
+        camera.viewport.size.x = 0.5;
+        let mut entity = Entity::default();
+        entity.add_component(camera);
+        world.add_entity(entity);
+        let mut game = Game::new(world);
+        add_render_2d(&mut game);
+        let frame = Duration::from_secs_f64(1.0 / 60.0);
+        let camera_size = |game: &Game| {
+            let camera = game.world.query::<(Camera2d,)>().next().unwrap();
+            camera.get_component::<Camera2d>().unwrap().size()
+        };
+
+        game.world.send_event(WindowEvent::Resized {
+            window: first,
+            size: Vector2::new(100, 80),
+        });
+        game.step(frame).unwrap();
+        let renderer = game.world.resource::<Renderer>().unwrap();
+        assert_eq!(renderer.window_size(window), Some(Vector2::new(100, 80)));
+        assert_eq!(renderer.scale_factor(window), Some(1.0));
+        assert_eq!(renderer.window_size(other), Some(Vector2::new(32, 32)));
+        drop(renderer);
+        assert_eq!(camera_size(&game), Vector2::new(50, 80));
+
+        game.world.send_event(WindowEvent::ScaleFactorChanged {
+            window: first,
+            scale_factor: 2.0,
+            size: Vector2::new(200, 160),
+        });
+        game.step(frame).unwrap();
+        let renderer = game.world.resource::<Renderer>().unwrap();
+        assert_eq!(renderer.window_size(window), Some(Vector2::new(200, 160)));
+        assert_eq!(renderer.scale_factor(window), Some(2.0));
+        assert_eq!(renderer.scale_factor(other), Some(1.0));
+        drop(renderer);
+        assert_eq!(camera_size(&game), Vector2::new(100, 160));
+
+        // Events of the other window only reach its own surface
+        game.world.send_event(WindowEvent::ScaleFactorChanged {
+            window: second,
+            scale_factor: 1.5,
+            size: Vector2::new(48, 48),
+        });
+        game.step(frame).unwrap();
+        let renderer = game.world.resource::<Renderer>().unwrap();
+        assert_eq!(renderer.window_size(other), Some(Vector2::new(48, 48)));
+        assert_eq!(renderer.scale_factor(other), Some(1.5));
+        assert_eq!(renderer.window_size(window), Some(Vector2::new(200, 160)));
+        assert_eq!(renderer.scale_factor(window), Some(2.0));
+        drop(renderer);
+        assert_eq!(camera_size(&game), Vector2::new(100, 160));
+
+        // Minimizing sends a zero size, which surfaces can't be configured with
+        game.world.send_event(WindowEvent::Resized {
+            window: first,
+            size: Vector2::zeros(),
+        });
+        game.step(frame).unwrap();
+        let renderer = game.world.resource::<Renderer>().unwrap();
+        assert_eq!(renderer.window_size(window), Some(Vector2::new(200, 160)));
+        drop(renderer);
+
+        // A camera whose window is gone is skipped, without failing the frame
+        let mut renderer = game.world.resource_mut::<Renderer>().unwrap();
+        renderer.remove_window(other);
+        let mut gone = Camera2d::new(CameraTarget::Window(other));
+        let mut camera = Camera2d::new(CameraTarget::Window(window));
+        renderer.render(&mut [&mut gone, &mut camera], &[]).unwrap();
+        assert_eq!(camera.size(), Vector2::new(200, 160));
+    }
+
+    #[tokio::test]
+    async fn test_render_to_texture() {
+        let Some(mut renderer) = renderer().await else {
+            return;
+        };
+        let minimap = renderer.create_render_texture(Vector2::new(32, 32));
+        let output = renderer.create_render_texture(Vector2::new(SIZE, SIZE));
+        let mut world = World::default();
+        world.insert_resource(renderer);
+        let mut entity = Entity::default();
+        entity.add_component(sprite([0.0, 1.0, 0.0, 1.0], 8, 8));
+        entity.add_component(Transform2::<f32>::from_position(Vector2::new(-16.0, -16.0)));
+        world.add_entity(entity);
+        // The green sprite centered on red, drawn at the top right of the output
+        let mut entity = Entity::default();
+        entity.add_component(Sprite::with_handle(minimap.handle, minimap));
+        entity.add_component(Transform2::<f32>::from_position(Vector2::new(16.0, 16.0)));
+        world.add_entity(entity);
+        let mut camera = Entity::default();
+        let mut camera_2d = Camera2d::new(CameraTarget::Texture(minimap.handle));
+        camera_2d.clear_color = Some(Color::RED);
+        camera.add_component(camera_2d);
+        camera.add_component(Transform2::<f32>::from_position(Vector2::new(-16.0, -16.0)));
+        world.add_entity(camera);
+        let mut camera = Entity::default();
+        let mut camera_2d = Camera2d::new(CameraTarget::Texture(output.handle));
+        camera_2d.clear_color = Some(Color::BLUE);
+        camera_2d.order = 1;
+        camera.add_component(camera_2d);
+        world.add_entity(camera);
+
+        render_2d(&world).unwrap();
+        let renderer = world.resource::<Renderer>().unwrap();
+        let image = renderer.read_texture(minimap.handle).await.unwrap();
+        assert_eq!(image.dimensions(), (32, 32));
+        assert_eq!(image.get_pixel(16, 16), &Rgba([0, 255, 0, 255]));
+        assert_eq!(image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        let image = renderer.read_texture(output.handle).await.unwrap();
+        let pixels: Vec<_> = image.pixels().map(|p| p.0).collect();
+        assert_eq!(pixel(&pixels, 16, 16), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 16 + 8, 16), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, -16, -16), [0, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, -16, 16), [0, 0, 255, 255]);
+        assert!(matches!(
+            renderer.read_texture(TextureHandle::new()).await,
+            Err(Error::Render(RenderError::UnknownTexture(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_split_screen_cameras() {
+        let Some(mut renderer) = renderer().await else {
+            return;
+        };
+        let output = renderer.create_render_texture(Vector2::new(SIZE, SIZE));
+        let mut world = World::default();
+        world.insert_resource(renderer);
+        let mut entity = Entity::default();
+        entity.add_component(sprite([1.0, 0.0, 0.0, 1.0], 8, 8));
+        world.add_entity(entity);
+        // Both clear to black, each only its own half
+        for (order, x) in [(0, 0.0), (1, 0.5)] {
+            let mut camera = Camera2d::new(CameraTarget::Texture(output.handle));
+            camera.viewport.position.x = x;
+            camera.viewport.size.x = 0.5;
+            camera.order = order;
+            let mut entity = Entity::default();
+            entity.add_component(camera);
+            world.add_entity(entity);
+        }
+
+        render_2d(&world).unwrap();
+        let renderer = world.resource::<Renderer>().unwrap();
+        let image = renderer.read_texture(output.handle).await.unwrap();
+        let pixels: Vec<_> = image.pixels().map(|p| p.0).collect();
+        assert_eq!(pixel(&pixels, -16, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 16, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, -16 + 5, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 16 - 5, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 0, 16), [0, 0, 0, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_screenshots() {
+        let Some(mut renderer) = renderer().await else {
+            return;
+        };
+        let target = renderer.create_render_texture(Vector2::new(SIZE, SIZE));
+        let directory = std::env::temp_dir().join("goosberry_test_screenshots");
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut world = World::default();
+        world.insert_resource(renderer);
+        world.add_event::<Screenshot>();
+        let mut entity = Entity::default();
+        entity.add_component(sprite([1.0, 0.0, 0.0, 1.0], 8, 8));
+        world.add_entity(entity);
+        let mut camera = Entity::default();
+        let mut camera_2d = Camera2d::new(CameraTarget::Texture(target.handle));
+        camera_2d.clear_color = Some(Color::BLUE);
+        camera_2d.viewport.size.x = 0.5;
+        camera.add_component(camera_2d);
+        camera.add_component(FrameCapture::new(&directory));
+        let camera = world.add_entity(camera);
+
+        let mut take_screenshots = take_screenshots();
+        world
+            .resource_mut::<Events<Screenshot>>()
+            .unwrap()
+            .send(Screenshot {
+                camera,
+                path: directory.join("screenshot.png"),
+            });
+        for _ in 0..2 {
+            take_screenshots(&world).unwrap();
+            render_2d(&world).unwrap();
+        }
+        world
+            .resource_mut::<Renderer>()
+            .unwrap()
+            .finish_captures()
+            .unwrap();
+        let capture = world.get(camera).unwrap();
+        assert_eq!(capture.get_component::<FrameCapture>().unwrap().frames(), 2);
+        // As large as the viewport
+        let screenshot = image::open(directory.join("screenshot.png"))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(screenshot.dimensions(), (SIZE / 2, SIZE));
+        assert_eq!(screenshot.get_pixel(16, 32), &Rgba([255, 0, 0, 255]));
+        assert_eq!(screenshot.get_pixel(0, 0), &Rgba([0, 0, 255, 255]));
+        for frame in ["frame_00000.png", "frame_00001.png"] {
+            let frame = image::open(directory.join(frame)).unwrap().to_rgba8();
+            assert_eq!(frame, screenshot);
+        }
+        assert!(!directory.join("frame_00002.png").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_frame_saver() {
+        let directory = std::env::temp_dir().join("goosberry_test_frame_saver");
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut saver = FrameSaver::default();
+        assert!(saver.finish(true).is_ok());
+        // More frames than can be queued, the sends waiting for the thread to catch up
+        let count = FrameSaver::QUEUED * 2 + 1;
+        for i in 0..count {
+            let image = RgbaImage::from_pixel(4, 4, Rgba([i as u8, 0, 0, 255]));
+            saver.save(image, vec![directory.join(format!("{i}.png"))]);
+        }
+        assert!(saver.finish(true).is_ok());
+        for i in 0..count {
+            let image = image::open(directory.join(format!("{i}.png"))).unwrap();
+            assert_eq!(
+                image.to_rgba8().get_pixel(0, 0),
+                &Rgba([i as u8, 0, 0, 255])
+            );
+        }
+        // A directory can't be written over
+        saver.save(RgbaImage::new(4, 4), vec![directory.clone()]);
+        assert!(saver.finish(true).is_err());
+        assert!(saver.finish(true).is_ok());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_draw_order() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        let mut add = |color, position: Vector2<f32>, layer, z| {
+            let mut entity = Entity::default();
+            let mut sprite = sprite(color, 16, 16);
+            sprite.layer = layer;
+            sprite.z = z;
+            entity.add_component(sprite);
+            entity.add_component(Transform2::<f32>::from_position(position));
+            world.add_entity(entity);
+        };
+        // Over the next two despite coming first, as it is on a higher layer
+        add([1.0, 0.0, 0.0, 1.0], Vector2::new(0.0, 0.0), 1, -1.0);
+        add([0.0, 1.0, 0.0, 1.0], Vector2::new(8.0, 0.0), 0, 1.0);
+        add([1.0, 1.0, 1.0, 1.0], Vector2::new(16.0, 0.0), 0, 0.0);
+        // Same depth, overlapping vertically
+        add([1.0, 1.0, 0.0, 1.0], Vector2::new(-16.0, -16.0), 0, 0.0);
+        add([0.0, 1.0, 1.0, 1.0], Vector2::new(-16.0, -8.0), 0, 0.0);
+        // Same depth with different materials, in both orders
+        let tinted = || {
+            let white = RgbaImage::from_pixel(16, 16, Rgba([255, 255, 255, 255]));
+            Sprite::new_with_material(
+                white,
+                TintedMaterial {
+                    color: Color::GREEN,
+                    amount: 1.0,
+                },
+            )
+        };
+        let red = || sprite([1.0, 0.0, 0.0, 1.0], 16, 16);
+        for (sprite, position) in [
+            (tinted(), Vector2::new(-20.0, 16.0)),
+            (red(), Vector2::new(-20.0, 24.0)),
+            (red(), Vector2::new(20.0, 16.0)),
+            (tinted(), Vector2::new(20.0, 24.0)),
+        ] {
+            let mut entity = Entity::default();
+            entity.add_component(sprite);
+            entity.add_component(Transform2::<f32>::from_position(position));
+            world.add_entity(entity);
+        }
+
+        headless.keep_order = true;
+        let pixels = headless.render(&world);
+        assert_eq!(pixel(&pixels, 6, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 12, 0), [0, 255, 0, 255]);
+        // In the order they were added
+        assert_eq!(pixel(&pixels, -16, -12), [0, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, -20, 20), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 20, 20), [0, 255, 0, 255]);
+        headless.y_sort = true;
+        let pixels = headless.render(&world);
+        // The lower sprite is in front
+        assert_eq!(pixel(&pixels, -16, -12), [255, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 6, 0), [255, 0, 0, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_layer_masks() {
+        let Some(mut renderer) = renderer().await else {
+            return;
+        };
+        let world_view = renderer.create_render_texture(Vector2::new(SIZE, SIZE));
+        let ui_view = renderer.create_render_texture(Vector2::new(SIZE, SIZE));
+        let mut world = World::default();
+        world.insert_resource(renderer);
+        for (layer, x) in [(0, -16.0), (40, 16.0)] {
+            let mut entity = Entity::default();
+            let mut sprite = sprite([1.0, 0.0, 0.0, 1.0], 8, 8);
+            sprite.layer = layer;
+            entity.add_component(sprite);
+            entity.add_component(Transform2::<f32>::from_position(Vector2::new(x, 0.0)));
+            world.add_entity(entity);
+        }
+        for (target, layers) in [
+            (world_view, RenderLayers::ALL.without(40)),
+            (ui_view, RenderLayers::layer(40)),
+        ] {
+            let mut camera = Entity::default();
+            let mut camera_2d = Camera2d::new(CameraTarget::Texture(target.handle));
+            camera_2d.clear_color = Some(Color::BLUE);
+            camera_2d.layers = layers;
+            camera.add_component(camera_2d);
+            world.add_entity(camera);
+        }
+
+        render_2d(&world).unwrap();
+        let renderer = world.resource::<Renderer>().unwrap();
+        let image = renderer.read_texture(world_view.handle).await.unwrap();
+        let pixels: Vec<_> = image.pixels().map(|p| p.0).collect();
+        assert_eq!(pixel(&pixels, -16, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 16, 0), [0, 0, 255, 255]);
+        let image = renderer.read_texture(ui_view.handle).await.unwrap();
+        let pixels: Vec<_> = image.pixels().map(|p| p.0).collect();
+        assert_eq!(pixel(&pixels, -16, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 16, 0), [255, 0, 0, 255]);
+        assert!(!RenderLayers::NONE.with(3).contains(2));
+        assert!(RenderLayers::ALL.contains(255));
+        assert!(!RenderLayers::ALL.without(255).contains(255));
+    }
+
+    #[tokio::test]
+    async fn test_high_layers() {
+        let Some(mut renderer) = renderer().await else {
+            return;
+        };
+        let target = renderer.create_render_texture(Vector2::new(SIZE, SIZE));
+        let mut world = World::default();
+        world.insert_resource(renderer);
+        let mut entity = Entity::default();
+        let mut sprite = sprite([1.0, 0.0, 0.0, 1.0], 8, 8);
+        sprite.layer = 40;
+        entity.add_component(sprite);
+        world.add_entity(entity);
+        let mut camera = Entity::default();
+        camera.add_component(Camera2d::new(CameraTarget::Texture(target.handle)));
+        world.add_entity(camera);
+
+        render_2d(&world).unwrap();
+        let renderer = world.resource::<Renderer>().unwrap();
+        let image = renderer.read_texture(target.handle).await.unwrap();
+        let pixels: Vec<_> = image.pixels().map(|p| p.0).collect();
+        // Cameras show every layer by default, including the ones above 31
+        assert_eq!(pixel(&pixels, 0, 0), [255, 0, 0, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_materials() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        let white = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255]));
+        let white_handle = TextureHandle::new();
+        // A red square with a transparent margin of 2 pixels
+        let square = RgbaImage::from_fn(8, 8, |x, y| match (x, y) {
+            (2..=5, 2..=5) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        let half_green = TintedMaterial {
+            color: Color::GREEN,
+            amount: 0.5,
+        };
+        let shader = r#"#include "sprite.wgsl"
+            struct Params {
+                color: vec4<f32>,
+            }
+            @group(2) @binding(0)
+            var<uniform> params: Params;
+            @group(2) @binding(1)
+            var mask: texture_2d<f32>;
+
+            @fragment
+            fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+                return params.color * textureSample(mask, sprite_sampler, in.uv);
+            }"#;
+        let mut cyan = ShaderMaterial::new("Cyan".to_string(), shader.to_string());
+        cyan.uniform = Some(bytemuck::cast_slice(&[0.0f32, 1.0, 1.0, 1.0]).to_vec());
+        cyan.textures = vec![white_handle];
+        let sprites = [
+            Sprite::new_with_material(white.clone(), half_green),
+            Sprite::with_handle(white_handle, white.clone()),
+            Sprite::new_with_material(white.clone(), half_green),
+            Sprite::new_with_material(
+                RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255])),
+                AdditiveMaterial,
+            ),
+            Sprite::new_with_material(
+                square,
+                OutlineMaterial {
+                    color: Color::WHITE,
+                    thickness: 1.0,
+                },
+            ),
+            Sprite::new_with_material(RgbaImage::new(8, 8), cyan),
+            // Doesn't validate, so it's left out instead of failing to create its pipeline
+            Sprite::new_with_shader(
+                white.clone(),
+                concat!(
+                    "#include \"sprite.wgsl\"\n",
+                    "@fragment\n",
+                    "fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {\n",
+                    "    var out = VertexOutput;\n",
+                    "    return in.tint;\n",
+                    "}\n",
+                )
+                .to_string(),
+                "Broken".to_string(),
+            ),
+        ];
+        for (i, sprite) in sprites.into_iter().enumerate() {
+            let mut entity = Entity::default();
+            entity.add_component(sprite);
+            let position = Vector2::new(i as f32 * 10.0 - 30.0, 0.0);
+            entity.add_component(Transform2::<f32>::from_position(position));
+            world.add_entity(entity);
+        }
+
+        let pixels = headless.render(&world);
+        // The tinted sprites share a bind group, the outline and cyan ones have their own
+        assert_eq!(headless.materials.len(), 3);
+        let [r, g, b, a] = pixel(&pixels, -30, 0);
+        assert!(r.abs_diff(128) <= 1 && g == 255 && b == r && a == 255);
+        assert_eq!(pixel(&pixels, -20, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, -10, 0), pixel(&pixels, -30, 0));
+        // Red added to the blue background
+        assert_eq!(pixel(&pixels, 0, 0), [255, 0, 255, 255]);
+        // Red, with a white outline a pixel wide and the background around it
+        assert_eq!(pixel(&pixels, 10, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 10 - 3, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, 10 - 4, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 20, 0), [0, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, 30, 0), [0, 0, 255, 255]);
+        assert_eq!(headless.pipelines.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_material_sampling_target() {
+        let Some(mut renderer) = renderer().await else {
+            return;
+        };
+        let mirror = renderer.create_render_texture(Vector2::new(16, 16));
+        let output = renderer.create_render_texture(Vector2::new(SIZE, SIZE));
+        let mut world = World::default();
+        world.insert_resource(renderer);
+        let shader = r#"#include "sprite.wgsl"
+            @group(2) @binding(1)
+            var mirror: texture_2d<f32>;
+
+            @fragment
+            fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+                return textureSample(mirror, sprite_sampler, in.uv) + vec4<f32>(0.0, 1.0, 0.0, 0.0);
+            }"#;
+        let mut material = ShaderMaterial::new("Mirror".to_string(), shader.to_string());
+        material.textures = vec![mirror.handle];
+        // Seen by both cameras, but it can't be drawn to the texture it samples
+        let mut entity = Entity::default();
+        let white = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255]));
+        entity.add_component(Sprite::new_with_material(white, material));
+        world.add_entity(entity);
+        let mut camera = Entity::default();
+        let mut camera_2d = Camera2d::new(CameraTarget::Texture(mirror.handle));
+        camera_2d.clear_color = Some(Color::RED);
+        camera.add_component(camera_2d);
+        world.add_entity(camera);
+        let mut camera = Entity::default();
+        let mut camera_2d = Camera2d::new(CameraTarget::Texture(output.handle));
+        camera_2d.clear_color = Some(Color::BLUE);
+        camera_2d.order = 1;
+        camera.add_component(camera_2d);
+        world.add_entity(camera);
+
+        render_2d(&world).unwrap();
+        let renderer = world.resource::<Renderer>().unwrap();
+        let image = renderer.read_texture(mirror.handle).await.unwrap();
+        assert_eq!(image.get_pixel(8, 8), &Rgba([255, 0, 0, 255]));
+        let image = renderer.read_texture(output.handle).await.unwrap();
+        let pixels: Vec<_> = image.pixels().map(|p| p.0).collect();
+        assert_eq!(pixel(&pixels, 0, 0), [255, 255, 0, 255]);
+        assert_eq!(pixel(&pixels, 8, 8), [0, 0, 255, 255]);
+    }
+
+    #[tokio::test]
+    async fn test_animated_materials() {
+        let Some(mut headless) = Headless::new().await else {
+            return;
+        };
+        let mut world = World::default();
+        for x in [-16.0, 0.0, 16.0] {
+            let mut entity = Entity::default();
+            entity.add_component(sprite([1.0, 0.0, 0.0, 1.0], 8, 8));
+            entity.add_component(Transform2::<f32>::from_position(Vector2::new(x, 0.0)));
+            world.add_entity(entity);
+        }
+        for frame in 0..10 {
+            for (i, mut entity) in world.query_mut::<(Sprite,)>().enumerate() {
+                // The first two flash together, the last one on its own
+                let amount = match i {
+                    0 | 1 => frame as f32 / 10.0,
+                    _ => 1.0 - frame as f32 / 10.0,
+                };
+                let sprite = entity.get_component_mut::<Sprite>().unwrap();
+                sprite.set_material(TintedMaterial {
+                    color: Color::GREEN,
+                    amount,
+                });
+            }
+            let pixels = headless.render(&world);
+            // Bind groups are reused for the new values instead of piling up
+            assert_eq!(headless.materials.len(), 2);
+            let green = |x| pixel(&pixels, x, 0)[1];
+            assert!(green(-16).abs_diff((frame * 255 / 10) as u8) <= 1);
+            assert_eq!(green(0), green(-16));
+            assert!(green(16).abs_diff(((10 - frame) * 255 / 10) as u8) <= 1);
+        }
+    }
+
+    #[test]
+    fn test_shaders() {
+        let mut library = ShaderLibrary::new();
+        assert_eq!(library.get("sprite.wgsl"), Some(SPRITE_WGSL));
+        let materials: [&dyn Material; 4] = [
+            &UnlitMaterial,
+            &AdditiveMaterial,
+            &TintedMaterial {
+                color: Color::RED,
+                amount: 1.0,
+            },
+            &OutlineMaterial {
+                color: Color::RED,
+                thickness: 1.0,
+            },
+        ];
+        for material in materials {
+            let layout = MaterialLayout::of(material);
+            if let Err(e) = library.load_sprite_shader(material.label(), material.shader(), layout)
+            {
+                panic!("{e}");
+            }
+        }
+
+        // Defines are replaced in whole identifiers, outside of comments
+        library.add(
+            "color.wgsl",
+            concat!(
+                "#define RED vec4<f32>(1.0, 0.0, 0.0, 1.0)\n",
+                "#define OUTLINE\n",
+                "let RED_COLOR = RED; // RED\n",
+            ),
+        );
+        let shader = library
+            .preprocess(
+                "main.wgsl",
+                concat!(
+                    "#include \"color.wgsl\"\n",
+                    "#include \"color.wgsl\"\n",
+                    "#ifdef OUTLINE\n",
+                    "let a = RED;\n",
+                    "#else\n",
+                    "let b = 0;\n",
+                    "#endif\n",
+                    "#ifndef OUTLINE\n",
+                    "let c = 0;\n",
+                    "#endif\n",
+                ),
+            )
+            .unwrap();
+        assert_eq!(
+            shader.source,
+            concat!(
+                "let RED_COLOR = vec4<f32>(1.0, 0.0, 0.0, 1.0); // RED\n",
+                "let a = vec4<f32>(1.0, 0.0, 0.0, 1.0);\n",
+            )
+        );
+        assert_eq!(
+            shader.location(2, 5),
+            Some(ShaderLocation {
+                file: "main.wgsl".to_string(),
+                line: 4,
+                column: 5,
+            })
+        );
+
+        // Nor in block comments, which span lines and nest
+        let shader = library
+            .preprocess(
+                "main.wgsl",
+                concat!(
+                    "#define RED 1.0\n",
+                    "/* RED /* RED */\n",
+                    "RED */ let a = RED; /* RED */ let b = RED;\n",
+                ),
+            )
+            .unwrap();
+        assert_eq!(
+            shader.source,
+            "/* RED /* RED */\nRED */ let a = 1.0; /* RED */ let b = 1.0;\n"
+        );
+
+        // Columns after a define are those of the line as written, and those in its value
+        // the define's
+        let shader = library
+            .preprocess(
+                "main.wgsl",
+                "#define LONG_NAME 1.0\nlet a = LONG_NAME + LONG_NAME + x;",
+            )
+            .unwrap();
+        assert_eq!(shader.source, "let a = 1.0 + 1.0 + x;\n");
+        let column = |column| shader.location(1, column).unwrap().column;
+        assert_eq!((column(5), column(16), column(21)), (5, 21, 33));
+        // Module constants can't add, so the first `+` is wrong
+        let location = shader.validate().unwrap_err().location.unwrap();
+        assert_eq!((location.line, location.column), (2, 19));
+
+        // Errors point at the file and line they're in, not at the preprocessed source
+        library.add("broken.wgsl", "fn f() -> f32 {\n    return 1.0\n}");
+        let error = library
+            .load_sprite_shader(
+                "main.wgsl",
+                "#include \"sprite.wgsl\"\n#include \"broken.wgsl\"",
+                None,
+            )
+            .unwrap_err();
+        let location = error.location.unwrap();
+        assert_eq!((location.file.as_str(), location.line), ("broken.wgsl", 3));
+        let error = library
+            .preprocess("main.wgsl", "\n#include \"missing.wgsl\"")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "main.wgsl:2:1: no shader file `missing.wgsl` to include"
+        );
+        assert!(library.preprocess("main.wgsl", "#ifdef A\n").is_err());
+        assert!(library.preprocess("main.wgsl", "#endif").is_err());
+
+        // Material bindings that don't match the material's layout
+        let tinted = TintedMaterial {
+            color: Color::RED,
+            amount: 1.0,
+        };
+        let error = library
+            .load_sprite_shader(tinted.label(), tinted.shader(), None)
+            .unwrap_err();
+        assert!(
+            error.message.contains("`tint` at @group(2) @binding(0)"),
+            "{error}"
+        );
+        let masked = concat!(
+            "#include \"sprite.wgsl\"\n",
+            "@group(2) @binding(1)\n",
+            "var mask: texture_2d<f32>;\n",
+            "@group(2) @binding(2)\n",
+            "var extra: texture_2d<f32>;\n",
+            "@fragment\n",
+            "fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {\n",
+            "    let masked = textureSample(mask, sprite_sampler, in.uv);\n",
+            "    return masked * textureSample(extra, sprite_sampler, in.uv);\n",
+            "}\n",
+        );
+        let one = MaterialLayout {
+            uniform: false,
+            textures: 1,
+        };
+        let error = library
+            .load_sprite_shader("Masked", masked, Some(one))
+            .unwrap_err();
+        assert!(
+            error.message.contains("`extra` at @group(2) @binding(2)"),
+            "{error}"
+        );
+        let two = MaterialLayout { textures: 2, ..one };
+        assert!(library
+            .load_sprite_shader("Masked", masked, Some(two))
+            .is_ok());
+
+        // Vertex inputs that don't match the layout sprites are drawn with
+        let vertex = SPRITE_WGSL.replace("position: vec2<f32>", "position: vec3<f32>");
+        library.add("sprite.wgsl", vertex);
+        let error = library
+            .load_sprite_shader(UnlitMaterial.label(), UnlitMaterial.shader(), None)
+            .unwrap_err();
+        assert!(error.message.contains("location 0"), "{error}");
+    }
 }
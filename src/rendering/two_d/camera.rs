@@ -1,158 +1,215 @@
+use crate::ecs::components::Transform2;
+use crate::rendering::projection::Projection;
+use crate::rendering::renderer::SurfaceId;
+use crate::rendering::texture::TextureHandle;
 use bytemuck::{Pod, Zeroable};
-use nalgebra::Vector2;
-use raw_window_handle::HasRawWindowHandle;
-use std::collections::HashMap;
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::{
-    Backends, Color, Device, DeviceDescriptor, Features, Instance, Queue, RenderPipeline,
-    RequestAdapterOptions, Surface, SurfaceConfiguration, SurfaceError, TextureViewDescriptor,
-    VertexBufferLayout,
-};
+use nalgebra::{Point2, Vector2};
+use std::path::{Path, PathBuf};
+use wgpu::{Color, VertexBufferLayout};
 
+#[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
     pub position: Vector2<f32>,
+    pub uv: Vector2<f32>,
 }
 unsafe impl Zeroable for Vertex {}
 unsafe impl Pod for Vertex {}
 impl Vertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2];
     pub fn desc<'a>() -> VertexBufferLayout<'a> {
         VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[wgpu::VertexAttribute {
-                offset: 0,
-                shader_location: 0,
-                format: wgpu::VertexFormat::Float32x3,
-            }],
+            attributes: &Self::ATTRIBUTES,
         }
     }
 }
 
-#[derive(Debug)]
-pub struct CameraOptions {
-    pub vsync: bool,
-    pub clear_color: Color,
+/// Per-sprite data of instanced draws: the transform from the unit quad to clip space, the
+/// part of the texture to draw and a tint.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SpriteInstance {
+    pub x_axis: Vector2<f32>,
+    pub y_axis: Vector2<f32>,
+    pub translation: Vector2<f32>,
+    pub uv_min: Vector2<f32>,
+    pub uv_size: Vector2<f32>,
+    pub tint: [f32; 4],
+}
+unsafe impl Zeroable for SpriteInstance {}
+unsafe impl Pod for SpriteInstance {}
+impl SpriteInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        2 => Float32x2,
+        3 => Float32x2,
+        4 => Float32x2,
+        5 => Float32x2,
+        6 => Float32x2,
+        7 => Float32x4,
+    ];
+    pub fn desc<'a>() -> VertexBufferLayout<'a> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// What a camera renders to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CameraTarget {
+    /// A window added to the [`Renderer`](crate::rendering::renderer::Renderer).
+    Window(SurfaceId),
+    /// A render target created with
+    /// [`Renderer::create_render_texture`](crate::rendering::renderer::Renderer::create_render_texture).
+    Texture(TextureHandle),
+}
+
+/// Part of a target a camera renders to, in fractions of its size from its top-left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub position: Vector2<f32>,
+    pub size: Vector2<f32>,
 }
-impl Default for CameraOptions {
+impl Default for Viewport {
+    /// The whole target.
     fn default() -> Self {
         Self {
-            vsync: true,
-            clear_color: Color::BLACK,
+            position: Vector2::zeros(),
+            size: Vector2::repeat(1.0),
         }
     }
 }
+impl Viewport {
+    /// Offset and size in pixels of the viewport, in a target `size` pixels large, clamped to
+    /// the target.
+    pub fn pixels(&self, size: Vector2<u32>) -> (Vector2<u32>, Vector2<u32>) {
+        let target = size.cast::<f32>();
+        let offset = self
+            .position
+            .component_mul(&target)
+            .map(|c| c.round() as u32);
+        let offset = offset.zip_map(&size, |o, s| o.min(s.saturating_sub(1)));
+        let pixels = self.size.component_mul(&target).map(|c| c.round() as u32);
+        let pixels = pixels.zip_zip_map(&offset, &size, |p, o, s| p.clamp(1, (s - o).max(1)));
+        (offset, pixels)
+    }
+}
+
+/// A set of the 256 render layers sprites can be on, one per value of
+/// [`Sprite::layer`](crate::rendering::sprite::Sprite::layer), that a camera draws.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderLayers(pub [u64; 4]);
+impl Default for RenderLayers {
+    /// Every layer.
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+impl RenderLayers {
+    pub const ALL: Self = Self([u64::MAX; 4]);
+    pub const NONE: Self = Self([0; 4]);
+
+    /// Only `layer`.
+    pub fn layer(layer: u8) -> Self {
+        Self::NONE.with(layer)
+    }
+    /// These layers and `layer`.
+    pub fn with(mut self, layer: u8) -> Self {
+        self.0[layer as usize / 64] |= 1 << (layer % 64);
+        self
+    }
+    pub fn without(mut self, layer: u8) -> Self {
+        self.0[layer as usize / 64] &= !(1 << (layer % 64));
+        self
+    }
+    pub fn contains(&self, layer: u8) -> bool {
+        self.0[layer as usize / 64] & 1 << (layer % 64) != 0
+    }
+}
 
+/// A view of the world rendered by [`render_2d`](crate::rendering::render_2d) to a target.
+///
+/// Its entity's `Transform2<f32>`, if it has one, places it in the world. Cameras are rendered
+/// by increasing `order`, so that for instance a minimap camera drawn over the main one has a
+/// higher order and no clear color.
+///
+/// Sprites are drawn back to front: by layer, then by z, then, when `y_sort` is set, from the
+/// highest to the lowest for top-down games where what's lower on screen is in front. Sprites
+/// at the same depth are grouped by shader, texture and material to draw them in fewer batches,
+/// so their order among themselves is unspecified unless `keep_order` is set.
 #[derive(Debug)]
 pub struct Camera2d {
-    pub surface: Surface,
-    pub device: Device,
-    pub queue: Queue,
-    pub config: SurfaceConfiguration,
-    pub size: Vector2<u32>,
-    pub pipelines: HashMap<u64, RenderPipeline>,
-    pub options: CameraOptions,
+    pub target: CameraTarget,
+    pub viewport: Viewport,
+    pub projection: Projection,
+    /// Color the camera's viewport is cleared to before it renders, or `None` to draw over what
+    /// cameras of lower order rendered.
+    pub clear_color: Option<Color>,
+    pub order: i32,
+    /// Layers of the sprites drawn, for instance to render the world and the UI with different
+    /// cameras.
+    pub layers: RenderLayers,
+    /// Draws the sprites of the same layer and z by decreasing `y`.
+    pub y_sort: bool,
+    /// Draws the sprites at the same depth in the order their entities were added, at the cost
+    /// of a batch every time the shader, texture or material changes.
+    pub keep_order: bool,
+    /// Where the camera looks from, synced from the `Transform2<f32>` of its entity by
+    /// [`render_2d`](crate::rendering::render_2d).
+    pub view: Transform2<f32>,
+    offset: Vector2<u32>,
+    size: Vector2<u32>,
+    screenshots: Vec<PathBuf>,
 }
 impl Camera2d {
-    pub async fn new<T: HasRawWindowHandle>(
-        window: &T,
-        size: Vector2<u32>,
-        options: CameraOptions,
-    ) -> Self {
-        let instance = Instance::new(Backends::all());
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    label: None,
-                    limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
-                    features: Features::empty(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
-        let config = SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_supported_formats(&adapter)[0],
-            width: size.x,
-            height: size.y,
-            present_mode: match options.vsync {
-                true => wgpu::PresentMode::Fifo,
-                false => wgpu::PresentMode::Immediate,
-            },
-        };
-        surface.configure(&device, &config);
+    pub fn new(target: CameraTarget) -> Self {
         Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            pipelines: HashMap::new(),
-            options,
+            target,
+            viewport: Viewport::default(),
+            projection: Projection::default(),
+            clear_color: Some(Color::BLACK),
+            order: 0,
+            layers: RenderLayers::ALL,
+            y_sort: false,
+            keep_order: false,
+            view: Transform2::default(),
+            offset: Vector2::zeros(),
+            size: Vector2::repeat(1),
+            screenshots: Vec::new(),
         }
     }
-    pub fn resize(&mut self, size: Vector2<u32>) {
-        if size.x > 0 && size.y > 0 {
-            self.size = size;
-            self.config.width = size.x;
-            self.config.height = size.y;
-            self.surface.configure(&self.device, &self.config);
-        }
+    /// Size in pixels of the viewport, when the camera last rendered.
+    pub fn size(&self) -> Vector2<u32> {
+        self.size
     }
-    pub fn render(
-        &mut self,
-        //render_pipeline: RenderPipeline,
-        //vertices: &[Vertex],
-    ) -> Result<(), SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&TextureViewDescriptor::default());
-        /*let vertex_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });*/
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
-        {
-            let _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.options.clear_color),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-            //render_pass.set_pipeline(&render_pipeline);
-            //render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            //render_pass.draw(0..vertices.len() as u32, 0..1);
-        }
-
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
+    /// Saves the next frame the camera renders as a PNG file at `path`, see
+    /// [`Screenshot`](crate::rendering::screenshot::Screenshot).
+    pub fn screenshot<P: AsRef<Path>>(&mut self, path: P) {
+        self.screenshots.push(path.as_ref().to_path_buf());
+    }
+    pub(crate) fn take_screenshots(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.screenshots)
+    }
+    pub(crate) fn set_pixels(&mut self, (offset, size): (Vector2<u32>, Vector2<u32>)) {
+        self.offset = offset;
+        self.size = size;
+    }
+    /// Converts a point of the world to pixels of the target, see
+    /// [`Projection::world_to_screen`].
+    pub fn world_to_screen(&self, point: &Point2<f32>) -> Point2<f32> {
+        self.projection
+            .world_to_screen(&self.view, self.size, point)
+            + self.offset.cast::<f32>()
+    }
+    /// Converts pixels of the target, like a cursor position, to a point of the world.
+    pub fn screen_to_world(&self, point: &Point2<f32>) -> Point2<f32> {
+        let point = point - self.offset.cast::<f32>();
+        self.projection
+            .screen_to_world(&self.view, self.size, &point)
     }
 }
@@ -0,0 +1,403 @@
+use crate::rendering::texture::{TextureCache, TextureHandle};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer,
+    Color, Device, Queue,
+};
+
+/// Inputs, bindings and vertex stage shared by sprite shaders, which include it with
+/// `#include "sprite.wgsl"` and only need to add a `fs_main`:
+/// - vertex inputs at locations 0 and 1, instance inputs at locations 2 to 7
+/// - the sprite's texture and sampler in bind group 0
+/// - the camera's `view_projection` and the `time` in seconds in bind group 1
+pub const SPRITE_WGSL: &str = include_str!("shaders/sprite.wgsl");
+
+/// How the colors a sprite shader outputs are combined with those already drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Over what's behind, by alpha.
+    #[default]
+    Alpha,
+    /// Added to what's behind, scaled by alpha, for glows and particles.
+    Additive,
+    /// Replaces what's behind, ignoring alpha.
+    Opaque,
+}
+impl BlendMode {
+    pub fn to_wgpu(self) -> Option<BlendState> {
+        match self {
+            BlendMode::Alpha => Some(BlendState::ALPHA_BLENDING),
+            BlendMode::Additive => Some(BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::Zero,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            }),
+            BlendMode::Opaque => None,
+        }
+    }
+}
+
+/// The shader a sprite is drawn with, along with its parameters and render state.
+///
+/// Shaders include [`SPRITE_WGSL`] and get the material's bindings in bind group 2: its
+/// [`Material::uniform`] at binding 0 if it has one, and its [`Material::textures`] from
+/// binding 1 on, to sample with the sprite's sampler.
+pub trait Material: Send + Sync {
+    /// Names the pipeline, in errors and GPU debuggers.
+    fn label(&self) -> &str;
+    /// WGSL source with a `vs_main` and a `fs_main`, which can use the directives of the
+    /// renderer's [`ShaderLibrary`](crate::rendering::shader::ShaderLibrary).
+    fn shader(&self) -> &str;
+    fn blend(&self) -> BlendMode {
+        BlendMode::Alpha
+    }
+    /// Contents of the uniform buffer, laid out as the shader's struct, if the shader has one.
+    fn uniform(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Extra textures, which must be in the renderer's [`TextureCache`] (render textures, or
+    /// those of other sprites) for the sprite to be drawn.
+    fn textures(&self) -> &[TextureHandle] {
+        &[]
+    }
+}
+
+/// Bytes of a color and a float, as a WGSL struct of a `vec4<f32>` and an `f32`.
+fn color_and_f32(color: Color, value: f32) -> Vec<u8> {
+    let fields = [color.r, color.g, color.b, color.a].map(|c| c as f32);
+    bytemuck::cast_slice(&[fields[0], fields[1], fields[2], fields[3], value]).to_vec()
+}
+
+/// The texture, multiplied by the sprite's tint.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct UnlitMaterial;
+impl Material for UnlitMaterial {
+    fn label(&self) -> &str {
+        "SpriteUnlit"
+    }
+    fn shader(&self) -> &str {
+        include_str!("shaders/sprite_unlit.wgsl")
+    }
+}
+
+/// Like [`UnlitMaterial`], but added to what's behind.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AdditiveMaterial;
+impl Material for AdditiveMaterial {
+    fn label(&self) -> &str {
+        "SpriteAdditive"
+    }
+    fn shader(&self) -> &str {
+        UnlitMaterial.shader()
+    }
+    fn blend(&self) -> BlendMode {
+        BlendMode::Additive
+    }
+}
+
+/// The colors of the texture mixed towards `color` by `amount`, from 0 to 1, keeping their
+/// alpha, like a sprite flashing when hit.
+#[derive(Copy, Clone, Debug)]
+pub struct TintedMaterial {
+    pub color: Color,
+    pub amount: f32,
+}
+impl Material for TintedMaterial {
+    fn label(&self) -> &str {
+        "SpriteTinted"
+    }
+    fn shader(&self) -> &str {
+        include_str!("shaders/sprite_tinted.wgsl")
+    }
+    fn uniform(&self) -> Option<Vec<u8>> {
+        Some(color_and_f32(self.color, self.amount))
+    }
+}
+
+/// An outline of `color`, `thickness` texels thick, around the opaque parts of the texture.
+///
+/// It's drawn inside the sprite, which needs a transparent margin for it.
+#[derive(Copy, Clone, Debug)]
+pub struct OutlineMaterial {
+    pub color: Color,
+    pub thickness: f32,
+}
+impl Material for OutlineMaterial {
+    fn label(&self) -> &str {
+        "SpriteOutline"
+    }
+    fn shader(&self) -> &str {
+        include_str!("shaders/sprite_outline.wgsl")
+    }
+    fn uniform(&self) -> Option<Vec<u8>> {
+        Some(color_and_f32(self.color, self.thickness))
+    }
+}
+
+/// A material with a custom shader.
+#[derive(Clone, Debug)]
+pub struct ShaderMaterial {
+    pub label: String,
+    pub shader: String,
+    pub blend: BlendMode,
+    pub uniform: Option<Vec<u8>>,
+    pub textures: Vec<TextureHandle>,
+}
+impl ShaderMaterial {
+    pub fn new(label: String, shader: String) -> Self {
+        Self {
+            label,
+            shader,
+            blend: BlendMode::Alpha,
+            uniform: None,
+            textures: Vec::new(),
+        }
+    }
+}
+impl Material for ShaderMaterial {
+    fn label(&self) -> &str {
+        &self.label
+    }
+    fn shader(&self) -> &str {
+        &self.shader
+    }
+    fn blend(&self) -> BlendMode {
+        self.blend
+    }
+    fn uniform(&self) -> Option<Vec<u8>> {
+        self.uniform.clone()
+    }
+    fn textures(&self) -> &[TextureHandle] {
+        &self.textures
+    }
+}
+
+/// Which bindings a material has, which decides the layout of bind group 2.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialLayout {
+    pub uniform: bool,
+    pub textures: usize,
+}
+impl MaterialLayout {
+    pub fn of(material: &dyn Material) -> Option<Self> {
+        let layout = Self {
+            uniform: material.uniform().is_some(),
+            textures: material.textures().len(),
+        };
+        (layout.uniform || layout.textures > 0).then_some(layout)
+    }
+}
+
+/// What the pipeline of a material depends on, worked out once when it is given to a sprite
+/// rather than every time the sprite is drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialKey {
+    /// Hash of the shader, label, blend mode and layout, which along with the format of the
+    /// target identifies the pipeline.
+    pub pipeline: u64,
+    pub layout: Option<MaterialLayout>,
+}
+impl MaterialKey {
+    pub fn of(material: &dyn Material) -> Self {
+        let layout = MaterialLayout::of(material);
+        let mut hasher = DefaultHasher::new();
+        material.shader().hash(&mut hasher);
+        material.label().hash(&mut hasher);
+        material.blend().hash(&mut hasher);
+        layout.hash(&mut hasher);
+        Self {
+            pipeline: hasher.finish(),
+            layout,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedBindGroup {
+    bind_group: BindGroup,
+    /// Rewritten when the bind group is reused for another uniform.
+    buffer: Option<Buffer>,
+    /// Key of everything bound but the contents of the uniform.
+    bindings: u64,
+    last_used: u64,
+}
+
+/// Bind groups of materials, by contents, so that sprites with equal materials share one and
+/// get batched.
+///
+/// A material whose uniform changed, like an animated [`TintedMaterial`], reuses a bind group
+/// with the same bindings that wasn't used yet in the frame, writing its uniform to the
+/// existing buffer. There are then only as many bind groups as distinct materials drawn in a
+/// frame, and like textures, those that weren't used for [`TextureCache::EVICT_AFTER`] frames
+/// are dropped.
+#[derive(Debug, Default)]
+pub struct MaterialCache {
+    layouts: HashMap<MaterialLayout, BindGroupLayout>,
+    bind_groups: HashMap<u64, CachedBindGroup>,
+    /// Keys of the bind groups that may not have been used yet this frame, by bindings.
+    spare: HashMap<u64, Vec<u64>>,
+    frame: u64,
+}
+impl MaterialCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn layout(&mut self, device: &Device, layout: MaterialLayout) -> &BindGroupLayout {
+        self.layouts.entry(layout).or_insert_with(|| {
+            let mut entries = Vec::new();
+            if layout.uniform {
+                entries.push(wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                });
+            }
+            for i in 0..layout.textures {
+                entries.push(wgpu::BindGroupLayoutEntry {
+                    binding: 1 + i as u32,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                });
+            }
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Material"),
+                entries: &entries,
+            })
+        })
+    }
+    pub fn get(&self, key: u64) -> Option<&BindGroup> {
+        self.bind_groups.get(&key).map(|cached| &cached.bind_group)
+    }
+    pub fn len(&self) -> usize {
+        self.bind_groups.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.bind_groups.is_empty()
+    }
+    /// Creates the bind group of `material` unless there's one for the same contents already,
+    /// returning its key, or `None` if one of its textures isn't in `textures`.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        textures: &TextureCache,
+        layout: MaterialLayout,
+        material: &dyn Material,
+    ) -> Option<u64> {
+        let uniform = material.uniform().map(|mut uniform| {
+            // Uniform structs are 16-byte aligned
+            uniform.resize(uniform.len().next_multiple_of(16).max(16), 0);
+            uniform
+        });
+        let mut hasher = DefaultHasher::new();
+        layout.hash(&mut hasher);
+        uniform.as_ref().map(Vec::len).hash(&mut hasher);
+        for handle in material.textures() {
+            textures.get(*handle)?.id().hash(&mut hasher);
+        }
+        let bindings = hasher.finish();
+        uniform.hash(&mut hasher);
+        let key = hasher.finish();
+        let frame = self.frame;
+        if let Some(cached) = self.bind_groups.get_mut(&key) {
+            cached.last_used = frame;
+            return Some(key);
+        }
+        if let Some(spare) = self.take_spare(bindings) {
+            let mut cached = self.bind_groups.remove(&spare).unwrap();
+            if let (Some(buffer), Some(uniform)) = (&cached.buffer, &uniform) {
+                queue.write_buffer(buffer, 0, uniform);
+            }
+            cached.last_used = frame;
+            self.bind_groups.insert(key, cached);
+            return Some(key);
+        }
+
+        let buffer = uniform.map(|uniform| {
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some(material.label()),
+                contents: &uniform,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        });
+        let views: Vec<_> = material
+            .textures()
+            .iter()
+            .map(|handle| {
+                let texture = textures.get(*handle).unwrap();
+                texture.texture.create_view(&Default::default())
+            })
+            .collect();
+        let mut entries = Vec::new();
+        if let Some(buffer) = &buffer {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            });
+        }
+        for (i, view) in views.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1 + i as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(material.label()),
+            layout: self.layout(device, layout),
+            entries: &entries,
+        });
+        self.bind_groups.insert(
+            key,
+            CachedBindGroup {
+                bind_group,
+                buffer,
+                bindings,
+                last_used: frame,
+            },
+        );
+        Some(key)
+    }
+    /// Takes a bind group with `bindings` that wasn't used this frame, if there is one.
+    fn take_spare(&mut self, bindings: u64) -> Option<u64> {
+        let spare = self.spare.get_mut(&bindings)?;
+        while let Some(key) = spare.pop() {
+            // Bind groups reused earlier in the frame are under another key now
+            let unused = self.bind_groups.get(&key);
+            if unused.is_some_and(|cached| cached.last_used < self.frame) {
+                return Some(key);
+            }
+        }
+        None
+    }
+    /// Ends a frame, dropping the bind groups that haven't been used for a while.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.bind_groups
+            .retain(|_, cached| frame - cached.last_used < TextureCache::EVICT_AFTER);
+        self.spare.clear();
+        for (key, cached) in &self.bind_groups {
+            self.spare.entry(cached.bindings).or_default().push(*key);
+        }
+        self.frame += 1;
+    }
+}
@@ -0,0 +1,96 @@
+use crate::ecs::components::Transform2;
+use nalgebra::{Matrix3, Point2, Vector2};
+
+/// How much of the world a camera shows, depending on the size of its target.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ScalingMode {
+    /// One unit is one pixel of the target.
+    #[default]
+    WindowSize,
+    /// Always this many units wide, the height following the target's aspect ratio.
+    FixedWidth(f32),
+    /// Always this many units high, the width following the target's aspect ratio.
+    FixedHeight(f32),
+    /// At least `width` × `height` units, with each unit covering a whole number of pixels so
+    /// that pixel art stays crisp.
+    PixelPerfect { width: u32, height: u32 },
+}
+
+/// Orthographic projection of a [`Camera2d`](crate::rendering::camera::Camera2d).
+///
+/// The camera looks at the world from its view transform: its position is the center of the
+/// target and its rotation turns the world the other way. The projection then maps the area
+/// the camera sees onto the target, Y up.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Projection {
+    pub scaling: ScalingMode,
+    /// Magnification: at 2, things look twice as large.
+    pub zoom: f32,
+}
+impl Default for Projection {
+    fn default() -> Self {
+        Self {
+            scaling: ScalingMode::default(),
+            zoom: 1.0,
+        }
+    }
+}
+impl Projection {
+    /// Size in world units of the area seen on a target `size` pixels large.
+    pub fn area(&self, size: Vector2<u32>) -> Vector2<f32> {
+        let pixels = size.cast::<f32>();
+        let area = match self.scaling {
+            ScalingMode::WindowSize => pixels,
+            ScalingMode::FixedWidth(width) => Vector2::new(width, width * pixels.y / pixels.x),
+            ScalingMode::FixedHeight(height) => Vector2::new(height * pixels.x / pixels.y, height),
+            ScalingMode::PixelPerfect { width, height } => {
+                let scale = (size.x / width.max(1)).min(size.y / height.max(1)).max(1);
+                pixels / scale as f32
+            }
+        };
+        area / self.zoom
+    }
+    /// Maps world coordinates to clip space, for a camera at `view`.
+    pub fn view_projection(&self, view: &Transform2<f32>, size: Vector2<u32>) -> Matrix3<f32> {
+        let area = self.area(size);
+        let projection = Matrix3::new_nonuniform_scaling(&Vector2::new(2.0 / area.x, 2.0 / area.y));
+        // The matrix inverse, as a rotated non-uniform scale has no inverse transform
+        let view = view
+            .to_matrix()
+            .try_inverse()
+            .unwrap_or_else(Matrix3::identity);
+        projection * view
+    }
+    /// Converts a point of the world to pixels of the target, from its top-left corner with Y
+    /// down, like cursor positions.
+    pub fn world_to_screen(
+        &self,
+        view: &Transform2<f32>,
+        size: Vector2<u32>,
+        point: &Point2<f32>,
+    ) -> Point2<f32> {
+        let clip = self.view_projection(view, size).transform_point(point);
+        Point2::new(
+            (clip.x + 1.0) / 2.0 * size.x as f32,
+            (1.0 - clip.y) / 2.0 * size.y as f32,
+        )
+    }
+    /// Converts pixels of the target, from its top-left corner with Y down, to a point of the
+    /// world.
+    pub fn screen_to_world(
+        &self,
+        view: &Transform2<f32>,
+        size: Vector2<u32>,
+        point: &Point2<f32>,
+    ) -> Point2<f32> {
+        let clip = Point2::new(
+            point.x / size.x as f32 * 2.0 - 1.0,
+            1.0 - point.y / size.y as f32 * 2.0,
+        );
+        let inverse = self
+            .view_projection(view, size)
+            .try_inverse()
+            .unwrap_or_else(Matrix3::identity);
+        inverse.transform_point(&clip)
+    }
+}
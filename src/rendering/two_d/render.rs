@@ -1,103 +1,543 @@
 use crate::ecs::components::Transform2;
-use crate::ecs::entity::Entity;
-use crate::rendering::camera::{Camera2d, Vertex};
-use crate::rendering::sprite::Sprite;
-use image::{ImageBuffer, Rgba};
-use std::collections::hash_map::{DefaultHasher, Entry};
+use crate::ecs::entity::EntityRef;
+use crate::rendering::camera::{SpriteInstance, Vertex};
+use crate::rendering::material::MaterialCache;
+use crate::rendering::shader::PipelineCache;
+use crate::rendering::sprite::{Sprite, UvRect};
+use crate::rendering::stats::RenderStats;
+use crate::rendering::texture::{TextureCache, TextureHandle};
+use bytemuck::{Pod, Zeroable};
+use nalgebra::{Matrix3, Matrix4, Vector2};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-use std::sync::RwLockReadGuard;
+use std::ops::Range;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
-    BlendState, ColorTargetState, ColorWrites, Face, FragmentState, FrontFace, MultisampleState,
-    PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipelineDescriptor,
-    ShaderModuleDescriptor, ShaderSource, VertexState,
+    BindGroup, BindGroupLayout, Buffer, Color, ColorTargetState, ColorWrites, Device,
+    FragmentState, FrontFace, MultisampleState, PolygonMode, PrimitiveState, PrimitiveTopology,
+    Queue, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, TextureFormat,
+    TextureView, VertexState,
 };
 
 pub struct RenderObject<'a> {
     pub pipeline: u64,
-    pub texture: ImageBuffer<Rgba<f32>, Vec<f32>>,
+    pub texture: TextureHandle,
+    /// Size of the sprite, in pixels of its texture.
+    pub size: Vector2<f32>,
     pub transform: Option<&'a Transform2<f32>>,
+    pub region: UvRect,
+    pub tint: Color,
+    pub layer: u8,
+    pub z: f32,
+    /// Key of the material's bind group in the [`MaterialCache`], if it has bindings.
+    pub material: Option<u64>,
+    /// Extra textures the material samples.
+    pub samples: &'a [TextureHandle],
 }
 impl<'a> RenderObject<'a> {
+    /// Collects the [`Sprite`]s of `entities`, uploading their textures and creating the
+    /// material bind groups and pipelines that aren't in `textures`, `materials` and
+    /// `pipelines` yet.
+    ///
+    /// Sprites with an empty texture, whose material has textures that aren't in `textures`, or
+    /// whose shader doesn't load, are left out.
+    #[allow(clippy::too_many_arguments)]
     pub fn from_entities(
-        entities: &'a Vec<RwLockReadGuard<Entity>>,
-        camera: &mut Camera2d,
+        entities: &'a [EntityRef],
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        textures: &mut TextureCache,
+        materials: &mut MaterialCache,
+        camera_layout: &BindGroupLayout,
+        pipelines: &mut PipelineCache,
     ) -> Vec<RenderObject<'a>> {
         let mut sprites = Vec::new();
         #[allow(clippy::needless_range_loop)] // Must be a range to avoid creating a new variable
         for i in 0..entities.len() {
-            sprites.push((
-                entities[i].get_component::<Sprite>().unwrap(),
-                entities[i].get_component::<Transform2<f32>>(),
-            ));
+            if let Some(sprite) = entities[i].get_component::<Sprite>() {
+                sprites.push((sprite, entities[i].get_component::<Transform2<f32>>()));
+            }
         }
         sprites
             .iter()
-            .map(|(sprite, transform)| {
+            .filter_map(|(sprite, transform)| {
+                // Textures can't be empty, and such sprites would be invisible anyway
+                if sprite.texture.width() == 0 || sprite.texture.height() == 0 {
+                    return None;
+                }
+                let material = sprite.material();
+                let samples = material.textures();
+                let label = material.label();
+                let material_key = sprite.material_key();
+                let material_layout = material_key.layout;
                 let mut hasher = DefaultHasher::new();
-                sprite.shader.hash(&mut hasher);
-                sprite.shader_label.hash(&mut hasher);
-                let shader_hash = hasher.finish();
-                let pipeline = if let Entry::Vacant(e) = camera.pipelines.entry(shader_hash) {
-                    let shader = camera.device.create_shader_module(ShaderModuleDescriptor {
-                        label: Some(sprite.shader_label.as_str()),
-                        source: ShaderSource::Wgsl(sprite.shader.as_str().into()),
+                material_key.pipeline.hash(&mut hasher);
+                format.hash(&mut hasher);
+                let pipeline = hasher.finish();
+                if !pipelines.contains(pipeline) {
+                    #[cfg(feature = "tracing")]
+                    let _span = tracing::info_span!("create_pipeline", label = %label).entered();
+                    let source = match pipelines.library.load_sprite_shader(
+                        label,
+                        material.shader(),
+                        material_layout,
+                    ) {
+                        Ok(source) => source,
+                        Err(e) => {
+                            log::error!("couldn't load shader: {e}");
+                            pipelines.fail(pipeline);
+                            return None;
+                        }
+                    };
+                    let shader = device.create_shader_module(ShaderModuleDescriptor {
+                        label: Some(label),
+                        source: ShaderSource::Wgsl(source.into()),
                     });
+                    let mut bind_group_layouts = vec![textures.layout(), camera_layout];
+                    if let Some(material_layout) = material_layout {
+                        bind_group_layouts.push(materials.layout(device, material_layout));
+                    }
                     let render_pipeline_layout =
-                        camera
-                            .device
-                            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                                label: Some(sprite.shader_label.as_str()),
-                                bind_group_layouts: &[],
-                                push_constant_ranges: &[],
-                            });
+                        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                            label: Some(label),
+                            bind_group_layouts: &bind_group_layouts,
+                            push_constant_ranges: &[],
+                        });
                     let render_pipeline =
-                        camera
-                            .device
-                            .create_render_pipeline(&RenderPipelineDescriptor {
-                                label: Some(sprite.shader_label.as_str()),
-                                layout: Some(&render_pipeline_layout),
-                                vertex: VertexState {
-                                    module: &shader,
-                                    entry_point: "vs_main",
-                                    buffers: &[Vertex::desc()],
-                                },
-                                fragment: Some(FragmentState {
-                                    module: &shader,
-                                    entry_point: "fs_main",
-                                    targets: &[Some(ColorTargetState {
-                                        format: camera.config.format,
-                                        blend: Some(BlendState::ALPHA_BLENDING),
-                                        write_mask: ColorWrites::ALL,
-                                    })],
-                                }),
-                                primitive: PrimitiveState {
-                                    topology: PrimitiveTopology::TriangleList,
-                                    strip_index_format: None,
-                                    front_face: FrontFace::Ccw,
-                                    cull_mode: Some(Face::Back),
-                                    polygon_mode: PolygonMode::Fill,
-                                    unclipped_depth: false,
-                                    conservative: false,
-                                },
-                                depth_stencil: None,
-                                multisample: MultisampleState {
-                                    count: 1,
-                                    mask: !0,
-                                    alpha_to_coverage_enabled: false,
-                                },
-                                multiview: None,
-                            });
-                    e.insert(render_pipeline);
-                    shader_hash
-                } else {
-                    shader_hash
+                        device.create_render_pipeline(&RenderPipelineDescriptor {
+                            label: Some(label),
+                            layout: Some(&render_pipeline_layout),
+                            vertex: VertexState {
+                                module: &shader,
+                                entry_point: "vs_main",
+                                buffers: &[Vertex::desc(), SpriteInstance::desc()],
+                            },
+                            fragment: Some(FragmentState {
+                                module: &shader,
+                                entry_point: "fs_main",
+                                targets: &[Some(ColorTargetState {
+                                    format,
+                                    blend: material.blend().to_wgpu(),
+                                    write_mask: ColorWrites::ALL,
+                                })],
+                            }),
+                            primitive: PrimitiveState {
+                                topology: PrimitiveTopology::TriangleList,
+                                strip_index_format: None,
+                                front_face: FrontFace::Ccw,
+                                // Sprites are mirrored with negative scales
+                                cull_mode: None,
+                                polygon_mode: PolygonMode::Fill,
+                                unclipped_depth: false,
+                                conservative: false,
+                            },
+                            depth_stencil: None,
+                            multisample: MultisampleState {
+                                count: 1,
+                                mask: !0,
+                                alpha_to_coverage_enabled: false,
+                            },
+                            multiview: None,
+                        });
+                    pipelines.insert(pipeline, render_pipeline);
+                }
+                pipelines.get(pipeline)?;
+                let size = textures
+                    .prepare(
+                        device,
+                        queue,
+                        sprite.handle,
+                        sprite.version(),
+                        sprite.texture.as_ref(),
+                    )
+                    .size;
+                let material = match material_layout {
+                    Some(layout) => {
+                        Some(materials.prepare(device, queue, textures, layout, material)?)
+                    }
+                    None => None,
                 };
-                RenderObject {
+                Some(RenderObject {
                     pipeline,
-                    texture: sprite.texture.complete(),
+                    texture: sprite.handle,
+                    size: size.cast::<f32>().component_mul(&sprite.region.size),
                     transform: *transform,
-                }
+                    region: sprite.region,
+                    tint: sprite.tint,
+                    layer: sprite.layer,
+                    z: sprite.z,
+                    material,
+                    samples,
+                })
             })
             .collect()
     }
+    /// Instance data of the sprite, mapping the unit quad to the world.
+    ///
+    /// Sprites are as many units wide and high as their region has pixels.
+    pub fn instance(&self) -> SpriteInstance {
+        let transform = self.transform.copied().unwrap_or_default().to_matrix();
+        let matrix = transform * Matrix3::new_nonuniform_scaling(&self.size);
+        let tint = self.tint;
+        SpriteInstance {
+            x_axis: matrix.fixed_slice::<2, 1>(0, 0).into(),
+            y_axis: matrix.fixed_slice::<2, 1>(0, 1).into(),
+            translation: matrix.fixed_slice::<2, 1>(0, 2).into(),
+            uv_min: self.region.min,
+            uv_size: self.region.size,
+            tint: [tint.r, tint.g, tint.b, tint.a].map(|c| c as f32),
+        }
+    }
+}
+
+/// The unit quad sprites are drawn from, as two triangles.
+fn quad() -> [Vertex; 6] {
+    let vertex = |x: f32, y: f32| Vertex {
+        position: Vector2::new(x, y),
+        uv: Vector2::new(x + 0.5, 0.5 - y),
+    };
+    let (bottom_left, bottom_right) = (vertex(-0.5, -0.5), vertex(0.5, -0.5));
+    let (top_right, top_left) = (vertex(0.5, 0.5), vertex(-0.5, 0.5));
+    [
+        bottom_left,
+        bottom_right,
+        top_right,
+        bottom_left,
+        top_right,
+        top_left,
+    ]
+}
+
+/// Key in the [`PipelineCache`] of the pipeline clearing viewports of targets in `format`.
+fn clear_pipeline_key(format: TextureFormat) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    "clear".hash(&mut hasher);
+    format.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Creates the pipeline [`draw`] clears viewports that don't cover the whole target with, for
+/// targets in `format`, unless `pipelines` already has it.
+///
+/// It draws a triangle over the viewport whose color is the blend constant.
+pub fn create_clear_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    pipelines: &mut PipelineCache,
+) {
+    let key = clear_pipeline_key(format);
+    if pipelines.contains(key) {
+        return;
+    }
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("Clear"),
+        source: ShaderSource::Wgsl(include_str!("shaders/clear.wgsl").into()),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Clear"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+    let constant = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Constant,
+        dst_factor: wgpu::BlendFactor::Zero,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("Clear"),
+        layout: Some(&layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState {
+                    color: constant,
+                    alpha: constant,
+                }),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        multiview: None,
+    });
+    pipelines.insert(key, pipeline);
+}
+
+/// Vertex buffers sprites are drawn from, kept across frames: the unit quad, and the instances
+/// of the last draw, growing as needed.
+#[derive(Debug)]
+pub struct SpriteBuffers {
+    quad: Buffer,
+    instances: Buffer,
+    capacity: usize,
+}
+impl SpriteBuffers {
+    pub fn new(device: &Device) -> Self {
+        let quad = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Sprite Quad"),
+            contents: bytemuck::cast_slice(&quad()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        Self {
+            quad,
+            instances: Self::create_instances(device, 64),
+            capacity: 64,
+        }
+    }
+    fn create_instances(device: &Device, capacity: usize) -> Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Instances"),
+            size: (capacity * std::mem::size_of::<SpriteInstance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+    /// Replaces the instances, before the next submission. Draws that were already submitted
+    /// keep the instances they were recorded with.
+    fn write(&mut self, device: &Device, queue: &Queue, instances: &[SpriteInstance]) {
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.instances = Self::create_instances(device, self.capacity);
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(instances));
+        }
+    }
+    #[cfg(test)]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// The view-projection matrix sprite shaders get in bind group 1.
+#[derive(Debug)]
+pub struct CameraUniform {
+    buffer: Buffer,
+    bind_group: BindGroup,
+}
+impl CameraUniform {
+    pub fn create_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Camera"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+    pub fn new(device: &Device, layout: &BindGroupLayout) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Camera"),
+            size: std::mem::size_of::<CameraData>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self { buffer, bind_group }
+    }
+    /// Writes a 2D homogeneous matrix as the `mat4x4<f32>` shaders get, and the time.
+    fn write(&self, queue: &Queue, view_projection: &Matrix3<f32>, time: f32) {
+        let mut matrix = Matrix4::identity();
+        matrix
+            .fixed_slice_mut::<2, 2>(0, 0)
+            .copy_from(&view_projection.fixed_slice::<2, 2>(0, 0));
+        matrix
+            .fixed_slice_mut::<2, 1>(0, 3)
+            .copy_from(&view_projection.fixed_slice::<2, 1>(0, 2));
+        let data = CameraData {
+            view_projection: matrix.into(),
+            time,
+            padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&data));
+    }
+}
+
+/// The `Camera` struct of sprite shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct CameraData {
+    view_projection: [[f32; 4]; 4],
+    time: f32,
+    padding: [f32; 3],
+}
+unsafe impl Zeroable for CameraData {}
+unsafe impl Pod for CameraData {}
+
+/// Where and how to draw a frame.
+pub struct RenderTarget<'a> {
+    pub view: &'a TextureView,
+    pub format: TextureFormat,
+    /// Size of the whole target, in pixels.
+    pub size: Vector2<u32>,
+    /// Color to clear the viewport to first, if any.
+    pub clear_color: Option<Color>,
+    /// Offset and size in pixels of the part of the target drawn to.
+    pub viewport: (Vector2<u32>, Vector2<u32>),
+    pub camera: &'a CameraUniform,
+    /// Maps world coordinates to clip space.
+    pub view_projection: Matrix3<f32>,
+    /// Sorts objects of the same layer and z by decreasing `y`.
+    pub y_sort: bool,
+    /// Keeps objects at the same depth in the order they are given, instead of grouping them
+    /// into fewer batches.
+    pub keep_order: bool,
+    /// Seconds since the game started, for animated shaders.
+    pub time: f32,
+}
+
+/// Consecutive objects drawn with a single instanced draw call.
+struct Batch {
+    pipeline: u64,
+    texture: TextureHandle,
+    material: Option<u64>,
+    instances: Range<u32>,
+}
+
+/// Clears the viewport of `target` if asked to, and draws `objects` in it.
+///
+/// Viewports that don't cover the whole target are cleared with the pipeline of
+/// [`create_clear_pipeline`], so that cameras sharing a target don't clear each other.
+///
+/// Objects are sorted back to front by layer, z and, if `target.y_sort` is set, decreasing `y`.
+/// Objects at the same depth are then sorted by pipeline, texture and material, unless
+/// `target.keep_order` is set. Each run of consecutive objects sharing a pipeline, texture and
+/// material is drawn with a single instanced draw call.
+#[allow(clippy::too_many_arguments)]
+pub fn draw(
+    device: &Device,
+    queue: &Queue,
+    textures: &TextureCache,
+    materials: &MaterialCache,
+    pipelines: &PipelineCache,
+    buffers: &mut SpriteBuffers,
+    target: RenderTarget,
+    objects: &[&RenderObject],
+) -> RenderStats {
+    let mut sorted: Vec<_> = objects
+        .iter()
+        .filter(|object| textures.get(object.texture).is_some())
+        .copied()
+        .collect();
+    let y = |object: &RenderObject| object.transform.map_or(0.0, |t| t.position.y);
+    sorted.sort_by(|a, b| {
+        a.layer
+            .cmp(&b.layer)
+            .then(a.z.total_cmp(&b.z))
+            .then(match target.y_sort {
+                true => y(b).total_cmp(&y(a)),
+                false => Ordering::Equal,
+            })
+            .then(match target.keep_order {
+                true => Ordering::Equal,
+                false => {
+                    (a.pipeline, a.texture, a.material).cmp(&(b.pipeline, b.texture, b.material))
+                }
+            })
+    });
+    let instances: Vec<_> = sorted.iter().map(|o| o.instance()).collect();
+    target
+        .camera
+        .write(queue, &target.view_projection, target.time);
+    let mut batches: Vec<Batch> = Vec::new();
+    for (i, object) in sorted.iter().enumerate() {
+        let i = i as u32;
+        match batches.last_mut() {
+            Some(batch)
+                if batch.pipeline == object.pipeline
+                    && batch.texture == object.texture
+                    && batch.material == object.material =>
+            {
+                batch.instances.end = i + 1
+            }
+            _ => batches.push(Batch {
+                pipeline: object.pipeline,
+                texture: object.texture,
+                material: object.material,
+                instances: i..i + 1,
+            }),
+        }
+    }
+
+    buffers.write(device, queue, &instances);
+    let (offset, size) = target.viewport;
+    let covers_target = offset == Vector2::zeros() && size == target.size;
+    let clear_viewport = target.clear_color.filter(|_| !covers_target);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Render Encoder"),
+    });
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: match target.clear_color {
+                        Some(color) if covers_target => wgpu::LoadOp::Clear(color),
+                        _ => wgpu::LoadOp::Load,
+                    },
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_viewport(
+            offset.x as f32,
+            offset.y as f32,
+            size.x as f32,
+            size.y as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_scissor_rect(offset.x, offset.y, size.x, size.y);
+        if let Some(color) = clear_viewport {
+            let clear = pipelines.get(clear_pipeline_key(target.format)).unwrap();
+            render_pass.set_pipeline(clear);
+            render_pass.set_blend_constant(color);
+            render_pass.draw(0..3, 0..1);
+        }
+        render_pass.set_bind_group(1, &target.camera.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffers.quad.slice(..));
+        render_pass.set_vertex_buffer(1, buffers.instances.slice(..));
+        for batch in &batches {
+            render_pass.set_pipeline(pipelines.get(batch.pipeline).unwrap());
+            let texture = textures.get(batch.texture).unwrap();
+            render_pass.set_bind_group(0, &texture.bind_group, &[]);
+            if let Some(material) = batch.material {
+                render_pass.set_bind_group(2, materials.get(material).unwrap(), &[]);
+            }
+            render_pass.draw(0..6, batch.instances.clone());
+        }
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+    RenderStats {
+        sprites: instances.len() as u64,
+        batches: batches.len() as u64,
+        draw_calls: batches.len() as u64 + clear_viewport.is_some() as u64,
+        texture_uploads: 0,
+    }
 }
@@ -0,0 +1,614 @@
+use crate::ecs::entity::EntityRef;
+use crate::error::Result;
+use crate::rendering::camera::{Camera2d, CameraTarget};
+use crate::rendering::material::MaterialCache;
+use crate::rendering::screenshot::{FrameSaver, Readback};
+use crate::rendering::shader::PipelineCache;
+use crate::rendering::stats::RenderStats;
+use crate::rendering::texture::{RenderTexture, TextureCache, TextureHandle};
+use crate::rendering::two_d::render::{
+    self, CameraUniform, RenderObject, RenderTarget, SpriteBuffers,
+};
+use crate::window::WindowId;
+use image::RgbaImage;
+use nalgebra::{Matrix3, Vector2};
+use raw_window_handle::HasRawWindowHandle;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::task::Poll;
+use wgpu::{
+    Adapter, Backends, BindGroupLayout, BufferAsyncError, Color, Device, DeviceDescriptor,
+    Features, Instance, Queue, RequestAdapterOptions, RequestDeviceError, Surface,
+    SurfaceConfiguration, SurfaceError, SurfaceTexture, TextureFormat, TextureView,
+    TextureViewDescriptor,
+};
+
+/// Identifies a window the [`Renderer`] draws to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SurfaceId(u32);
+
+#[derive(Copy, Clone, Debug)]
+pub struct RendererOptions {
+    /// Wait for the display's refresh before presenting frames.
+    pub vsync: bool,
+    pub power_preference: wgpu::PowerPreference,
+    /// Use a software adapter, to render without a GPU such as in tests.
+    pub force_fallback_adapter: bool,
+}
+impl Default for RendererOptions {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    /// No GPU adapter could be found, or none able to draw to the window.
+    NoAdapter,
+    Device(RequestDeviceError),
+    /// A camera targets a window that isn't, or no longer is, in the renderer.
+    UnknownSurface(SurfaceId),
+    /// A texture that isn't a render target of the renderer was rendered to or read back.
+    UnknownTexture(TextureHandle),
+    /// Reading a texture back failed.
+    Readback(BufferAsyncError),
+    /// Saving a screenshot or captured frame failed.
+    Capture(image::ImageError),
+}
+impl Display for RenderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::NoAdapter => write!(f, "no suitable GPU adapter found"),
+            RenderError::Device(e) => write!(f, "couldn't get a GPU device: {e}"),
+            RenderError::UnknownSurface(id) => write!(f, "no window surface {}", id.0),
+            RenderError::UnknownTexture(handle) => write!(f, "no render target {handle:?}"),
+            RenderError::Readback(e) => write!(f, "couldn't read a texture back: {e}"),
+            RenderError::Capture(e) => write!(f, "couldn't save a captured frame: {e}"),
+        }
+    }
+}
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Device(e) => Some(e),
+            RenderError::Readback(e) => Some(e),
+            RenderError::Capture(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct WindowSurface {
+    /// The window the surface draws to, whose
+    /// [`WindowEvent`](crate::window::WindowEvent)s it follows.
+    window: WindowId,
+    /// `None` for the windows tests add without a GPU surface, which are never drawn to.
+    surface: Option<Surface>,
+    config: SurfaceConfiguration,
+    scale_factor: f64,
+    /// The texture being drawn to this frame, once a camera targets the window.
+    frame: Option<(SurfaceTexture, TextureView)>,
+}
+impl WindowSurface {
+    /// Acquires the next surface texture, if there isn't one already.
+    ///
+    /// A lost or outdated surface is reconfigured and acquired again; `None` means the surface
+    /// timed out (for instance while the window is minimized) and the frame should be skipped.
+    fn acquire(&mut self, device: &Device) -> Result<Option<&TextureView>, SurfaceError> {
+        let Some(surface) = &self.surface else {
+            return Ok(None);
+        };
+        if self.frame.is_none() {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("acquire").entered();
+            let output = match surface.get_current_texture() {
+                Ok(output) => output,
+                Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+                    surface.configure(device, &self.config);
+                    match surface.get_current_texture() {
+                        Ok(output) => output,
+                        Err(SurfaceError::Timeout) => return Ok(None),
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(SurfaceError::Timeout) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let view = output
+                .texture
+                .create_view(&TextureViewDescriptor::default());
+            self.frame = Some((output, view));
+        }
+        Ok(self.frame.as_ref().map(|(_, view)| view))
+    }
+}
+
+/// The GPU device, and everything shared by the cameras drawing with it: window surfaces,
+/// pipelines, textures and render targets.
+///
+/// Insert it as a resource for [`render_2d`](crate::rendering::render_2d) to render every
+/// [`Camera2d`].
+///
+/// ## Usage
+/// ```rust,no_run
+/// # use goosberry::ecs::entity::Entity;
+/// # use goosberry::ecs::world::World;
+/// # use goosberry::rendering::camera::{Camera2d, CameraTarget};
+/// # use goosberry::rendering::renderer::{Renderer, RendererOptions};
+/// # use goosberry::window::WindowId;
+/// # use nalgebra::Vector2;
+/// # use winit::event_loop::EventLoop;
+/// # use winit::window::WindowBuilder;
+/// # async fn run() {
+/// let event_loop = EventLoop::new();
+/// let window = WindowBuilder::new().build(&event_loop).unwrap();
+/// let size = Vector2::new(window.inner_size().width, window.inner_size().height);
+/// let id = WindowId(window.id().into());
+/// let (renderer, surface) = Renderer::for_window(&window, id, size, RendererOptions::default())
+///     .await
+///     .unwrap();
+/// let mut world = World::default();
+/// world.insert_resource(renderer);
+/// let mut camera = Entity::default();
+/// camera.add_component(Camera2d::new(CameraTarget::Window(surface)));
+/// world.add_entity(camera);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Renderer {
+    instance: Instance,
+    adapter: Adapter,
+    pub device: Device,
+    pub queue: Queue,
+    /// Pipelines of the materials drawn, with the shader files their shaders can include.
+    pub pipelines: PipelineCache,
+    pub textures: TextureCache,
+    pub materials: MaterialCache,
+    /// Seconds shaders get as `camera.time`, set from the [`Time`](crate::ecs::time::Time) resource by
+    /// [`render_2d`](crate::rendering::render_2d).
+    pub time: f32,
+    options: RendererOptions,
+    camera_layout: BindGroupLayout,
+    /// One per camera rendered in a frame.
+    camera_uniforms: Vec<CameraUniform>,
+    sprite_buffers: SpriteBuffers,
+    surfaces: HashMap<SurfaceId, WindowSurface>,
+    next_surface: u32,
+    /// Frames being copied back, and the files to save them to.
+    captures: Vec<(Readback, Vec<PathBuf>)>,
+    saver: FrameSaver,
+}
+impl Renderer {
+    /// A renderer without any window, drawing to render targets only until one is added.
+    pub async fn new(options: RendererOptions) -> Result<Self> {
+        let instance = Instance::new(Backends::all());
+        Self::with_instance(instance, None, options).await
+    }
+    /// A renderer with an adapter able to draw to `window`, and the window added to it under
+    /// `id`.
+    pub async fn for_window<W: HasRawWindowHandle>(
+        window: &W,
+        id: WindowId,
+        size: Vector2<u32>,
+        options: RendererOptions,
+    ) -> Result<(Self, SurfaceId)> {
+        let instance = Instance::new(Backends::all());
+        let surface = unsafe { instance.create_surface(window) };
+        let mut renderer = Self::with_instance(instance, Some(&surface), options).await?;
+        let surface = renderer.add_surface(surface, id, size);
+        Ok((renderer, surface))
+    }
+    async fn with_instance(
+        instance: Instance,
+        surface: Option<&Surface>,
+        options: RendererOptions,
+    ) -> Result<Self> {
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: options.power_preference,
+                force_fallback_adapter: options.force_fallback_adapter,
+                compatible_surface: surface,
+            })
+            .await
+            .ok_or(RenderError::NoAdapter)?;
+        let (device, queue) = adapter
+            .request_device(
+                &DeviceDescriptor {
+                    label: None,
+                    limits: if cfg!(target_arch = "wasm32") {
+                        wgpu::Limits::downlevel_webgl2_defaults()
+                    } else {
+                        wgpu::Limits::default()
+                    }
+                    .using_resolution(adapter.limits()),
+                    features: Features::empty(),
+                },
+                None,
+            )
+            .await
+            .map_err(RenderError::Device)?;
+        let textures = TextureCache::new(&device);
+        let camera_layout = CameraUniform::create_layout(&device);
+        let sprite_buffers = SpriteBuffers::new(&device);
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            pipelines: PipelineCache::new(),
+            textures,
+            materials: MaterialCache::new(),
+            time: 0.0,
+            options,
+            camera_layout,
+            camera_uniforms: Vec::new(),
+            sprite_buffers,
+            surfaces: HashMap::new(),
+            next_surface: 0,
+            captures: Vec::new(),
+            saver: FrameSaver::default(),
+        })
+    }
+    /// Adds a window to draw to, `size` pixels large, following the
+    /// [`WindowEvent`](crate::window::WindowEvent)s sent for `id`.
+    pub fn add_window<W: HasRawWindowHandle>(
+        &mut self,
+        window: &W,
+        id: WindowId,
+        size: Vector2<u32>,
+    ) -> SurfaceId {
+        let surface = unsafe { self.instance.create_surface(window) };
+        self.add_surface(surface, id, size)
+    }
+    fn add_surface(&mut self, surface: Surface, window: WindowId, size: Vector2<u32>) -> SurfaceId {
+        let config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface.get_supported_formats(&self.adapter)[0],
+            width: size.x.max(1),
+            height: size.y.max(1),
+            present_mode: match self.options.vsync {
+                true => wgpu::PresentMode::Fifo,
+                false => wgpu::PresentMode::Immediate,
+            },
+        };
+        surface.configure(&self.device, &config);
+        self.insert_surface(Some(surface), window, config)
+    }
+    /// Adds a window `size` pixels large without a GPU surface, that cameras targeting it
+    /// skip, to test how windows are tracked.
+    #[cfg(test)]
+    pub(crate) fn add_test_window(&mut self, window: WindowId, size: Vector2<u32>) -> SurfaceId {
+        let config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: size.x.max(1),
+            height: size.y.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        self.insert_surface(None, window, config)
+    }
+    fn insert_surface(
+        &mut self,
+        surface: Option<Surface>,
+        window: WindowId,
+        config: SurfaceConfiguration,
+    ) -> SurfaceId {
+        let id = SurfaceId(self.next_surface);
+        self.next_surface += 1;
+        self.surfaces.insert(
+            id,
+            WindowSurface {
+                window,
+                surface,
+                config,
+                scale_factor: 1.0,
+                frame: None,
+            },
+        );
+        id
+    }
+    /// Stops drawing to a window, which must be done before it is closed.
+    pub fn remove_window(&mut self, id: SurfaceId) {
+        self.surfaces.remove(&id);
+    }
+    /// The first window added that is still there.
+    pub fn primary_window(&self) -> Option<SurfaceId> {
+        self.surfaces.keys().min().copied()
+    }
+    /// The surface drawing to `window`, if it was added.
+    pub fn surface(&self, window: WindowId) -> Option<SurfaceId> {
+        self.surfaces
+            .iter()
+            .find(|(_, surface)| surface.window == window)
+            .map(|(id, _)| *id)
+    }
+    pub fn window_size(&self, id: SurfaceId) -> Option<Vector2<u32>> {
+        let surface = self.surfaces.get(&id)?;
+        Some(Vector2::new(surface.config.width, surface.config.height))
+    }
+    pub fn resize(&mut self, id: SurfaceId, size: Vector2<u32>) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            if size.x > 0 && size.y > 0 {
+                surface.config.width = size.x;
+                surface.config.height = size.y;
+                if let Some(gpu_surface) = &surface.surface {
+                    gpu_surface.configure(&self.device, &surface.config);
+                }
+            }
+        }
+    }
+    /// Physical pixels per logical pixel of a window.
+    pub fn scale_factor(&self, id: SurfaceId) -> Option<f64> {
+        self.surfaces.get(&id).map(|surface| surface.scale_factor)
+    }
+    pub fn set_scale_factor(&mut self, id: SurfaceId, scale_factor: f64) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.scale_factor = scale_factor;
+        }
+    }
+    /// Creates a texture `size` pixels large that cameras can render to with
+    /// [`CameraTarget::Texture`], and sprites can draw.
+    pub fn create_render_texture(&mut self, size: Vector2<u32>) -> RenderTexture {
+        let handle = TextureHandle::new();
+        let size = size.map(|c| c.max(1));
+        self.textures
+            .create_render_target(&self.device, handle, size);
+        RenderTexture { handle, size }
+    }
+    pub fn remove_render_texture(&mut self, texture: RenderTexture) {
+        self.textures.remove(texture.handle);
+    }
+    /// Copies the pixels of a render target back from the GPU, once everything submitted so
+    /// far has been drawn.
+    pub async fn read_texture(&self, handle: TextureHandle) -> Result<RgbaImage> {
+        let texture = self
+            .textures
+            .get(handle)
+            .filter(|texture| texture.is_render_target())
+            .ok_or(RenderError::UnknownTexture(handle))?;
+        let readback = Readback::start(&self.device, &self.queue, &texture.texture, texture.size);
+        // Waits for the copy on native, and does nothing on the web where the browser maps
+        // the buffer
+        self.device.poll(wgpu::Maintain::Wait);
+        let image = std::future::poll_fn(|cx| readback.poll(Some(cx)))
+            .await
+            .map_err(RenderError::Readback)?;
+        Ok(image)
+    }
+    /// Renders the [`Sprite`](crate::rendering::sprite::Sprite)s of `entities` with every
+    /// camera, by order, then presents the windows drawn to.
+    ///
+    /// A camera rendering to a texture doesn't draw the sprites showing that texture, and
+    /// should come before the cameras that do.
+    pub fn render(
+        &mut self,
+        cameras: &mut [&mut Camera2d],
+        entities: &[EntityRef],
+    ) -> Result<RenderStats> {
+        let uploads = self.textures.uploads();
+        cameras.sort_by_key(|camera| camera.order);
+        while self.camera_uniforms.len() < cameras.len() {
+            let uniform = CameraUniform::new(&self.device, &self.camera_layout);
+            self.camera_uniforms.push(uniform);
+        }
+        let mut stats = RenderStats::default();
+        let mut objects = FrameObjects::new();
+        for (index, camera) in cameras.iter_mut().enumerate() {
+            let offscreen;
+            let (view, format, size) = match camera.target {
+                CameraTarget::Window(id) => {
+                    let Some(surface) = self.surfaces.get_mut(&id) else {
+                        // Its window may have closed before the camera was despawned
+                        log::warn!("skipping a camera: {}", RenderError::UnknownSurface(id));
+                        continue;
+                    };
+                    let format = surface.config.format;
+                    let size = Vector2::new(surface.config.width, surface.config.height);
+                    // Follows the window even through frames that are skipped
+                    camera.set_pixels(camera.viewport.pixels(size));
+                    let Some(view) = surface.acquire(&self.device)? else {
+                        continue;
+                    };
+                    (view, format, size)
+                }
+                CameraTarget::Texture(handle) => {
+                    let texture = self
+                        .textures
+                        .get(handle)
+                        .filter(|texture| texture.is_render_target())
+                        .ok_or(RenderError::UnknownTexture(handle))?;
+                    offscreen = texture
+                        .texture
+                        .create_view(&TextureViewDescriptor::default());
+                    (&offscreen, texture.format.to_wgpu(), texture.size)
+                }
+            };
+            let viewport = camera.viewport.pixels(size);
+            camera.set_pixels(viewport);
+            if camera.clear_color.is_some() && viewport != (Vector2::zeros(), size) {
+                render::create_clear_pipeline(&self.device, format, &mut self.pipelines);
+            }
+            let visible: Vec<_> = objects
+                .entry(format)
+                .or_insert_with(|| {
+                    RenderObject::from_entities(
+                        entities,
+                        &self.device,
+                        &self.queue,
+                        format,
+                        &mut self.textures,
+                        &mut self.materials,
+                        &self.camera_layout,
+                        &mut self.pipelines,
+                    )
+                })
+                .iter()
+                .filter(|object| shows(camera, object))
+                .collect();
+            let view_projection = camera.projection.view_projection(&camera.view, viewport.1);
+            stats += render::draw(
+                &self.device,
+                &self.queue,
+                &self.textures,
+                &self.materials,
+                &self.pipelines,
+                &mut self.sprite_buffers,
+                RenderTarget {
+                    view,
+                    format,
+                    size,
+                    clear_color: camera.clear_color,
+                    viewport,
+                    camera: &self.camera_uniforms[index],
+                    view_projection,
+                    y_sort: camera.y_sort,
+                    keep_order: camera.keep_order,
+                    time: self.time,
+                },
+                &visible,
+            );
+            let paths = camera.take_screenshots();
+            if !paths.is_empty() {
+                stats += self.capture(
+                    camera,
+                    &mut objects,
+                    entities,
+                    index,
+                    view_projection,
+                    paths,
+                );
+            }
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("present").entered();
+        for surface in self.surfaces.values_mut() {
+            if let Some((output, _)) = surface.frame.take() {
+                output.present();
+            }
+        }
+        stats.texture_uploads = self.textures.uploads() - uploads;
+        self.textures.end_frame();
+        self.materials.end_frame();
+        if let Err(e) = self.save_captures(false) {
+            log::error!("{e}");
+        }
+        Ok(stats)
+    }
+    /// Renders `camera` again into a texture as large as its viewport, to save it to `paths`
+    /// once copied back. Only what the camera draws itself is captured, on a transparent
+    /// background if it has no clear color.
+    fn capture<'a>(
+        &mut self,
+        camera: &Camera2d,
+        objects: &mut FrameObjects<'a>,
+        entities: &'a [EntityRef],
+        uniform: usize,
+        view_projection: Matrix3<f32>,
+        paths: Vec<PathBuf>,
+    ) -> RenderStats {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("capture").entered();
+        let size = camera.size();
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture"),
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let visible: Vec<_> = objects
+            .entry(format)
+            .or_insert_with(|| {
+                RenderObject::from_entities(
+                    entities,
+                    &self.device,
+                    &self.queue,
+                    format,
+                    &mut self.textures,
+                    &mut self.materials,
+                    &self.camera_layout,
+                    &mut self.pipelines,
+                )
+            })
+            .iter()
+            .filter(|object| shows(camera, object))
+            .collect();
+        let stats = render::draw(
+            &self.device,
+            &self.queue,
+            &self.textures,
+            &self.materials,
+            &self.pipelines,
+            &mut self.sprite_buffers,
+            RenderTarget {
+                view: &view,
+                format,
+                size,
+                clear_color: Some(camera.clear_color.unwrap_or(Color::TRANSPARENT)),
+                viewport: (Vector2::zeros(), size),
+                camera: &self.camera_uniforms[uniform],
+                view_projection,
+                y_sort: camera.y_sort,
+                keep_order: camera.keep_order,
+                time: self.time,
+            },
+            &visible,
+        );
+        let readback = Readback::start(&self.device, &self.queue, &texture, size);
+        self.captures.push((readback, paths));
+        stats
+    }
+    /// Queues the frames that were copied back to be saved, and collects the saves that are
+    /// done, or waits for everything if `wait` is true.
+    fn save_captures(&mut self, wait: bool) -> Result<()> {
+        self.device.poll(match wait {
+            true => wgpu::Maintain::Wait,
+            false => wgpu::Maintain::Poll,
+        });
+        let mut result = Ok(());
+        let mut copying = Vec::new();
+        for (readback, paths) in self.captures.drain(..) {
+            match readback.poll(None) {
+                Poll::Ready(Ok(image)) => self.saver.save(image, paths),
+                Poll::Ready(Err(e)) => result = Err(RenderError::Readback(e)),
+                Poll::Pending => copying.push((readback, paths)),
+            }
+        }
+        self.captures = copying;
+        if let Err(e) = self.saver.finish(wait) {
+            result = Err(RenderError::Capture(e));
+        }
+        Ok(result?)
+    }
+    /// Waits for every screenshot and captured frame to be saved, returning the last error
+    /// if any failed.
+    pub fn finish_captures(&mut self) -> Result<()> {
+        self.save_captures(true)
+    }
+}
+
+/// Sprites collected once per frame for each target format, as pipelines depend on it.
+type FrameObjects<'a> = HashMap<TextureFormat, Vec<RenderObject<'a>>>;
+
+/// Whether `camera` draws `object`: it must be on one of its layers, and can't be the texture
+/// it renders to, nor sample it through its material.
+fn shows(camera: &Camera2d, object: &RenderObject) -> bool {
+    let renders_to = |texture: &TextureHandle| CameraTarget::Texture(*texture) == camera.target;
+    camera.layers.contains(object.layer)
+        && !renders_to(&object.texture)
+        && !object.samples.iter().any(renders_to)
+}
@@ -0,0 +1,266 @@
+use crate::ecs::entity::EntityId;
+use crate::ecs::event::{EventReader, Events};
+use crate::ecs::world::World;
+use crate::error::Result;
+use crate::rendering::camera::Camera2d;
+use image::RgbaImage;
+use nalgebra::Vector2;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+use wgpu::{Buffer, BufferAsyncError, Device, Queue};
+
+/// Asks for the next frame a camera renders to be saved as a PNG file at `path`, once
+/// [`take_screenshots`] sees it.
+///
+/// [`Camera2d::screenshot`] does the same without the event.
+///
+/// The target isn't read back: the camera's own sprites are rendered again into a texture the
+/// size of its viewport. What other cameras drew on the same target is missing, and the
+/// background is transparent if the camera has no clear color.
+#[derive(Clone, Debug)]
+pub struct Screenshot {
+    /// The entity with the [`Camera2d`].
+    pub camera: EntityId,
+    pub path: PathBuf,
+}
+
+/// Saves every frame its entity's [`Camera2d`] renders as numbered PNG files, like
+/// `frame_00000.png`, until it is removed; for trailers and bug reports.
+///
+/// Frames are rendered again like [`Screenshot`]s, so they only show what this camera draws.
+#[derive(Clone, Debug)]
+pub struct FrameCapture {
+    pub directory: PathBuf,
+    pub prefix: String,
+    frames: u64,
+}
+impl FrameCapture {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+            prefix: "frame_".to_string(),
+            frames: 0,
+        }
+    }
+    /// Number of frames captured so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+    /// Path of the next frame captured.
+    pub(crate) fn next_path(&mut self) -> PathBuf {
+        let path = self
+            .directory
+            .join(format!("{}{:05}.png", self.prefix, self.frames));
+        self.frames += 1;
+        path
+    }
+}
+
+/// Passes [`Screenshot`] events on to their cameras.
+pub fn take_screenshots() -> impl FnMut(&World) -> Result<()> {
+    let mut reader = EventReader::default();
+    move |world: &World| {
+        let Some(events) = world.resource::<Events<Screenshot>>() else {
+            return Ok(());
+        };
+        for event in reader.read(&events) {
+            let Some(mut entity) = world.get_mut(event.camera) else {
+                log::warn!("no camera {:?} to take a screenshot with", event.camera);
+                continue;
+            };
+            match entity.get_component_mut::<Camera2d>() {
+                Some(camera) => camera.screenshot(&event.path),
+                None => log::warn!("no camera {:?} to take a screenshot with", event.camera),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Mapping {
+    result: Option<Result<(), BufferAsyncError>>,
+    /// The task awaiting the mapping, if any.
+    waker: Option<Waker>,
+}
+
+/// Pixels of a texture being copied back from the GPU.
+#[derive(Debug)]
+pub(crate) struct Readback {
+    buffer: Buffer,
+    size: Vector2<u32>,
+    padded_row: u32,
+    mapping: Arc<Mutex<Mapping>>,
+}
+impl Readback {
+    /// Copies `texture`, 8-bit RGBA and `size` pixels large, into a buffer then maps it, which
+    /// happens as the device is polled.
+    pub fn start(
+        device: &Device,
+        queue: &Queue,
+        texture: &wgpu::Texture,
+        size: Vector2<u32>,
+    ) -> Self {
+        let row = size.x * 4;
+        // Rows of a copy must be aligned
+        let padded_row = row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback"),
+            size: (padded_row * size.y) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let mapping = Arc::new(Mutex::new(Mapping::default()));
+        let callback = mapping.clone();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let mut mapping = callback.lock().unwrap();
+                mapping.result = Some(result);
+                if let Some(waker) = mapping.waker.take() {
+                    waker.wake();
+                }
+            });
+        Self {
+            buffer,
+            size,
+            padded_row,
+            mapping,
+        }
+    }
+    /// The pixels, once mapped.
+    pub fn poll(&self, cx: Option<&Context>) -> Poll<Result<RgbaImage, BufferAsyncError>> {
+        let mut mapping = self.mapping.lock().unwrap();
+        match mapping.result.take() {
+            Some(Ok(())) => Poll::Ready(Ok(self.image())),
+            Some(Err(e)) => Poll::Ready(Err(e)),
+            None => {
+                if let Some(cx) = cx {
+                    mapping.waker = Some(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+    fn image(&self) -> RgbaImage {
+        let row = (self.size.x * 4) as usize;
+        let slice = self.buffer.slice(..);
+        let pixels = slice.get_mapped_range();
+        let mut image = Vec::with_capacity(row * self.size.y as usize);
+        for padded in pixels.chunks(self.padded_row as usize) {
+            image.extend_from_slice(&padded[..row]);
+        }
+        drop(pixels);
+        self.buffer.unmap();
+        RgbaImage::from_raw(self.size.x, self.size.y, image).unwrap()
+    }
+}
+
+/// Saves `image` as a PNG file at every path.
+pub(crate) fn save(image: &RgbaImage, paths: &[PathBuf]) -> image::ImageResult<()> {
+    for path in paths {
+        if let Some(directory) = path.parent().filter(|d| d != &Path::new("")) {
+            std::fs::create_dir_all(directory)?;
+        }
+        image.save_with_format(path, image::ImageFormat::Png)?;
+    }
+    Ok(())
+}
+
+/// A frame and the files to save it to.
+type Frame = (RgbaImage, Vec<PathBuf>);
+
+/// Saves frames on a thread of its own, started with the first one.
+///
+/// At most [`FrameSaver::QUEUED`] frames wait for it, so a game capturing faster than it can
+/// encode blocks instead of piling up frames in memory.
+#[derive(Debug, Default)]
+pub(crate) struct FrameSaver {
+    thread: Option<(SyncSender<Frame>, JoinHandle<()>)>,
+    /// In a mutex only for the renderer to be `Sync`.
+    results: Option<Mutex<Receiver<image::ImageResult<()>>>>,
+    /// Frames sent whose result hasn't been received yet.
+    pending: usize,
+}
+impl FrameSaver {
+    pub const QUEUED: usize = 4;
+    /// Queues `image` to be saved at `paths`.
+    pub fn save(&mut self, image: RgbaImage, paths: Vec<PathBuf>) {
+        let results = &mut self.results;
+        let (frames, _) = self.thread.get_or_insert_with(|| {
+            let (frames, queued) = mpsc::sync_channel::<Frame>(Self::QUEUED);
+            let (done, received) = mpsc::channel();
+            *results = Some(Mutex::new(received));
+            let thread = std::thread::spawn(move || {
+                for (image, paths) in queued {
+                    if done.send(save(&image, &paths)).is_err() {
+                        break;
+                    }
+                }
+            });
+            (frames, thread)
+        });
+        frames
+            .send((image, paths))
+            .expect("the frame saving thread panicked");
+        self.pending += 1;
+    }
+    /// Collects the results of the saves that are done, or waits for every queued one if
+    /// `wait` is true, returning the last error.
+    pub fn finish(&mut self, wait: bool) -> image::ImageResult<()> {
+        let mut result = Ok(());
+        let Some(results) = self.results.as_mut().map(|r| r.get_mut().unwrap()) else {
+            return result;
+        };
+        while self.pending > 0 {
+            let saved = match wait {
+                true => results.recv().ok(),
+                false => results.try_recv().ok(),
+            };
+            let Some(saved) = saved else {
+                break;
+            };
+            self.pending -= 1;
+            if saved.is_err() {
+                result = saved;
+            }
+        }
+        assert!(
+            !wait || self.pending == 0,
+            "the frame saving thread panicked"
+        );
+        result
+    }
+}
+impl Drop for FrameSaver {
+    /// Lets the queued frames be saved before returning.
+    fn drop(&mut self) {
+        if let Some((frames, thread)) = self.thread.take() {
+            drop(frames);
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use crate::rendering::camera::{SpriteInstance, Vertex};
+use crate::rendering::material::{MaterialLayout, SPRITE_WGSL};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{
+    AddressSpace, Binding, ImageClass, ImageDimension, Module, ScalarKind, ShaderStage, TypeInner,
+    VectorSize,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use wgpu::{RenderPipeline, VertexBufferLayout, VertexFormat};
+
+/// Where in a shader file something is, from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+impl Display for ShaderLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// A shader that couldn't be preprocessed, parsed or validated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderError {
+    pub message: String,
+    /// Where the error is, in the file it was written in rather than in the preprocessed
+    /// source, when known.
+    pub location: Option<ShaderLocation>,
+}
+impl ShaderError {
+    fn at(file: &str, line: u32, message: String) -> Self {
+        Self {
+            message,
+            location: Some(ShaderLocation {
+                file: file.to_string(),
+                line,
+                column: 1,
+            }),
+        }
+    }
+}
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{location}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+impl std::error::Error for ShaderError {}
+
+/// A define replaced on a line, as the columns it covers after and before, from 0.
+#[derive(Clone, Debug)]
+struct Substitution {
+    output: Range<u32>,
+    source: Range<u32>,
+}
+
+/// WGSL source after preprocessing, with where each of its lines comes from.
+#[derive(Clone, Debug)]
+pub struct PreprocessedShader {
+    pub source: String,
+    /// File, line and defines replaced of every line of `source`.
+    lines: Vec<(String, u32, Vec<Substitution>)>,
+}
+impl PreprocessedShader {
+    /// Maps a line and column of `source`, from 1, back to the file it was written in.
+    ///
+    /// Columns within the value of a define point at the define.
+    pub fn location(&self, line: u32, column: u32) -> Option<ShaderLocation> {
+        let (file, line, substitutions) = self.lines.get(line.checked_sub(1)? as usize)?;
+        let column = column.checked_sub(1)?;
+        let (mut output_end, mut source_end) = (0, 0);
+        for substitution in substitutions {
+            if column < substitution.output.start {
+                break;
+            }
+            if column < substitution.output.end {
+                source_end = substitution.source.start;
+                output_end = column;
+                break;
+            }
+            output_end = substitution.output.end;
+            source_end = substitution.source.end;
+        }
+        Some(ShaderLocation {
+            file: file.clone(),
+            line: *line,
+            column: source_end + column - output_end + 1,
+        })
+    }
+    /// Parses and validates the shader with naga, without a GPU.
+    pub fn validate(&self) -> Result<Module, ShaderError> {
+        let module = naga::front::wgsl::parse_str(&self.source).map_err(|e| ShaderError {
+            message: e.message().to_string(),
+            location: e
+                .location(&self.source)
+                .and_then(|l| self.location(l.line_number, l.line_position)),
+        })?;
+        Validator::new(ValidationFlags::all(), Capabilities::empty())
+            .validate(&module)
+            .map_err(|e| ShaderError {
+                message: e.as_inner().to_string(),
+                location: e
+                    .location(&self.source)
+                    .and_then(|l| self.location(l.line_number, l.line_position)),
+            })?;
+        Ok(module)
+    }
+}
+
+/// State of a shader being preprocessed.
+#[derive(Default)]
+struct Preprocessor {
+    output: String,
+    lines: Vec<(String, u32, Vec<Substitution>)>,
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+    /// Block comments open at the end of the last line, as they nest.
+    comments: u32,
+}
+
+/// Shader files that shaders can `#include`, and the preprocessor resolving them.
+///
+/// Directives take a line each, starting with `#`:
+/// - `#include "file"` inserts a file of the library, once per shader however many times it
+///   is included
+/// - `#define NAME value` replaces the identifier `NAME` by `value` on the following lines,
+///   outside of comments, and `#define NAME` defines it as empty
+/// - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` keep lines depending on whether
+///   `NAME` is defined
+///
+/// It starts with [`SPRITE_WGSL`] as `sprite.wgsl`, which sprite shaders include.
+#[derive(Clone, Debug)]
+pub struct ShaderLibrary {
+    files: HashMap<String, String>,
+}
+impl Default for ShaderLibrary {
+    fn default() -> Self {
+        let mut library = Self {
+            files: HashMap::new(),
+        };
+        library.add("sprite.wgsl", SPRITE_WGSL);
+        library
+    }
+}
+impl ShaderLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Makes `source` includable as `name`, replacing the file of that name if there is one.
+    pub fn add<N: Into<String>, S: Into<String>>(&mut self, name: N, source: S) {
+        self.files.insert(name.into(), source.into());
+    }
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.files.get(name).map(String::as_str)
+    }
+    /// Resolves the directives of `source`, named `name` in errors.
+    pub fn preprocess(&self, name: &str, source: &str) -> Result<PreprocessedShader, ShaderError> {
+        let mut preprocessor = Preprocessor::default();
+        preprocessor.included.insert(name.to_string());
+        self.preprocess_file(&mut preprocessor, name, source)?;
+        Ok(PreprocessedShader {
+            source: preprocessor.output,
+            lines: preprocessor.lines,
+        })
+    }
+    fn preprocess_file(
+        &self,
+        preprocessor: &mut Preprocessor,
+        name: &str,
+        source: &str,
+    ) -> Result<(), ShaderError> {
+        // Whether lines are kept, whether an `#else` was seen and where each open `#if` is
+        let mut conditions: Vec<(bool, bool, u32)> = Vec::new();
+        for (i, text) in source.lines().enumerate() {
+            let line = i as u32 + 1;
+            let active = conditions.iter().all(|(keep, _, _)| *keep);
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    let (text, substitutions) =
+                        substitute(text, &preprocessor.defines, &mut preprocessor.comments);
+                    preprocessor.output.push_str(&text);
+                    preprocessor.output.push('\n');
+                    preprocessor
+                        .lines
+                        .push((name.to_string(), line, substitutions));
+                }
+                continue;
+            };
+            let (keyword, argument) = directive
+                .trim()
+                .split_once(char::is_whitespace)
+                .map_or((directive.trim(), ""), |(k, a)| (k, a.trim()));
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = preprocessor.defines.contains_key(argument);
+                    conditions.push((defined == (keyword == "ifdef"), false, line));
+                }
+                "else" => match conditions.last_mut() {
+                    Some((keep, seen_else @ false, _)) => {
+                        *keep = !*keep;
+                        *seen_else = true;
+                    }
+                    _ => return Err(ShaderError::at(name, line, "unexpected #else".into())),
+                },
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(ShaderError::at(name, line, "unexpected #endif".into()));
+                    }
+                }
+                _ if !active => {}
+                "define" => {
+                    let (define, value) = argument
+                        .split_once(char::is_whitespace)
+                        .map_or((argument, ""), |(d, v)| (d, v.trim()));
+                    if !is_identifier(define) {
+                        let message = format!("#define of `{define}`, which isn't an identifier");
+                        return Err(ShaderError::at(name, line, message));
+                    }
+                    preprocessor
+                        .defines
+                        .insert(define.to_string(), value.to_string());
+                }
+                "include" => {
+                    let Some(file) = argument.strip_prefix('"').and_then(|a| a.strip_suffix('"'))
+                    else {
+                        let message = format!("expected #include \"file\", found `{argument}`");
+                        return Err(ShaderError::at(name, line, message));
+                    };
+                    let Some(included) = self.get(file) else {
+                        let message = format!("no shader file `{file}` to include");
+                        return Err(ShaderError::at(name, line, message));
+                    };
+                    if preprocessor.included.insert(file.to_string()) {
+                        self.preprocess_file(preprocessor, file, included)?;
+                    }
+                }
+                _ => {
+                    let message = format!("unknown directive #{keyword}");
+                    return Err(ShaderError::at(name, line, message));
+                }
+            }
+        }
+        match conditions.last() {
+            Some((_, _, line)) => Err(ShaderError::at(name, *line, "missing #endif".into())),
+            None => Ok(()),
+        }
+    }
+    /// Preprocesses and validates a sprite shader, checking that its `vs_main` takes the
+    /// vertex and instance inputs sprites are drawn with and that its material bindings are
+    /// those of `layout`, and returns the WGSL to create its pipeline with.
+    pub fn load_sprite_shader(
+        &self,
+        name: &str,
+        source: &str,
+        layout: Option<MaterialLayout>,
+    ) -> Result<String, ShaderError> {
+        let shader = self.preprocess(name, source)?;
+        let module = shader.validate()?;
+        if !module
+            .entry_points
+            .iter()
+            .any(|e| e.stage == ShaderStage::Fragment && e.name == "fs_main")
+        {
+            return Err(ShaderError {
+                message: format!("{name} has no fragment entry point `fs_main`"),
+                location: None,
+            });
+        }
+        check_vertex_inputs(
+            &module,
+            "vs_main",
+            &[Vertex::desc(), SpriteInstance::desc()],
+        )
+        .and_then(|()| check_material_bindings(&module, layout))
+        .map_err(|message| ShaderError {
+            message: format!("{name}: {message}"),
+            location: None,
+        })?;
+        Ok(shader.source)
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replaces the defined identifiers of `text`, outside of comments, returning the line and
+/// where values were put in it. `comments` counts the block comments open, from line to line.
+fn substitute(
+    text: &str,
+    defines: &HashMap<String, String>,
+    comments: &mut u32,
+) -> (String, Vec<Substitution>) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::with_capacity(text.len());
+    let mut substitutions = Vec::new();
+    let mut column = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('/', Some('/')) if *comments == 0 => {
+                output.extend(&chars[i..]);
+                break;
+            }
+            ('/', Some('*')) => *comments += 1,
+            ('*', Some('/')) if *comments > 0 => *comments -= 1,
+            (c, _) if *comments == 0 && (c.is_ascii_alphanumeric() || c == '_') => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| !c.is_ascii_alphanumeric() && *c != '_')
+                    .map_or(chars.len(), |length| i + length);
+                let identifier: String = chars[i..end].iter().collect();
+                // Identifiers can't start with a digit, so numbers are never replaced
+                let value = match defines.get(&identifier) {
+                    Some(value) => {
+                        let length = value.chars().count() as u32;
+                        substitutions.push(Substitution {
+                            output: column..column + length,
+                            source: i as u32..end as u32,
+                        });
+                        value
+                    }
+                    None => &identifier,
+                };
+                output.push_str(value);
+                column += value.chars().count() as u32;
+                i = end;
+                continue;
+            }
+            (c, _) => {
+                output.push(c);
+                column += 1;
+                i += 1;
+                continue;
+            }
+        }
+        // Both characters of a comment delimiter
+        output.extend(&chars[i..i + 2]);
+        column += 2;
+        i += 2;
+    }
+    (output, substitutions)
+}
+
+/// Checks that every binding of group 2 of `module` is one of `layout`: its uniform at binding
+/// 0, and its textures from binding 1.
+pub fn check_material_bindings(
+    module: &Module,
+    layout: Option<MaterialLayout>,
+) -> Result<(), String> {
+    let layout = layout.unwrap_or(MaterialLayout {
+        uniform: false,
+        textures: 0,
+    });
+    for (_, variable) in module.global_variables.iter() {
+        let Some(binding) = variable.binding.as_ref().filter(|b| b.group == 2) else {
+            continue;
+        };
+        let texture = matches!(
+            module.types[variable.ty].inner,
+            TypeInner::Image {
+                dim: ImageDimension::D2,
+                arrayed: false,
+                class: ImageClass::Sampled {
+                    kind: ScalarKind::Float,
+                    multi: false,
+                },
+            }
+        );
+        let provided = match binding.binding {
+            0 => layout.uniform && variable.space == AddressSpace::Uniform,
+            i => texture && i as usize <= layout.textures,
+        };
+        if !provided {
+            let name = variable.name.as_deref().unwrap_or("_");
+            let uniform = match layout.uniform {
+                true => "a uniform",
+                false => "no uniform",
+            };
+            return Err(format!(
+                "`{name}` at @group(2) @binding({}) isn't a binding of the material, which has \
+                 {uniform} and {} texture(s)",
+                binding.binding, layout.textures
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every input of the `entry_point` vertex stage of `module` is provided by an
+/// attribute of `layouts` of the same type.
+pub fn check_vertex_inputs(
+    module: &Module,
+    entry_point: &str,
+    layouts: &[VertexBufferLayout],
+) -> Result<(), String> {
+    let Some(entry_point) = module
+        .entry_points
+        .iter()
+        .find(|e| e.stage == ShaderStage::Vertex && e.name == entry_point)
+    else {
+        return Err(format!("no vertex entry point `{entry_point}`"));
+    };
+    let mut inputs = Vec::new();
+    for argument in &entry_point.function.arguments {
+        match (&argument.binding, &module.types[argument.ty].inner) {
+            (Some(binding), inner) => inputs.push((binding, inner)),
+            (None, TypeInner::Struct { members, .. }) => {
+                for member in members {
+                    if let Some(binding) = &member.binding {
+                        inputs.push((binding, &module.types[member.ty].inner));
+                    }
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    let attributes: HashMap<_, _> = layouts
+        .iter()
+        .flat_map(|layout| layout.attributes)
+        .map(|attribute| (attribute.shader_location, attribute.format))
+        .collect();
+    for (binding, inner) in inputs {
+        let Binding::Location { location, .. } = binding else {
+            continue;
+        };
+        let Some(format) = attributes.get(location) else {
+            return Err(format!(
+                "the input at location {location} isn't in the vertex layout"
+            ));
+        };
+        if vertex_format(inner) != Some(*format) {
+            return Err(format!(
+                "the input at location {location} is a {inner:?} but {format:?} in the vertex layout"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The 32-bit vertex format of a scalar or vector type.
+fn vertex_format(inner: &TypeInner) -> Option<VertexFormat> {
+    let (kind, size) = match *inner {
+        TypeInner::Scalar { kind, width: 4 } => (kind, None),
+        TypeInner::Vector {
+            kind,
+            size,
+            width: 4,
+        } => (kind, Some(size)),
+        _ => return None,
+    };
+    Some(match (kind, size) {
+        (ScalarKind::Float, None) => VertexFormat::Float32,
+        (ScalarKind::Float, Some(VectorSize::Bi)) => VertexFormat::Float32x2,
+        (ScalarKind::Float, Some(VectorSize::Tri)) => VertexFormat::Float32x3,
+        (ScalarKind::Float, Some(VectorSize::Quad)) => VertexFormat::Float32x4,
+        (ScalarKind::Uint, None) => VertexFormat::Uint32,
+        (ScalarKind::Uint, Some(VectorSize::Bi)) => VertexFormat::Uint32x2,
+        (ScalarKind::Uint, Some(VectorSize::Tri)) => VertexFormat::Uint32x3,
+        (ScalarKind::Uint, Some(VectorSize::Quad)) => VertexFormat::Uint32x4,
+        (ScalarKind::Sint, None) => VertexFormat::Sint32,
+        (ScalarKind::Sint, Some(VectorSize::Bi)) => VertexFormat::Sint32x2,
+        (ScalarKind::Sint, Some(VectorSize::Tri)) => VertexFormat::Sint32x3,
+        (ScalarKind::Sint, Some(VectorSize::Quad)) => VertexFormat::Sint32x4,
+        (ScalarKind::Bool, _) => return None,
+    })
+}
+
+/// Render pipelines of sprite materials, by shader and render state, and the
+/// [`ShaderLibrary`] their shaders are loaded with.
+///
+/// Shaders that fail to load are logged once, and the sprites drawn with them are skipped.
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    pub library: ShaderLibrary,
+    pipelines: HashMap<u64, RenderPipeline>,
+    failed: HashSet<u64>,
+}
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get(&self, key: u64) -> Option<&RenderPipeline> {
+        self.pipelines.get(&key)
+    }
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+    /// Whether there's a pipeline for `key`, or its shader failed to load.
+    pub(crate) fn contains(&self, key: u64) -> bool {
+        self.pipelines.contains_key(&key) || self.failed.contains(&key)
+    }
+    pub(crate) fn insert(&mut self, key: u64, pipeline: RenderPipeline) {
+        self.pipelines.insert(key, pipeline);
+    }
+    /// Remembers that the shader of `key` failed to load, so it isn't tried every frame.
+    pub(crate) fn fail(&mut self, key: u64) {
+        self.failed.insert(key);
+    }
+}
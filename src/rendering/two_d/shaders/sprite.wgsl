@@ -0,0 +1,49 @@
+// Inputs, bindings and vertex stage shared by sprite shaders, which add a `fs_main`.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+struct InstanceInput {
+    @location(2) x_axis: vec2<f32>,
+    @location(3) y_axis: vec2<f32>,
+    @location(4) translation: vec2<f32>,
+    @location(5) uv_min: vec2<f32>,
+    @location(6) uv_size: vec2<f32>,
+    @location(7) tint: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var sprite_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var sprite_sampler: sampler;
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+    // Seconds since the game started
+    time: f32,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+@vertex
+fn vs_main(
+    input: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let position = instance.x_axis * input.position.x
+        + instance.y_axis * input.position.y
+        + instance.translation;
+    out.clip_position = camera.view_projection * vec4<f32>(position, 0.0, 1.0);
+    out.uv = instance.uv_min + input.uv * instance.uv_size;
+    out.tint = instance.tint;
+    return out;
+}
@@ -0,0 +1,27 @@
+#include "sprite.wgsl"
+
+
+struct Outline {
+    color: vec4<f32>,
+    thickness: f32,
+}
+@group(2) @binding(0)
+var<uniform> outline: Outline;
+
+fn alpha_at(uv: vec2<f32>, offset: vec2<f32>) -> f32 {
+    return textureSample(sprite_texture, sprite_sampler, uv + offset).a;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(sprite_texture, sprite_sampler, in.uv) * in.tint;
+    let texel = outline.thickness / vec2<f32>(textureDimensions(sprite_texture));
+    let diagonal = texel * 0.7071;
+    // Coverage of the neighbouring texels, in the 8 directions
+    var alpha = max(alpha_at(in.uv, vec2<f32>(texel.x, 0.0)), alpha_at(in.uv, vec2<f32>(-texel.x, 0.0)));
+    alpha = max(alpha, max(alpha_at(in.uv, vec2<f32>(0.0, texel.y)), alpha_at(in.uv, vec2<f32>(0.0, -texel.y))));
+    alpha = max(alpha, max(alpha_at(in.uv, diagonal), alpha_at(in.uv, -diagonal)));
+    alpha = max(alpha, max(alpha_at(in.uv, vec2<f32>(diagonal.x, -diagonal.y)), alpha_at(in.uv, vec2<f32>(-diagonal.x, diagonal.y))));
+    let edge = vec4<f32>(outline.color.rgb, outline.color.a * alpha);
+    return mix(edge, color, color.a);
+}
@@ -0,0 +1,15 @@
+#include "sprite.wgsl"
+
+
+struct Tint {
+    color: vec4<f32>,
+    amount: f32,
+}
+@group(2) @binding(0)
+var<uniform> tint: Tint;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(sprite_texture, sprite_sampler, in.uv) * in.tint;
+    return vec4<f32>(mix(color.rgb, tint.color.rgb, tint.amount), color.a);
+}
@@ -1,21 +1,7 @@
-struct VertexInput {
-    @location(0) position: vec2<f32>,
-}
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-};
+#include "sprite.wgsl"
 
-@vertex
-fn vs_main(
-    input: VertexInput
-) -> VertexOutput {
-    var out = VertexOutput;
-    out.clip_position = vec4(input.position, 0.0, 1.0);
-    return out;
-}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(0.3, 0.2, 0.1, 1.0);
-}
\ No newline at end of file
+    return textureSample(sprite_texture, sprite_sampler, in.uv) * in.tint;
+}
@@ -1,30 +1,132 @@
-use crate::rendering::texture::Texture;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use crate::rendering::material::{Material, MaterialKey, ShaderMaterial, UnlitMaterial};
+use crate::rendering::texture::{Texture, TextureHandle};
+use nalgebra::Vector2;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use wgpu::Color;
+
+/// Part of a texture, in UV coordinates: from 0 to 1, starting at its top-left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: Vector2<f32>,
+    pub size: Vector2<f32>,
+}
+impl Default for UvRect {
+    /// The whole texture.
+    fn default() -> Self {
+        Self {
+            min: Vector2::zeros(),
+            size: Vector2::repeat(1.0),
+        }
+    }
+}
+impl UvRect {
+    /// The rectangle of `size` pixels at `min` in a texture `texture_size` pixels large, like a
+    /// tile of an atlas.
+    pub fn from_pixels(min: Vector2<u32>, size: Vector2<u32>, texture_size: Vector2<u32>) -> Self {
+        let texture_size = texture_size.cast::<f32>();
+        Self {
+            min: min.cast::<f32>().component_div(&texture_size),
+            size: size.cast::<f32>().component_div(&texture_size),
+        }
+    }
+}
+
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
 
 pub struct Sprite {
+    /// Call [`Sprite::mark_dirty`] after changing it, or replace it with
+    /// [`Sprite::set_texture`], for the GPU copy to be updated.
     pub texture: Box<dyn Texture>,
-    pub shader_label: String,
-    pub shader: String,
+    /// Identifies the texture in the renderer's [`TextureCache`](crate::rendering::texture::TextureCache).
+    pub handle: TextureHandle,
+    /// The part of the texture drawn. The sprite is as large as it is in pixels.
+    pub region: UvRect,
+    /// Multiplied with the texture's colors.
+    pub tint: Color,
+    /// Render layer, drawn over the lower ones by the cameras that show it, see
+    /// [`RenderLayers`](crate::rendering::camera::RenderLayers).
+    pub layer: u8,
+    /// Sprites of a layer with a higher z are drawn over those with a lower one.
+    pub z: f32,
+    material: Box<dyn Material>,
+    material_key: MaterialKey,
+    version: u64,
 }
 impl Sprite {
     pub fn new<T: Texture + 'static>(texture: T) -> Self {
+        Self::with_handle(TextureHandle::new(), texture)
+    }
+    /// A sprite drawn with the texture already uploaded for `handle`, if there is one, so that
+    /// many sprites can share a texture.
+    pub fn with_handle<T: Texture + 'static>(handle: TextureHandle, texture: T) -> Self {
         Self {
             texture: Box::new(texture),
-            shader_label: "SpriteUnlit".to_string(),
-            shader: include_str!("shaders/sprite_unlit.wgsl").to_string(),
+            handle,
+            region: UvRect::default(),
+            tint: Color::WHITE,
+            layer: 0,
+            z: 0.0,
+            material: Box::new(UnlitMaterial),
+            material_key: MaterialKey::of(&UnlitMaterial),
+            version: 0,
         }
     }
+    /// A sprite drawn with a custom WGSL shader, see [`Material`].
     pub fn new_with_shader<T: Texture + 'static>(
         texture: T,
         shader: String,
         label: String,
     ) -> Self {
-        Self {
-            texture: Box::new(texture),
-            shader_label: label,
-            shader,
-        }
+        Self::new_with_material(texture, ShaderMaterial::new(label, shader))
+    }
+    pub fn new_with_material<T: Texture + 'static, M: Material + 'static>(
+        texture: T,
+        material: M,
+    ) -> Self {
+        let mut sprite = Self::new(texture);
+        sprite.set_material(material);
+        sprite
+    }
+    /// The shader the sprite is drawn with, and its parameters.
+    pub fn material(&self) -> &dyn Material {
+        self.material.as_ref()
+    }
+    /// Replaces the material, for instance every frame to animate its parameters.
+    pub fn set_material<M: Material + 'static>(&mut self, material: M) {
+        self.material_key = MaterialKey::of(&material);
+        self.material = Box::new(material);
+    }
+    pub fn material_key(&self) -> MaterialKey {
+        self.material_key
+    }
+    /// Makes the renderer upload the texture again before drawing it next. Of the sprites
+    /// sharing a handle, the one marked dirty last provides the texture they are all drawn with.
+    pub fn mark_dirty(&mut self) {
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn set_texture<T: Texture + 'static>(&mut self, texture: T) {
+        self.texture = Box::new(texture);
+        self.mark_dirty();
+    }
+    /// Version of the texture, 0 until the sprite is marked dirty, then newer than that of any
+    /// sprite marked dirty before.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+impl Debug for Sprite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sprite")
+            .field("width", &self.texture.width())
+            .field("height", &self.texture.height())
+            .field("handle", &self.handle)
+            .field("region", &self.region)
+            .field("tint", &self.tint)
+            .field("layer", &self.layer)
+            .field("z", &self.z)
+            .field("material", &self.material.label())
+            .field("version", &self.version)
+            .finish_non_exhaustive()
     }
 }
@@ -0,0 +1,21 @@
+use std::ops::AddAssign;
+
+/// What the 2D renderer drew during the last frame, over all cameras.
+///
+/// A resource of games set up with [`add_render_2d`](crate::rendering::add_render_2d).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub sprites: u64,
+    /// Runs of sprites sharing a pipeline, texture and material.
+    pub batches: u64,
+    pub draw_calls: u64,
+    pub texture_uploads: u64,
+}
+impl AddAssign for RenderStats {
+    fn add_assign(&mut self, other: Self) {
+        self.sprites += other.sprites;
+        self.batches += other.batches;
+        self.draw_calls += other.draw_calls;
+        self.texture_uploads += other.texture_uploads;
+    }
+}
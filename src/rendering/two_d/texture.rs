@@ -1,23 +1,74 @@
-use image::{ImageBuffer, Rgba};
+use image::{ImageBuffer, Rgba, RgbaImage};
 use nalgebra::Vector2;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU64, Ordering};
+use wgpu::{BindGroup, BindGroupLayout, Device, Queue, Sampler};
 
-pub trait Texture {
-    fn sample(&self, uv: Vector2<f32>) -> &Rgba<f32>;
+/// How the pixels of a texture are stored on the GPU, always 8 bits per channel.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    /// Colors as stored in most image files, converted to linear when sampled.
+    #[default]
+    Rgba8Srgb,
+    /// Linear values, like normal maps or float images.
+    Rgba8,
+}
+impl TextureFormat {
+    pub fn to_wgpu(self) -> wgpu::TextureFormat {
+        match self {
+            TextureFormat::Rgba8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgba8 => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+pub trait Texture: Send + Sync {
+    fn sample(&self, uv: Vector2<f32>) -> Rgba<f32>;
     fn width(&self) -> u32;
     fn height(&self) -> u32;
     fn complete(&self) -> ImageBuffer<Rgba<f32>, Vec<f32>> {
         let mut image = ImageBuffer::new(self.width(), self.height());
         for x in 0..self.width() {
             for y in 0..self.height() {
-                image.put_pixel(x, y, *self.sample(Vector2::new(x as f32, y as f32)));
+                image.put_pixel(x, y, self.sample(Vector2::new(x as f32, y as f32)));
             }
         }
         image
     }
+    fn format(&self) -> TextureFormat {
+        TextureFormat::Rgba8
+    }
+    /// Rows of RGBA pixels, as uploaded to the GPU in [`Texture::format`].
+    fn rgba8(&self) -> Vec<u8> {
+        self.complete()
+            .as_raw()
+            .iter()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect()
+    }
+}
+impl Texture for RgbaImage {
+    fn sample(&self, uv: Vector2<f32>) -> Rgba<f32> {
+        let Rgba(pixel) = self.get_pixel(uv.x as u32, uv.y as u32);
+        Rgba(pixel.map(|c| c as f32 / 255.0))
+    }
+    fn width(&self) -> u32 {
+        self.width()
+    }
+    fn height(&self) -> u32 {
+        self.height()
+    }
+    fn format(&self) -> TextureFormat {
+        TextureFormat::Rgba8Srgb
+    }
+    fn rgba8(&self) -> Vec<u8> {
+        self.as_raw().clone()
+    }
 }
 impl Texture for ImageBuffer<Rgba<f32>, Vec<f32>> {
-    fn sample(&self, uv: Vector2<f32>) -> &Rgba<f32> {
-        self.get_pixel(uv.x as u32, uv.y as u32)
+    fn sample(&self, uv: Vector2<f32>) -> Rgba<f32> {
+        *self.get_pixel(uv.x as u32, uv.y as u32)
     }
     fn width(&self) -> u32 {
         self.width()
@@ -30,10 +81,6 @@ impl Texture for ImageBuffer<Rgba<f32>, Vec<f32>> {
     }
 }
 
-pub struct Sprite {
-    pub texture: Box<dyn Texture>,
-}
-
 pub struct Tilemap {
     pub textures: Vec<Box<dyn Texture>>,
     pub tile_size: Vector2<u32>,
@@ -52,7 +99,7 @@ impl Tilemap {
     }
 }
 impl Texture for Tilemap {
-    fn sample(&self, uv: Vector2<f32>) -> &Rgba<f32> {
+    fn sample(&self, uv: Vector2<f32>) -> Rgba<f32> {
         let tile_x = uv.x as usize / self.tile_size.x as usize;
         let tile_y = uv.y as usize / self.tile_size.y as usize;
         let tile_id = self.tilemap[tile_y][tile_x];
@@ -64,7 +111,7 @@ impl Texture for Tilemap {
         )
     }
     fn width(&self) -> u32 {
-        self.tilemap.get(0).map_or(0, |v| v.len()) as u32 * self.tile_size.x
+        self.tilemap.first().map_or(0, |v| v.len()) as u32 * self.tile_size.x
     }
     fn height(&self) -> u32 {
         self.tilemap.len() as u32 * self.tile_size.y
@@ -89,3 +136,271 @@ impl Texture for Tilemap {
         ImageBuffer::from_raw(self.width(), self.height(), final_vec).unwrap()
     }
 }
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a texture in a [`TextureCache`]. Sprites with the same handle share the texture
+/// uploaded for the first of them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureHandle(u64);
+impl TextureHandle {
+    /// A handle no other texture has.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A texture uploaded to the GPU, ready to be bound by sprite shaders.
+#[derive(Debug)]
+pub struct GpuTexture {
+    pub texture: wgpu::Texture,
+    pub bind_group: BindGroup,
+    pub size: Vector2<u32>,
+    pub format: TextureFormat,
+    version: u64,
+    last_used: u64,
+    render_target: bool,
+    /// Different for every GPU texture the cache created.
+    id: u64,
+}
+impl GpuTexture {
+    /// Whether cameras can render to the texture, see [`TextureCache::create_render_target`].
+    pub fn is_render_target(&self) -> bool {
+        self.render_target
+    }
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// Stands in for the contents of a render target on the CPU, to draw it with a sprite:
+/// `Sprite::with_handle(texture.handle, texture)`.
+///
+/// The contents are only on the GPU, so it samples as transparent black; read them back with
+/// [`Renderer::read_texture`](crate::rendering::renderer::Renderer::read_texture).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RenderTexture {
+    pub handle: TextureHandle,
+    pub size: Vector2<u32>,
+}
+impl Texture for RenderTexture {
+    fn sample(&self, _uv: Vector2<f32>) -> Rgba<f32> {
+        Rgba([0.0; 4])
+    }
+    fn width(&self) -> u32 {
+        self.size.x
+    }
+    fn height(&self) -> u32 {
+        self.size.y
+    }
+    fn format(&self) -> TextureFormat {
+        TextureFormat::Rgba8Srgb
+    }
+}
+
+/// Textures uploaded to the GPU, by handle.
+///
+/// A texture is uploaded the first time it is drawn, and again only when a sprite with its handle
+/// is drawn with a newer version, see
+/// [`Sprite::mark_dirty`](crate::rendering::sprite::Sprite::mark_dirty). Textures that
+/// weren't drawn for [`TextureCache::EVICT_AFTER`] frames are dropped, except render targets
+/// which stay until removed.
+#[derive(Debug)]
+pub struct TextureCache {
+    textures: HashMap<TextureHandle, GpuTexture>,
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    frame: u64,
+    uploads: u64,
+    created: u64,
+}
+impl TextureCache {
+    pub const EVICT_AFTER: u64 = 600;
+
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite Texture"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite Sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        Self {
+            textures: HashMap::new(),
+            layout,
+            sampler,
+            frame: 0,
+            uploads: 0,
+            created: 0,
+        }
+    }
+    /// The sampler sprite shaders get in bind group 0, with nearest filtering.
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+    /// Layout of the texture and sampler sprite shaders get in bind group 0.
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+    pub fn get(&self, handle: TextureHandle) -> Option<&GpuTexture> {
+        self.textures.get(&handle)
+    }
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+    /// Number of uploads since the cache was created.
+    pub fn uploads(&self) -> u64 {
+        self.uploads
+    }
+    pub fn remove(&mut self, handle: TextureHandle) -> Option<GpuTexture> {
+        self.textures.remove(&handle)
+    }
+    /// Creates a texture `size` pixels large that cameras can render to, in sRGB, replacing
+    /// any texture of `handle`.
+    pub fn create_render_target(
+        &mut self,
+        device: &Device,
+        handle: TextureHandle,
+        size: Vector2<u32>,
+    ) -> &GpuTexture {
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC;
+        let texture = GpuTexture {
+            render_target: true,
+            ..self.create(device, size, TextureFormat::Rgba8Srgb, usage)
+        };
+        self.textures.insert(handle, texture);
+        &self.textures[&handle]
+    }
+    /// Uploads `texture` unless the cache already has this `version` of `handle` or a newer one,
+    /// or it is a render target.
+    ///
+    /// Versions belong to the handle rather than to one of the sprites sharing it, so a sprite
+    /// that is still on an older version doesn't upload its texture again.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        handle: TextureHandle,
+        version: u64,
+        texture: &dyn Texture,
+    ) -> &GpuTexture {
+        let size = Vector2::new(texture.width(), texture.height());
+        let format = texture.format();
+        let frame = self.frame;
+        let outdated = match self.textures.get(&handle) {
+            Some(cached) => cached.version < version && !cached.render_target,
+            None => true,
+        };
+        if outdated {
+            self.uploads += 1;
+            let reusable = self
+                .textures
+                .remove(&handle)
+                .filter(|cached| cached.size == size && cached.format == format);
+            let cached = reusable.unwrap_or_else(|| {
+                let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+                self.create(device, size, format, usage)
+            });
+            queue.write_texture(
+                cached.texture.as_image_copy(),
+                &texture.rgba8(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * size.x),
+                    rows_per_image: None,
+                },
+                extent(size),
+            );
+            self.textures
+                .insert(handle, GpuTexture { version, ..cached });
+        }
+        let cached = self.textures.get_mut(&handle).unwrap();
+        cached.last_used = frame;
+        cached
+    }
+    /// Ends a frame, dropping the textures that haven't been used for a while.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.textures.retain(|_, cached| {
+            cached.render_target || frame - cached.last_used < Self::EVICT_AFTER
+        });
+        self.frame += 1;
+    }
+    fn create(
+        &mut self,
+        device: &Device,
+        size: Vector2<u32>,
+        format: TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> GpuTexture {
+        self.created += 1;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sprite Texture"),
+            size: extent(size),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: format.to_wgpu(),
+            usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Texture"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        GpuTexture {
+            texture,
+            bind_group,
+            size,
+            format,
+            version: 0,
+            last_used: 0,
+            render_target: false,
+            id: self.created,
+        }
+    }
+}
+
+fn extent(size: Vector2<u32>) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    }
+}
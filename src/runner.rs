@@ -0,0 +1,65 @@
+use crate::ecs::game::Game;
+use crate::ecs::pacing::FrameRateCap;
+use crate::error::Result;
+
+#[cfg(feature = "winit")]
+pub mod winit;
+
+/// Drives a [`Game`], calling [`Game::update`] until it should stop. Set with
+/// [`Game::set_runner`] and started by [`Game::run`].
+pub trait Runner {
+    fn run(self: Box<Self>, game: Game) -> Result<()>;
+}
+
+/// Updates the game as fast as possible, without any window, until an
+/// [`AppExit`](crate::ecs::game::AppExit) event is sent or the frame limit is reached.
+#[derive(Debug, Default, Clone)]
+pub struct HeadlessRunner {
+    pub frames: Option<u64>,
+}
+impl HeadlessRunner {
+    /// Runs at most `frames` frames.
+    pub fn frames(frames: u64) -> Self {
+        Self {
+            frames: Some(frames),
+        }
+    }
+}
+impl Runner for HeadlessRunner {
+    fn run(self: Box<Self>, mut game: Game) -> Result<()> {
+        let mut frame = 0;
+        while frame < self.frames.unwrap_or(u64::MAX) {
+            game.update()?;
+            frame += 1;
+            if game.exit_requested() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Updates the game in a loop at most at `cap`, until an [`AppExit`](crate::ecs::game::AppExit)
+/// event is sent.
+#[derive(Debug, Clone)]
+pub struct FrameCappedRunner {
+    pub cap: FrameRateCap,
+}
+impl FrameCappedRunner {
+    pub fn new(frames_per_second: f64) -> Self {
+        Self {
+            cap: FrameRateCap::fixed(frames_per_second),
+        }
+    }
+}
+impl Runner for FrameCappedRunner {
+    fn run(self: Box<Self>, mut game: Game) -> Result<()> {
+        game.set_frame_rate_cap(self.cap);
+        loop {
+            game.update()?;
+            if game.exit_requested() {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::ecs::event::Events;
+use crate::ecs::game::{AppExit, Game};
+use crate::error::Result;
+use crate::input::winit::{convert_device_event, convert_window_event};
+use crate::input::InputEvent;
+use crate::runner::Runner;
+use crate::window::winit as window;
+use crate::window::WindowEvent;
+use std::collections::HashSet;
+use winit::event::Event;
+use winit::event_loop::EventLoop;
+use winit::window::Window;
+
+/// Drives the game from a winit event loop, updating it every time the runner's window is
+/// redrawn.
+///
+/// Lifecycle events of every window of the event loop are sent into the world as
+/// [`WindowEvent`]s, and closing the runner's window also sends an [`AppExit`] event. The game is
+/// [focused](Game::set_focused) while any of the windows is. If the game was set up with
+/// [`add_input`](crate::input::add_input), keyboard, mouse and touch events are also sent as
+/// [`InputEvent`]s.
+///
+/// ## Usage
+/// ```rust,no_run
+/// # use goosberry::ecs::game::Game;
+/// # use goosberry::ecs::world::World;
+/// # use goosberry::runner::winit::WinitRunner;
+/// # use winit::event_loop::EventLoop;
+/// # use winit::window::WindowBuilder;
+/// let event_loop = EventLoop::new();
+/// let window = WindowBuilder::new().build(&event_loop).unwrap();
+/// // Create cameras from `&window` here
+/// let mut game = Game::new(World::default());
+/// game.set_runner(WinitRunner::new(event_loop, window));
+/// game.run().unwrap();
+/// ```
+pub struct WinitRunner {
+    event_loop: EventLoop<()>,
+    window: Window,
+}
+impl WinitRunner {
+    pub fn new(event_loop: EventLoop<()>, window: Window) -> Self {
+        Self { event_loop, window }
+    }
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+}
+impl Runner for WinitRunner {
+    /// Never returns: the process exits along with the event loop.
+    fn run(self: Box<Self>, mut game: Game) -> Result<()> {
+        let WinitRunner { event_loop, window } = *self;
+        game.world.add_event::<WindowEvent>();
+        let mut focused = HashSet::new();
+        event_loop.run(move |event, _, control_flow| {
+            control_flow.set_poll();
+            match event {
+                Event::WindowEvent { event, window_id } => {
+                    if let Some(event) = window::convert_window_event(window_id, &event) {
+                        match event {
+                            WindowEvent::CloseRequested { .. } if window_id == window.id() => {
+                                game.world.send_event(AppExit)
+                            }
+                            WindowEvent::Focused { focused: true, .. } => {
+                                focused.insert(window_id);
+                                game.set_focused(true);
+                            }
+                            WindowEvent::Focused { focused: false, .. } => {
+                                focused.remove(&window_id);
+                                game.set_focused(!focused.is_empty());
+                            }
+                            _ => {}
+                        }
+                        game.world.send_event(event);
+                    }
+                    if let Some(event) = convert_window_event(&event) {
+                        if game.world.contains_resource::<Events<InputEvent>>() {
+                            game.world.send_event(event);
+                        }
+                    }
+                }
+                Event::Suspended => game.world.send_event(WindowEvent::Suspended),
+                Event::Resumed => game.world.send_event(WindowEvent::Resumed),
+                Event::DeviceEvent { event, .. } => {
+                    if let Some(event) = convert_device_event(&event) {
+                        if game.world.contains_resource::<Events<InputEvent>>() {
+                            game.world.send_event(event);
+                        }
+                    }
+                }
+                Event::MainEventsCleared => window.request_redraw(),
+                Event::RedrawRequested(id) if id == window.id() => {
+                    if let Err(e) = game.update() {
+                        log::error!("{e}");
+                        control_flow.set_exit();
+                    } else if game.exit_requested() {
+                        control_flow.set_exit();
+                    }
+                }
+                _ => {}
+            }
+        })
+    }
+}
@@ -0,0 +1,96 @@
+use nalgebra::Vector2;
+
+#[cfg(feature = "winit")]
+pub mod winit;
+
+/// Identifies one of the game's windows, whatever the windowing library.
+///
+/// With winit, it converts from `winit::window::WindowId`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WindowId(pub u64);
+
+/// Lifecycle events of the game's windows, sent into the world by the runner.
+///
+/// Read them with an [`EventReader`](crate::ecs::event::EventReader) over
+/// `Events<WindowEvent>`. Cameras added with
+/// [`add_render_2d`](crate::rendering::add_render_2d) follow resizes and scale factor changes
+/// of the window they render to on their own.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WindowEvent {
+    /// New size of the window's client area, in physical pixels.
+    Resized {
+        window: WindowId,
+        size: Vector2<u32>,
+    },
+    /// The window moved to a monitor with a different DPI, or the setting changed.
+    ScaleFactorChanged {
+        window: WindowId,
+        scale_factor: f64,
+        size: Vector2<u32>,
+    },
+    Focused {
+        window: WindowId,
+        focused: bool,
+    },
+    /// The user asked to close the window. For the runner's main window, the runner also sends
+    /// an [`AppExit`](crate::ecs::game::AppExit) event.
+    CloseRequested {
+        window: WindowId,
+    },
+    /// The application was suspended, and its surfaces may be gone (Android and iOS).
+    Suspended,
+    Resumed,
+}
+
+#[cfg(all(test, feature = "winit"))]
+mod tests {
+    use crate::window::winit::convert_window_event;
+    use crate::window::{WindowEvent, WindowId};
+    use nalgebra::Vector2;
+    use winit::dpi::PhysicalSize;
+    use winit::event::WindowEvent as Winit;
+
+    #[test]
+    fn test_convert_window_events() {
+        // Safe as it is only compared, never passed to winit
+        let id = unsafe { winit::window::WindowId::dummy() };
+        let window = WindowId::from(id);
+        let resized = Winit::Resized(PhysicalSize::new(640, 480));
+        assert_eq!(
+            convert_window_event(id, &resized),
+            Some(WindowEvent::Resized {
+                window,
+                size: Vector2::new(640, 480),
+            })
+        );
+        let mut size = PhysicalSize::new(1280, 960);
+        let scale_factor_changed = Winit::ScaleFactorChanged {
+            scale_factor: 2.0,
+            new_inner_size: &mut size,
+        };
+        assert_eq!(
+            convert_window_event(id, &scale_factor_changed),
+            Some(WindowEvent::ScaleFactorChanged {
+                window,
+                scale_factor: 2.0,
+                size: Vector2::new(1280, 960),
+            })
+        );
+        assert_eq!(
+            convert_window_event(id, &Winit::Focused(false)),
+            Some(WindowEvent::Focused {
+                window,
+                focused: false,
+            })
+        );
+        assert_eq!(
+            convert_window_event(id, &Winit::CloseRequested),
+            Some(WindowEvent::CloseRequested { window })
+        );
+        // Input is sent as InputEvents instead
+        assert_eq!(
+            convert_window_event(id, &Winit::ReceivedCharacter('a')),
+            None
+        );
+    }
+}
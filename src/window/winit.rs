@@ -0,0 +1,37 @@
+use crate::window::{WindowEvent, WindowId};
+use nalgebra::Vector2;
+
+impl From<winit::window::WindowId> for WindowId {
+    fn from(id: winit::window::WindowId) -> Self {
+        Self(id.into())
+    }
+}
+
+/// Translates an event of the winit window `window` into a [`WindowEvent`], if it is one.
+pub fn convert_window_event(
+    window: winit::window::WindowId,
+    event: &winit::event::WindowEvent<'_>,
+) -> Option<WindowEvent> {
+    use winit::event::WindowEvent as Winit;
+    let window = window.into();
+    Some(match event {
+        Winit::Resized(size) => WindowEvent::Resized {
+            window,
+            size: Vector2::new(size.width, size.height),
+        },
+        Winit::ScaleFactorChanged {
+            scale_factor,
+            new_inner_size,
+        } => WindowEvent::ScaleFactorChanged {
+            window,
+            scale_factor: *scale_factor,
+            size: Vector2::new(new_inner_size.width, new_inner_size.height),
+        },
+        Winit::Focused(focused) => WindowEvent::Focused {
+            window,
+            focused: *focused,
+        },
+        Winit::CloseRequested => WindowEvent::CloseRequested { window },
+        _ => return None,
+    })
+}